@@ -0,0 +1,256 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains [`AsyncCamera`], an async wrapper of [`Camera`].
+//!
+//! Operations on [`Camera`] block the calling thread until the device responds. [`AsyncCamera`]
+//! moves the camera to a dedicated worker thread and runs the operations there, so that they can
+//! be awaited from async runtimes like `tokio` without blocking their executor threads.
+//!
+//! # Examples
+//! ```no_run
+//! use cameleon::{u3v, AsyncCamera};
+//!
+//! # async fn run() {
+//! let mut cameras = u3v::enumerate_cameras().unwrap();
+//! let camera = AsyncCamera::new(cameras.pop().unwrap());
+//!
+//! camera.open().await.unwrap();
+//! camera.load_context().await.unwrap();
+//!
+//! // Reads `Gain` on the worker thread.
+//! let gain = camera
+//!     .with_params(|ctxt| {
+//!         let node = ctxt.node("Gain").unwrap().as_float(ctxt).unwrap();
+//!         Ok(node.value(ctxt)?)
+//!     })
+//!     .await
+//!     .unwrap();
+//! println!("{}", gain);
+//!
+//! camera.close().await.unwrap();
+//! # }
+//! ```
+
+use std::{sync::mpsc, thread};
+
+use async_channel::Receiver;
+
+use super::{
+    camera::{Camera, CameraInfo, DeviceControl, PayloadStream},
+    genapi::{FromXml, GenApiCtxt, ParamsCtxt},
+    payload::PayloadReceiver,
+    CameleonError, CameleonResult, ControlError, DeviceIoError,
+};
+
+type Job<Ctrl, Strm, Ctxt> = Box<dyn FnOnce(&mut Camera<Ctrl, Strm, Ctxt>) + Send>;
+
+/// Provides async access to [`Camera`] by running its blocking operations on a worker thread.
+///
+/// The worker thread lives as long as `AsyncCamera`, and operations are executed on it one by
+/// one in the order they are called.
+///
+/// Note that [`Camera::close`] is NOT automatically called when `AsyncCamera` is dropped, same
+/// as [`Camera`].
+#[derive(Debug)]
+pub struct AsyncCamera<Ctrl, Strm, Ctxt> {
+    jobs: mpsc::Sender<Job<Ctrl, Strm, Ctxt>>,
+    /// Receives the camera back when the worker thread finishes.
+    done: Receiver<Camera<Ctrl, Strm, Ctxt>>,
+    info: CameraInfo,
+}
+
+impl<Ctrl, Strm, Ctxt> AsyncCamera<Ctrl, Strm, Ctxt>
+where
+    Ctrl: Send + 'static,
+    Strm: Send + 'static,
+    Ctxt: Send + 'static,
+{
+    /// Moves `camera` to a newly spawned worker thread.
+    pub fn new(mut camera: Camera<Ctrl, Strm, Ctxt>) -> Self {
+        let (jobs_tx, jobs_rx) = mpsc::channel::<Job<Ctrl, Strm, Ctxt>>();
+        let (done_tx, done_rx) = async_channel::bounded(1);
+        let info = camera.info().clone();
+
+        thread::spawn(move || {
+            for job in jobs_rx {
+                job(&mut camera);
+            }
+            done_tx.try_send(camera).ok();
+        });
+
+        Self {
+            jobs: jobs_tx,
+            done: done_rx,
+            info,
+        }
+    }
+
+    /// Runs `f` with the camera on the worker thread, and returns its result.
+    pub async fn run<F, R>(&self, f: F) -> CameleonResult<R>
+    where
+        F: FnOnce(&mut Camera<Ctrl, Strm, Ctxt>) -> CameleonResult<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = async_channel::bounded(1);
+        let job: Job<Ctrl, Strm, Ctxt> = Box::new(move |camera| {
+            tx.try_send(f(camera)).ok();
+        });
+        self.jobs.send(job).map_err(|_| worker_terminated())?;
+        rx.recv().await.map_err(|_| worker_terminated())?
+    }
+
+    /// Async version of [`Camera::open`].
+    pub async fn open(&self) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+    {
+        self.run(Camera::open).await
+    }
+
+    /// Async version of [`Camera::close`].
+    pub async fn close(&self) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        self.run(Camera::close).await
+    }
+
+    /// Async version of [`Camera::load_context`].
+    pub async fn load_context(&self) -> CameleonResult<String>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt + FromXml,
+    {
+        self.run(Camera::load_context).await
+    }
+
+    /// Async version of [`Camera::start_streaming`].
+    ///
+    /// The returned [`PayloadReceiver`] implements `futures_core::Stream`.
+    pub async fn start_streaming(&self, cap: usize) -> CameleonResult<PayloadReceiver>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        self.run(move |camera| camera.start_streaming(cap)).await
+    }
+
+    /// Async version of [`Camera::stop_streaming`].
+    pub async fn stop_streaming(&self) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        self.run(Camera::stop_streaming).await
+    }
+
+    /// Runs `f` with the params context of the camera on the worker thread.
+    ///
+    /// This is the async counterpart of [`Camera::params_ctxt`]. All node accesses in `f` are
+    /// performed on the worker thread.
+    pub async fn with_params<F, R>(&self, f: F) -> CameleonResult<R>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+        F: FnOnce(&mut ParamsCtxt<&mut Ctrl, &mut Ctxt>) -> CameleonResult<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run(move |camera| f(&mut camera.params_ctxt()?)).await
+    }
+
+    /// Returns basic information of the camera.
+    pub fn info(&self) -> &CameraInfo {
+        &self.info
+    }
+
+    /// Stops the worker thread and returns the camera.
+    ///
+    /// Waits until all operations already called are finished.
+    pub async fn into_inner(self) -> CameleonResult<Camera<Ctrl, Strm, Ctxt>> {
+        let Self { jobs, done, .. } = self;
+        drop(jobs);
+        done.recv().await.map_err(|_| worker_terminated())
+    }
+}
+
+impl<Ctrl, Strm, Ctxt> From<Camera<Ctrl, Strm, Ctxt>> for AsyncCamera<Ctrl, Strm, Ctxt>
+where
+    Ctrl: Send + 'static,
+    Strm: Send + 'static,
+    Ctxt: Send + 'static,
+{
+    fn from(camera: Camera<Ctrl, Strm, Ctxt>) -> Self {
+        Self::new(camera)
+    }
+}
+
+fn worker_terminated() -> CameleonError {
+    ControlError::Io(DeviceIoError::msg(
+        "the camera worker thread has terminated",
+    ))
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils;
+
+    #[test]
+    fn test_run_on_worker() {
+        let camera = AsyncCamera::new(test_utils::camera(test_utils::STREAMING_NODES));
+
+        block_on(camera.open()).unwrap();
+        let opened = block_on(camera.run(|camera| Ok(camera.ctrl.opened))).unwrap();
+        assert!(opened);
+        assert!(matches!(
+            block_on(camera.with_params(|_| Ok(()))),
+            Err(CameleonError::GenApiContextMissing)
+        ));
+
+        block_on(camera.run(|camera| camera.load_context().map(drop))).unwrap();
+        let has_node =
+            block_on(camera.with_params(|ctxt| Ok(ctxt.node("AcquisitionStart").is_some())));
+        assert!(has_node.unwrap());
+
+        let camera = block_on(camera.into_inner()).unwrap();
+        assert!(camera.ctrl.opened);
+        assert!(camera.strm.opened);
+    }
+
+    /// Polls `fut` to completion on the current thread.
+    fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+        use std::{
+            pin::pin,
+            sync::Arc,
+            task::{Context, Poll, Wake},
+        };
+
+        struct Unparker(thread::Thread);
+        impl Wake for Unparker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Arc::new(Unparker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+}
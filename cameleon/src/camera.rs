@@ -62,7 +62,7 @@ use tracing::info;
 
 use super::{
     genapi::{DefaultGenApiCtxt, FromXml, GenApiCtxt, ParamsCtxt},
-    payload::{channel, PayloadReceiver, PayloadSender},
+    payload::{self, channel, Payload, PayloadReceiver, PayloadSender},
     CameleonError, CameleonResult, ControlResult, StreamError, StreamResult,
 };
 
@@ -146,6 +146,10 @@ impl<Ctrl, Strm, Ctxt> Camera<Ctrl, Strm, Ctxt> {
     ///
     /// See also [`close`](Self::close) which must be called when an opened camera is no more needed.
     ///
+    /// U3V cameras are locked while they are opened so that other processes can't open them at the
+    /// same time, `ControlError::Busy` is returned if the camera is already opened by another
+    /// process. See `u3v::OpenMode` to open a camera without the lock.
+    ///
     /// # Examples
     /// ```rust
     /// # use cameleon::u3v;
@@ -297,6 +301,61 @@ impl<Ctrl, Strm, Ctxt> Camera<Ctrl, Strm, Ctxt> {
         Ctxt: GenApiCtxt,
     {
         const DEFAULT_BUFFER_CAP: usize = 5;
+        let (sender, receiver) = channel(cap, DEFAULT_BUFFER_CAP);
+        self.start_streaming_impl(sender)?;
+        Ok(receiver)
+    }
+
+    /// Starts streaming and passes each `Payload` to `f`.
+    ///
+    /// Unlike [`start_streaming`](Self::start_streaming), `f` is called directly on the streaming
+    /// thread without going through a channel, and the payload buffer is reused for the next
+    /// payload once `f` returns. `f` should return quickly, since no payload is received while
+    /// it's running.
+    ///
+    /// Make sure to load `GenApi` context before calling this method.
+    /// See [`load_context`](Self::load_context) and [`set_context`](Self::set_context) how to configure `GenApi` context.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// camera
+    ///     .start_streaming_with(|payload| match payload {
+    ///         Ok(payload) => println!("payload received! block_id: {:?}", payload.id()),
+    ///         Err(err) => println!("streaming error: {}", err),
+    ///     })
+    ///     .unwrap();
+    ///
+    /// // Closes the camera.
+    /// camera.close().unwrap();
+    /// ```
+    #[tracing::instrument(skip(self, f),
+                          level = "info",
+                          fields(camera = ?self.info()))]
+    pub fn start_streaming_with<F>(&mut self, f: F) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+        F: FnMut(StreamResult<&Payload>) + Send + 'static,
+    {
+        self.start_streaming_impl(payload::callback(f))
+    }
+
+    pub(crate) fn start_streaming_impl(&mut self, sender: PayloadSender) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
         info!("try starting streaming");
 
         if self.strm.is_loop_running() {
@@ -307,20 +366,36 @@ impl<Ctrl, Strm, Ctxt> Camera<Ctrl, Strm, Ctxt> {
         self.ctrl.enable_streaming()?;
         let mut ctxt = self.params_ctxt()?;
         expect_node!(&ctxt, "TLParamsLocked", as_integer).set_value(&mut ctxt, 1)?;
-        expect_node!(&ctxt, "AcquisitionStart", as_command).execute(&mut ctxt)?;
 
-        // Start streaming loop.
-        let (sender, receiver) = channel(cap, DEFAULT_BUFFER_CAP);
+        // Start streaming loop before starting acquisition, the loop may reconfigure the stream
+        // channel.
         self.strm.start_streaming_loop(sender, &mut self.ctrl)?;
 
+        if let Err(e) = self.acquisition_start() {
+            self.strm.stop_streaming_loop().ok();
+            return Err(e);
+        }
+
         info!("start streaming successfully");
-        Ok(receiver)
+        Ok(())
+    }
+
+    fn acquisition_start(&mut self) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        let mut ctxt = self.params_ctxt()?;
+        expect_node!(&ctxt, "AcquisitionStart", as_command).execute(&mut ctxt)?;
+        Ok(())
     }
 
     /// Stops the streaming.
     ///
     /// The receiver returned from the previous [`Self::start_streaming`]
-    /// call will be invalidated.
+    /// call will be invalidated, and the callback passed to [`Self::start_streaming_with`] won't
+    /// be called anymore.
     ///
     /// This method is automatically called in [`close`](Self::close), so no need to call
     /// explicitly when you close the camera.
@@ -0,0 +1,308 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains writes of multiple features ordered by the dependencies between them.
+
+use std::collections::HashSet;
+
+use cameleon_genapi::{dependency::DependencyKind, GenApiError, GenApiResult};
+
+use super::{DeviceControl, FeaturePath, GenApiCtxt, Node, ParamValue, ParamsCtxt};
+
+/// A write which failed in [`ParamsCtxt::apply_params`].
+#[derive(Debug, thiserror::Error)]
+#[error("failed to write `{path}`: {error}")]
+pub struct ApplyError {
+    /// The path of the feature, e.g. `Gain[GainSelector=DigitalAll]`.
+    pub path: String,
+    /// The error of the last attempt to write the feature.
+    pub error: GenApiError,
+}
+
+struct Write {
+    path: FeaturePath,
+    node: Node,
+    value: ParamValue,
+}
+
+impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    /// Writes multiple features, ordering the writes by the dependencies between the features.
+    ///
+    /// A feature is written after its selectors and after the features which its value, range,
+    /// availability or lock state refers to, e.g. `OffsetX` is written after `Width` if the
+    /// maximum of `OffsetX` is computed from `Width`. Features which don't depend on each other
+    /// are written in the given order.
+    ///
+    /// A write which fails, e.g. because the value is out of the current range, is deferred and
+    /// retried after the other writes. Retries continue while some of the deferred writes
+    /// succeed, so features which limit each other are applied regardless of the given order.
+    ///
+    /// Returns the errors of the writes which couldn't be applied. The writes which succeeded
+    /// are not reverted; use [`ParamsCtxt::transaction`] for all-or-nothing writes.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// let errors = params_ctxt.apply_params(vec![
+    ///     ("OffsetX", 16.into()),
+    ///     ("Width", 640.into()),
+    ///     ("Gain[GainSelector=DigitalAll]", 1.5.into()),
+    /// ]);
+    /// for err in errors {
+    ///     println!("{}", err);
+    /// }
+    /// ```
+    pub fn apply_params<I, P>(&mut self, params: I) -> Vec<ApplyError>
+    where
+        I: IntoIterator<Item = (P, ParamValue)>,
+        P: AsRef<str>,
+    {
+        let mut errors = vec![];
+        let mut writes = vec![];
+        for (path, value) in params {
+            let path = path.as_ref();
+            let parsed = path
+                .parse::<FeaturePath>()
+                .and_then(|parsed| Ok((self.expect_node(parsed.feature())?, parsed)));
+            match parsed {
+                Ok((node, path)) => writes.push(Some(Write { path, node, value })),
+                Err(error) => errors.push(ApplyError {
+                    path: path.to_string(),
+                    error,
+                }),
+            }
+        }
+
+        let nodes: Vec<Node> = writes.iter().flatten().map(|write| write.node).collect();
+        let mut pending: Vec<Write> = self
+            .write_order(&nodes)
+            .into_iter()
+            .map(|i| writes[i].take().unwrap())
+            .collect();
+        loop {
+            let attempted = pending.len();
+            let mut deferred = vec![];
+            for write in pending {
+                let result = self.with_selected(&write.path, |ctxt, node| {
+                    ctxt.checked_set_node_param(node, &write.value)
+                });
+                if let Err(error) = result {
+                    deferred.push((write, error));
+                }
+            }
+
+            if deferred.is_empty() {
+                break;
+            } else if deferred.len() == attempted {
+                errors.extend(deferred.into_iter().map(|(write, error)| ApplyError {
+                    path: write.path.to_string(),
+                    error,
+                }));
+                break;
+            }
+            pending = deferred.into_iter().map(|(write, _)| write).collect();
+        }
+
+        errors
+    }
+
+    /// Returns the indices of `nodes` in the order they should be written.
+    pub(super) fn write_order(&self, nodes: &[Node]) -> Vec<usize> {
+        let prerequisites: Vec<_> = nodes.iter().map(|&node| self.prerequisites(node)).collect();
+
+        let mut remaining: Vec<usize> = (0..nodes.len()).collect();
+        let mut order = Vec::with_capacity(nodes.len());
+        while !remaining.is_empty() {
+            let next = remaining
+                .iter()
+                .position(|&i| {
+                    !remaining
+                        .iter()
+                        .any(|&j| nodes[j] != nodes[i] && prerequisites[i].contains(&nodes[j]))
+                })
+                // Nodes which depend on each other are ordered as they are given.
+                .unwrap_or(0);
+            order.push(remaining.remove(next));
+        }
+        order
+    }
+
+    /// Returns nodes which should be written before `node`, i.e. its selectors and the nodes
+    /// which its value, range, availability or lock state depend on directly or indirectly.
+    fn prerequisites(&self, node: Node) -> HashSet<Node> {
+        let mut stack = self.all_selectors(node);
+        let mut visited: HashSet<Node> = stack.iter().copied().collect();
+        stack.push(node);
+        while let Some(node) = stack.pop() {
+            for (kind, dep) in node.dependencies(self) {
+                if is_prerequisite(kind) && visited.insert(dep) {
+                    stack.push(dep);
+                }
+            }
+        }
+        visited
+    }
+
+    /// Writes `value` to `node` after checking that the node is writable and the value is in
+    /// its range.
+    fn checked_set_node_param(&mut self, node: Node, value: &ParamValue) -> GenApiResult<()> {
+        if !node.is_writable(self)? {
+            return Err(GenApiError::NotWritable);
+        }
+
+        let out_of_range = |ctxt: &Self, min: &dyn ToString, max: &dyn ToString| {
+            GenApiError::InvalidData(
+                format!(
+                    "{} is out of the range of `{}`: [{}, {}]",
+                    value,
+                    node.name(ctxt),
+                    min.to_string(),
+                    max.to_string()
+                )
+                .into(),
+            )
+        };
+        match value {
+            ParamValue::Integer(v) => {
+                if let Some(integer) = node.as_integer(self) {
+                    let (min, max) = (integer.min(self)?, integer.max(self)?);
+                    if !(min..=max).contains(v) {
+                        return Err(out_of_range(self, &min, &max));
+                    }
+                }
+            }
+            ParamValue::Float(v) => {
+                if let Some(float) = node.as_float(self) {
+                    let (min, max) = (float.min(self)?, float.max(self)?);
+                    if !(min..=max).contains(v) {
+                        return Err(out_of_range(self, &min, &max));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.set_node_param(node, value)
+    }
+}
+
+/// Returns `true` if a node referred through `kind` affects whether and which value can be
+/// written to the referring node.
+fn is_prerequisite(kind: DependencyKind) -> bool {
+    use DependencyKind::*;
+
+    matches!(
+        kind,
+        Value
+            | Index
+            | Min
+            | Max
+            | Inc
+            | Variable
+            | Address
+            | Length
+            | ChunkId
+            | CommandValue
+            | IsImplemented
+            | IsAvailable
+            | IsLocked
+            | Entry
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils;
+
+    const NODES: &str = r#"
+        <Integer Name="SensorWidth">
+            <Value>100</Value>
+        </Integer>
+        <Integer Name="Width">
+            <Value>100</Value>
+            <Min>1</Min>
+            <pMax>WidthMax</pMax>
+        </Integer>
+        <IntSwissKnife Name="WidthMax">
+            <pVariable Name="SENSOR">SensorWidth</pVariable>
+            <pVariable Name="OFFSET">OffsetX</pVariable>
+            <Formula>SENSOR - OFFSET</Formula>
+        </IntSwissKnife>
+        <Integer Name="OffsetX">
+            <Value>0</Value>
+            <Min>0</Min>
+            <pMax>OffsetXMax</pMax>
+        </Integer>
+        <IntSwissKnife Name="OffsetXMax">
+            <pVariable Name="SENSOR">SensorWidth</pVariable>
+            <pVariable Name="WIDTH">Width</pVariable>
+            <Formula>SENSOR - WIDTH</Formula>
+        </IntSwissKnife>
+        <Integer Name="GainSelector">
+            <Value>0</Value>
+            <Min>0</Min>
+            <Max>1</Max>
+            <pSelected>Gain</pSelected>
+        </Integer>
+        <Integer Name="Gain">
+            <pIndex>GainSelector</pIndex>
+            <pValueIndexed Index="0">Gain0</pValueIndexed>
+            <pValueIndexed Index="1">Gain1</pValueIndexed>
+            <pValueDefault>Gain0</pValueDefault>
+        </Integer>
+        <Integer Name="Gain0">
+            <Value>0</Value>
+        </Integer>
+        <Integer Name="Gain1">
+            <Value>0</Value>
+        </Integer>
+    "#;
+
+    #[test]
+    fn test_apply_params_ordered() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        // `GainSelector` is written first, so `5` is written to `Gain1`.
+        let errors = ctxt.apply_params(vec![("Gain", 5.into()), ("GainSelector", 1.into())]);
+
+        assert!(errors.is_empty());
+        assert_eq!(ctxt.param("Gain0").unwrap(), 0.into());
+        assert_eq!(ctxt.param("Gain1").unwrap(), 5.into());
+    }
+
+    #[test]
+    fn test_apply_params_deferred() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        // `OffsetX` is out of range until `Width` is written.
+        let errors = ctxt.apply_params(vec![("OffsetX", 60.into()), ("Width", 40.into())]);
+
+        assert!(errors.is_empty());
+        assert_eq!(ctxt.param("OffsetX").unwrap(), 60.into());
+        assert_eq!(ctxt.param("Width").unwrap(), 40.into());
+    }
+
+    #[test]
+    fn test_apply_params_errors() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        let errors = ctxt.apply_params(vec![
+            ("Width", 200.into()),
+            ("Unknown", 0.into()),
+            ("Gain[GainSelector=1]", 3.into()),
+        ]);
+
+        let paths: Vec<_> = errors.iter().map(|err| err.path.as_str()).collect();
+        assert_eq!(paths, ["Unknown", "Width"]);
+        assert_eq!(ctxt.param("Width").unwrap(), 100.into());
+        assert_eq!(ctxt.param("Gain1").unwrap(), 3.into());
+    }
+}
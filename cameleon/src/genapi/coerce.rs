@@ -0,0 +1,188 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains writes which adjust values to the nearest value the node accepts.
+
+use std::{cmp::Reverse, convert::TryFrom};
+
+use cameleon_genapi::{GenApiError, GenApiResult};
+
+use super::{DeviceControl, FloatNode, GenApiCtxt, IntegerNode, ParamsCtxt};
+
+impl IntegerNode {
+    /// Writes the value nearest to `value` which the node currently accepts, and returns the
+    /// written value.
+    ///
+    /// The value is snapped to the nearest value of [`Self::valid_value_set`] in the range if the
+    /// set isn't empty. Otherwise it is clamped to [`Self::min`] and [`Self::max`], then rounded
+    /// to the nearest `min + i * inc`. Ties are rounded up.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// let width = params_ctxt.node("Width").unwrap().as_integer(&params_ctxt).unwrap();
+    /// let written = width.set_value_coerced(&mut params_ctxt, 641).unwrap();
+    /// println!("`Width` is set to {}", written);
+    /// ```
+    pub fn set_value_coerced<Ctrl, Ctxt>(
+        self,
+        ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+        value: i64,
+    ) -> GenApiResult<i64>
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        let (min, max) = (self.min(ctxt)?, self.max(ctxt)?);
+        let valid_values = self.valid_value_set(ctxt);
+        let coerced = if valid_values.is_empty() {
+            coerce_integer(value, min, max, self.inc(ctxt)?)
+        } else {
+            valid_values
+                .iter()
+                .copied()
+                .filter(|v| (min..=max).contains(v))
+                .min_by_key(|v| ((i128::from(*v) - i128::from(value)).abs(), Reverse(*v)))
+                .ok_or_else(|| {
+                    GenApiError::InvalidData(
+                        format!("no valid value in the range [{}, {}]", min, max).into(),
+                    )
+                })?
+        };
+
+        self.set_value(ctxt, coerced)?;
+        Ok(coerced)
+    }
+}
+
+impl FloatNode {
+    /// Writes the value nearest to `value` which the node currently accepts, and returns the
+    /// written value.
+    ///
+    /// The value is clamped to [`Self::min`] and [`Self::max`], then rounded to the nearest
+    /// `min + i * inc` if the node has an increment. Ties are rounded up. `NaN` is rejected.
+    pub fn set_value_coerced<Ctrl, Ctxt>(
+        self,
+        ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+        value: f64,
+    ) -> GenApiResult<f64>
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        if value.is_nan() {
+            return Err(GenApiError::InvalidData("NaN can't be written".into()));
+        }
+
+        let (min, max) = (self.min(ctxt)?, self.max(ctxt)?);
+        let coerced = coerce_float(value, min, max, self.inc(ctxt)?);
+        self.set_value(ctxt, coerced)?;
+        Ok(coerced)
+    }
+}
+
+fn coerce_integer(value: i64, min: i64, max: i64, inc: Option<i64>) -> i64 {
+    let clamped = value.clamp(min, max.max(min));
+    match inc {
+        Some(inc) if inc > 0 => {
+            // Computed in `i128` because `clamped - min` can exceed `i64::MAX`.
+            let (min, inc) = (i128::from(min), i128::from(inc));
+            let offset = i128::from(clamped) - min;
+            let steps = (offset + inc / 2) / inc;
+            let mut snapped = min + steps * inc;
+            if snapped > i128::from(max) {
+                snapped -= inc;
+            }
+            i64::try_from(snapped.max(min)).unwrap()
+        }
+        _ => clamped,
+    }
+}
+
+/// Tolerance of rounding errors in coercion of floats, relative to the increment.
+const FLOAT_TOLERANCE: f64 = 1e-9;
+
+fn coerce_float(value: f64, min: f64, max: f64, inc: Option<f64>) -> f64 {
+    let clamped = value.max(min).min(max);
+    match inc {
+        Some(inc) if inc > 0.0 => {
+            let mut snapped = min + ((clamped - min) / inc).round() * inc;
+            // `snapped` can exceed `max` by a rounding error even if `max` is on the grid, so step
+            // down only if it exceeds by more than the error.
+            if snapped - max > inc * FLOAT_TOLERANCE {
+                snapped -= inc;
+            }
+            snapped.min(max).max(min)
+        }
+        _ => clamped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    const NODES: &str = r#"
+        <Integer Name="Width">
+            <Value>64</Value>
+            <Min>16</Min>
+            <Max>101</Max>
+            <Inc>8</Inc>
+        </Integer>
+        <Float Name="ExposureTime">
+            <Value>10.0</Value>
+            <Min>1.0</Min>
+            <Max>100.0</Max>
+            <Inc>0.5</Inc>
+        </Float>
+    "#;
+
+    #[test]
+    fn test_coerce_integer() {
+        assert_eq!(coerce_integer(50, 16, 101, Some(8)), 48);
+        assert_eq!(coerce_integer(53, 16, 101, Some(8)), 56);
+        // Ties are rounded up.
+        assert_eq!(coerce_integer(52, 16, 101, Some(8)), 56);
+        assert_eq!(coerce_integer(0, 16, 101, Some(8)), 16);
+        // 104 exceeds `max`.
+        assert_eq!(coerce_integer(200, 16, 101, Some(8)), 96);
+        assert_eq!(coerce_integer(200, 16, 101, None), 101);
+        assert_eq!(
+            coerce_integer(i64::MAX, i64::MIN, i64::MAX, Some(2)),
+            i64::MAX - 1
+        );
+    }
+
+    #[test]
+    fn test_coerce_float() {
+        assert!((coerce_float(10.2, 1.0, 100.0, Some(0.5)) - 10.0).abs() < f64::EPSILON);
+        assert!((coerce_float(10.3, 1.0, 100.0, Some(0.5)) - 10.5).abs() < f64::EPSILON);
+        assert!((coerce_float(0.0, 1.0, 100.0, Some(0.5)) - 1.0).abs() < f64::EPSILON);
+        assert!((coerce_float(100.2, 1.0, 99.9, Some(0.5)) - 99.5).abs() < f64::EPSILON);
+        assert!((coerce_float(7.25, 1.0, 100.0, None) - 7.25).abs() < f64::EPSILON);
+        // `0.1 + 2 * 0.1` exceeds `0.3` by a rounding error.
+        assert!((coerce_float(0.3, 0.1, 0.3, Some(0.1)) - 0.3).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_set_value_coerced() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        let width = ctxt.node("Width").unwrap().as_integer(&ctxt).unwrap();
+        assert_eq!(width.set_value_coerced(&mut ctxt, 1000).unwrap(), 96);
+        assert_eq!(width.value(&mut ctxt).unwrap(), 96);
+
+        let exposure = ctxt.node("ExposureTime").unwrap().as_float(&ctxt).unwrap();
+        let written = exposure.set_value_coerced(&mut ctxt, 12.4).unwrap();
+        assert!((written - 12.5).abs() < f64::EPSILON);
+        assert!((exposure.value(&mut ctxt).unwrap() - 12.5).abs() < f64::EPSILON);
+        assert!(exposure.set_value_coerced(&mut ctxt, f64::NAN).is_err());
+    }
+}
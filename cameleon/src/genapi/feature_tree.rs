@@ -0,0 +1,315 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains the tree of features built from categories, which GUIs and CLIs show.
+
+use cameleon_genapi::{GenApiError, GenApiResult};
+
+use super::{AccessMode, DeviceControl, GenApiCtxt, Node, ParamsCtxt, Visibility};
+
+/// A feature in the tree returned by [`ParamsCtxt::feature_tree`].
+///
+/// The tree holds the properties which don't change while the device is used. The state which
+/// depends on other nodes, i.e. availability and access mode, is evaluated on demand with
+/// [`Self::is_available`] and [`Self::access_mode`], so that a GUI can evaluate only the visible
+/// features and an error of one feature doesn't hide the others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureTreeNode {
+    /// The node of the feature.
+    pub node: Node,
+    /// The name of the node.
+    pub name: String,
+    /// The display name of the node, or the name if the node doesn't have one.
+    pub display_name: String,
+    /// The tooltip of the node.
+    pub tooltip: Option<String>,
+    /// The unit of the value, e.g. `us`. Only integer and float nodes have a unit.
+    pub unit: Option<String>,
+    /// The visibility of the node.
+    pub visibility: Visibility,
+    /// Features in the category. Empty if the node is not a category.
+    pub children: Vec<FeatureTreeNode>,
+}
+
+impl FeatureTreeNode {
+    /// Returns `false` if the node is currently unavailable, e.g. `ExposureTime` while
+    /// `ExposureAuto` is `Continuous`.
+    pub fn is_available<Ctrl, Ctxt>(&self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        self.node.is_available(ctxt)
+    }
+
+    /// Returns the current access mode of the node. `None` if the node is neither readable nor
+    /// writable, e.g. categories and unavailable nodes.
+    pub fn access_mode<Ctrl, Ctxt>(
+        &self,
+        ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    ) -> GenApiResult<Option<AccessMode>>
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        if !self.node.is_available(ctxt)? {
+            return Ok(None);
+        }
+        Ok(
+            match (self.node.is_readable(ctxt)?, self.node.is_writable(ctxt)?) {
+                (true, true) => Some(AccessMode::RW),
+                (true, false) => Some(AccessMode::RO),
+                (false, true) => Some(AccessMode::WO),
+                (false, false) => None,
+            },
+        )
+    }
+}
+
+impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    /// Returns the features in the categories under `Root` whose visibility is at most
+    /// `visibility`, e.g. `Visibility::Expert` yields `Beginner` and `Expert` features.
+    ///
+    /// Nodes whose `ExposeStatic` is `No` and nodes which are not implemented are skipped, and so
+    /// are categories left without features. Implementation is evaluated only for the nodes which
+    /// pass the visibility filter, and the features of an unimplemented category are not visited.
+    /// A node whose implementation fails to be evaluated is kept, so that the error is reported
+    /// when its availability or access mode is evaluated.
+    ///
+    /// Returns an error only if there is no `Root` category.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use cameleon::{genapi::{FeatureTreeNode, ParamsCtxt, Visibility}, u3v};
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// fn print<Ctrl, Ctxt>(
+    ///     ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    ///     nodes: &[FeatureTreeNode],
+    ///     depth: usize,
+    /// ) where
+    ///     Ctrl: cameleon::DeviceControl,
+    ///     Ctxt: cameleon::genapi::GenApiCtxt,
+    /// {
+    ///     for node in nodes {
+    ///         let access_mode = node.access_mode(ctxt);
+    ///         println!("{}{} {:?}", "  ".repeat(depth), node.display_name, access_mode);
+    ///         print(ctxt, &node.children, depth + 1);
+    ///     }
+    /// }
+    ///
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// let tree = params_ctxt.feature_tree(Visibility::Expert).unwrap();
+    /// print(&mut params_ctxt, &tree, 0);
+    /// ```
+    pub fn feature_tree(&mut self, visibility: Visibility) -> GenApiResult<Vec<FeatureTreeNode>> {
+        let root = self
+            .node("Root")
+            .filter(|node| node.as_category(self).is_some())
+            .ok_or_else(|| GenApiError::InvalidNode("no category named `Root`".into()))?;
+        let mut path = vec![root];
+        Ok(self.feature_tree_children(root, visibility, &mut path))
+    }
+
+    /// Builds the trees of the features in `category`. `path` holds the categories from `Root`
+    /// to `category` to stop at cyclic categories.
+    fn feature_tree_children(
+        &mut self,
+        category: Node,
+        visibility: Visibility,
+        path: &mut Vec<Node>,
+    ) -> Vec<FeatureTreeNode> {
+        let features = category.as_category(self).unwrap().nodes(self);
+        let mut children = vec![];
+        for feature in features {
+            if path.contains(&feature)
+                || feature.visibility(self) > visibility
+                || feature.expose_static(self) == Some(false)
+                || matches!(feature.is_implemented(self), Ok(false))
+            {
+                continue;
+            }
+
+            let is_category = feature.as_category(self).is_some();
+            let grandchildren = if is_category {
+                path.push(feature);
+                let grandchildren = self.feature_tree_children(feature, visibility, path);
+                path.pop();
+                if grandchildren.is_empty() {
+                    continue;
+                }
+                grandchildren
+            } else {
+                vec![]
+            };
+
+            let unit = if let Some(node) = feature.as_integer(self) {
+                node.unit(self)
+            } else if let Some(node) = feature.as_float(self) {
+                node.unit(self)
+            } else {
+                None
+            };
+
+            children.push(FeatureTreeNode {
+                node: feature,
+                name: feature.name(self).to_string(),
+                display_name: feature.display_name(self).to_string(),
+                tooltip: feature.tooltip(self).map(String::from),
+                unit,
+                visibility: feature.visibility(self),
+                children: grandchildren,
+            });
+        }
+        children
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    const NODES: &str = r#"
+        <Category Name="Root">
+            <pFeature>ImageFormatControl</pFeature>
+            <pFeature>AcquisitionControl</pFeature>
+            <pFeature>DebugControl</pFeature>
+        </Category>
+        <Category Name="ImageFormatControl">
+            <DisplayName>Image Format Control</DisplayName>
+            <pFeature>Width</pFeature>
+            <pFeature>BinningHorizontal</pFeature>
+            <pFeature>SensorName</pFeature>
+            <pFeature>Root</pFeature>
+        </Category>
+        <Category Name="AcquisitionControl">
+            <pFeature>ExposureAuto</pFeature>
+            <pFeature>ExposureTime</pFeature>
+            <pFeature>Unsupported</pFeature>
+            <pFeature>TriggerDelay</pFeature>
+        </Category>
+        <Category Name="DebugControl">
+            <Visibility>Guru</Visibility>
+            <pFeature>DebugLevel</pFeature>
+        </Category>
+        <Integer Name="Width">
+            <ToolTip>Width of the image.</ToolTip>
+            <Value>640</Value>
+            <Unit>px</Unit>
+        </Integer>
+        <Integer Name="BinningHorizontal">
+            <Visibility>Expert</Visibility>
+            <Value>1</Value>
+        </Integer>
+        <String Name="SensorName" ExposeStatic="No">
+            <Value>sensor</Value>
+        </String>
+        <Boolean Name="ExposureAuto">
+            <Value>true</Value>
+        </Boolean>
+        <Float Name="ExposureTime">
+            <pIsAvailable>ExposureManual</pIsAvailable>
+            <Value>100.0</Value>
+        </Float>
+        <IntSwissKnife Name="ExposureManual">
+            <pVariable Name="AUTO">ExposureAuto</pVariable>
+            <Formula>AUTO = 0</Formula>
+        </IntSwissKnife>
+        <Integer Name="Unsupported">
+            <pIsImplemented>NotImplemented</pIsImplemented>
+            <Value>0</Value>
+        </Integer>
+        <Integer Name="NotImplemented">
+            <Value>0</Value>
+        </Integer>
+        <Integer Name="DebugLevel">
+            <Visibility>Guru</Visibility>
+            <Value>0</Value>
+        </Integer>
+        <Float Name="TriggerDelay">
+            <pIsAvailable>TriggerDelayAvailable</pIsAvailable>
+            <Value>0.0</Value>
+        </Float>
+        <IntReg Name="TriggerDelayAvailable">
+            <Address>0x10000</Address>
+            <Length>4</Length>
+            <AccessMode>RO</AccessMode>
+            <pPort>Device</pPort>
+            <Sign>Unsigned</Sign>
+            <Endianess>LittleEndian</Endianess>
+        </IntReg>
+        <Port Name="Device">
+        </Port>
+    "#;
+
+    fn names(nodes: &[FeatureTreeNode]) -> Vec<&str> {
+        nodes.iter().map(|node| node.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_feature_tree() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        let tree = ctxt.feature_tree(Visibility::Beginner).unwrap();
+        assert_eq!(names(&tree), ["ImageFormatControl", "AcquisitionControl"]);
+
+        let image_format = &tree[0];
+        assert_eq!(image_format.display_name, "Image Format Control");
+        assert_eq!(image_format.access_mode(&mut ctxt).unwrap(), None);
+        assert_eq!(names(&image_format.children), ["Width"]);
+        let width = &image_format.children[0];
+        assert_eq!(width.tooltip.as_deref(), Some("Width of the image."));
+        assert_eq!(width.unit.as_deref(), Some("px"));
+        assert_eq!(width.access_mode(&mut ctxt).unwrap(), Some(AccessMode::RW));
+
+        let acquisition = &tree[1];
+        assert_eq!(
+            names(&acquisition.children),
+            ["ExposureAuto", "ExposureTime", "TriggerDelay"]
+        );
+        let exposure_time = &acquisition.children[1];
+        assert!(!exposure_time.is_available(&mut ctxt).unwrap());
+        assert_eq!(exposure_time.access_mode(&mut ctxt).unwrap(), None);
+
+        // Changes are reflected without rebuilding the tree.
+        ctxt.set_param("ExposureAuto", &false.into()).unwrap();
+        assert!(exposure_time.is_available(&mut ctxt).unwrap());
+    }
+
+    #[test]
+    fn test_feature_tree_node_error() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        let tree = ctxt.feature_tree(Visibility::Beginner).unwrap();
+
+        // The register which `TriggerDelay` refers to is out of the device memory, but only the
+        // feature itself fails.
+        let trigger_delay = &tree[1].children[2];
+        assert_eq!(trigger_delay.name, "TriggerDelay");
+        assert!(trigger_delay.is_available(&mut ctxt).is_err());
+        assert!(trigger_delay.access_mode(&mut ctxt).is_err());
+        assert!(tree[1].children[0].access_mode(&mut ctxt).is_ok());
+    }
+
+    #[test]
+    fn test_feature_tree_visibility() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        let tree = ctxt.feature_tree(Visibility::Expert).unwrap();
+        assert_eq!(names(&tree), ["ImageFormatControl", "AcquisitionControl"]);
+        assert_eq!(names(&tree[0].children), ["Width", "BinningHorizontal"]);
+
+        let tree = ctxt.feature_tree(Visibility::Guru).unwrap();
+        assert_eq!(
+            names(&tree),
+            ["ImageFormatControl", "AcquisitionControl", "DebugControl"]
+        );
+        assert_eq!(names(&tree[2].children), ["DebugLevel"]);
+    }
+}
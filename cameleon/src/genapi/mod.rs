@@ -38,24 +38,45 @@
 //! # camera.close().unwrap();
 //! ```
 
+mod apply;
+mod coerce;
+mod feature_tree;
 mod node_kind;
-
+mod notification;
+mod persistence;
+mod polling;
+mod selector;
+mod transaction;
+mod value_string;
+
+pub use apply::ApplyError;
+pub use feature_tree::FeatureTreeNode;
 pub use node_kind::{
     BooleanNode, CategoryNode, CommandNode, EnumEntryNode, EnumerationNode, FloatNode, IntegerNode,
     Node, PortNode, RegisterNode, StringNode,
 };
+pub use notification::{
+    ChangeCause, ChangeEvent, ChangeNotifier, NotifyingGenApiCtxt, SubscriptionId,
+};
+pub use persistence::{FeatureError, PersistenceError};
+pub use polling::Poller;
+pub use selector::FeaturePath;
+pub use transaction::Transaction;
 
 use std::{
+    collections::HashSet,
     convert::TryInto,
+    fmt,
     sync::{Arc, Mutex},
 };
 
 use auto_impl::auto_impl;
-use cameleon_genapi::{builder::GenApiBuilder, store};
+use cameleon_genapi::{builder::GenApiBuilder, store, GenApiResult};
 
 use super::{ControlError, ControlResult, DeviceControl};
 
 pub use cameleon_genapi::{
+    dependency::{Dependency, DependencyGraph, DependencyKind},
     elem_type::{AccessMode, NameSpace, Visibility},
     store::{
         CacheSink, CacheStore, DefaultCacheStore, DefaultNodeStore, DefaultValueStore, NodeId,
@@ -121,6 +142,31 @@ where
     pub fn node_store(&self) -> &Ctxt::NS {
         self.ctxt.node_store()
     }
+
+    /// Collects references between the nodes in the context, e.g. which nodes a node reads and
+    /// which nodes invalidate it.
+    ///
+    /// The graph doesn't change while the context is alive, so it can be built once and reused.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use cameleon::{genapi::Node, u3v};
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// let params_ctxt = camera.params_ctxt().unwrap();
+    /// let graph = params_ctxt.dependency_graph();
+    /// let width = params_ctxt.node("Width").unwrap();
+    /// // Nodes which may change when `Width` is written.
+    /// for nid in graph.affected_nodes(width.into()) {
+    ///     println!("{}", Node::from(nid).name(&params_ctxt));
+    /// }
+    /// ```
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        DependencyGraph::new(self.node_store())
+    }
 }
 
 impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt>
@@ -141,9 +187,153 @@ where
     where
         F: FnOnce(&mut Ctrl, &Ctxt::NS, &mut ValueCtxt<Ctxt::VS, Ctxt::CS>) -> R,
     {
-        self.enter(|ctrl, ctxt| {
-            ctxt.enter(|node_store, value_ctxt| f(ctrl, node_store, value_ctxt))
-        })
+        // Writes are recorded only while a callback is registered, since recording costs a set
+        // insertion per written node.
+        let notifies = self
+            .ctxt
+            .notifier()
+            .is_some_and(ChangeNotifier::has_subscriptions);
+        let (result, written) = self.enter(|ctrl, ctxt| {
+            ctxt.enter(|node_store, value_ctxt| {
+                if notifies {
+                    cameleon_genapi::record_written_nodes(|| f(ctrl, node_store, value_ctxt))
+                } else {
+                    (f(ctrl, node_store, value_ctxt), HashSet::new())
+                }
+            })
+        });
+        if !written.is_empty() {
+            self.notify_changes(written, ChangeCause::Written);
+        }
+        result
+    }
+}
+
+impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    /// Reads the value of the node named `name`.
+    ///
+    /// The variant of the returned [`ParamValue`] is determined by the interface of the node.
+    pub fn param(&mut self, name: &str) -> GenApiResult<ParamValue> {
+        let node = self.expect_node(name)?;
+        self.node_param(node)
+    }
+
+    /// Writes `value` to the node named `name` through the interface corresponding to the
+    /// variant of `value`.
+    pub fn set_param(&mut self, name: &str, value: &ParamValue) -> GenApiResult<()> {
+        let node = self.expect_node(name)?;
+        self.set_node_param(node, value)
+    }
+
+    fn node_param(&mut self, node: Node) -> GenApiResult<ParamValue> {
+        if let Some(node) = node.as_enumeration(self) {
+            let entry = node.current_entry(self)?;
+            Ok(ParamValue::Enumeration(entry.symbolic(self).to_string()))
+        } else if let Some(node) = node.as_boolean(self) {
+            Ok(ParamValue::Boolean(node.value(self)?))
+        } else if let Some(node) = node.as_integer(self) {
+            Ok(ParamValue::Integer(node.value(self)?))
+        } else if let Some(node) = node.as_float(self) {
+            Ok(ParamValue::Float(node.value(self)?))
+        } else if let Some(node) = node.as_string(self) {
+            Ok(ParamValue::String(node.value(self)?))
+        } else {
+            Err(GenApiError::InvalidNode(
+                format!("`{}` doesn't have a value", node.name(self)).into(),
+            ))
+        }
+    }
+
+    fn set_node_param(&mut self, node: Node, value: &ParamValue) -> GenApiResult<()> {
+        let mismatch = |ctxt: &Self| {
+            GenApiError::InvalidNode(
+                format!(
+                    "`{}` doesn't have the interface to write {:?}",
+                    node.name(ctxt),
+                    value
+                )
+                .into(),
+            )
+        };
+        match value {
+            ParamValue::Integer(v) => node
+                .as_integer(self)
+                .ok_or_else(|| mismatch(self))?
+                .set_value(self, *v),
+            ParamValue::Float(v) => node
+                .as_float(self)
+                .ok_or_else(|| mismatch(self))?
+                .set_value(self, *v),
+            ParamValue::String(v) => node
+                .as_string(self)
+                .ok_or_else(|| mismatch(self))?
+                .set_value(self, v.clone()),
+            ParamValue::Boolean(v) => node
+                .as_boolean(self)
+                .ok_or_else(|| mismatch(self))?
+                .set_value(self, *v),
+            ParamValue::Enumeration(v) => node
+                .as_enumeration(self)
+                .ok_or_else(|| mismatch(self))?
+                .set_entry_by_symbolic(self, v),
+        }
+    }
+
+    fn expect_node(&self, name: &str) -> GenApiResult<Node> {
+        self.node(name)
+            .ok_or_else(|| GenApiError::InvalidNode(format!("no node named `{}`", name).into()))
+    }
+}
+
+/// A value of a node, used to read and write nodes by name.
+///
+/// See [`ParamsCtxt::param`] and [`ParamsCtxt::set_param`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamValue {
+    /// A value of a node with `IInteger` interface.
+    Integer(i64),
+    /// A value of a node with `IFloat` interface.
+    Float(f64),
+    /// A value of a node with `IString` interface.
+    String(String),
+    /// A value of a node with `IBoolean` interface.
+    Boolean(bool),
+    /// A symbolic of the entry of a node with `IEnumeration` interface.
+    Enumeration(String),
+}
+
+impl fmt::Display for ParamValue {
+    /// Formats the value in the same way as [`Node::value_string`] except that integers are
+    /// always decimal.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(v) => write!(f, "{}", v),
+            Self::Float(v) => write!(f, "{}", v),
+            Self::String(v) | Self::Enumeration(v) => f.write_str(v),
+            Self::Boolean(v) => f.write_str(if *v { "1" } else { "0" }),
+        }
+    }
+}
+
+impl From<i64> for ParamValue {
+    fn from(v: i64) -> Self {
+        Self::Integer(v)
+    }
+}
+
+impl From<f64> for ParamValue {
+    fn from(v: f64) -> Self {
+        Self::Float(v)
+    }
+}
+
+impl From<bool> for ParamValue {
+    fn from(v: bool) -> Self {
+        Self::Boolean(v)
     }
 }
 
@@ -197,6 +387,12 @@ pub trait GenApiCtxt {
     fn clear_cache(&mut self) {
         self.enter(|_, value_ctxt| value_ctxt.clear_cache())
     }
+
+    /// Returns [`ChangeNotifier`] of the context, or `None` if the context doesn't support
+    /// change notifications.
+    fn notifier(&self) -> Option<&ChangeNotifier> {
+        None
+    }
 }
 
 /// A trait that provides directly conversion from `GenApi` string to a `GenApi` context.
@@ -6,9 +6,11 @@
 //! Starndard`.
 
 use cameleon_genapi::{
+    dependency::DependencyKind,
     elem_type::{DisplayNotation, FloatRepresentation, IntegerRepresentation},
     interface::IncrementMode,
     prelude::*,
+    store::{NodeData, NodeStore},
     GenApiError, GenApiResult, NodeId,
 };
 
@@ -64,6 +66,12 @@ impl From<NodeId> for Node {
     }
 }
 
+impl From<Node> for NodeId {
+    fn from(node: Node) -> Self {
+        node.0
+    }
+}
+
 macro_rules! delegate {
     (
         $expect_kind:ident,
@@ -154,6 +162,20 @@ impl IntegerNode {
             .map(String::from)
     }
 
+    /// Returns the values which the node can take if `inc_mode` returns
+    /// IncrementMode::ListIncrement.
+    pub fn valid_value_set<Ctrl, Ctxt>(self, ctxt: &ParamsCtxt<Ctrl, Ctxt>) -> Vec<i64>
+    where
+        Ctxt: GenApiCtxt,
+    {
+        let ns = ctxt.node_store();
+        self.0
+            .expect_iinteger_kind(ns)
+            .unwrap()
+            .valid_value_set(ns)
+            .to_vec()
+    }
+
     /// Upcast to [`Node`].
     pub fn as_node(self) -> Node {
         Node(self.0)
@@ -192,6 +214,8 @@ impl FloatNode {
        pub fn representation<Ctrl, Ctxt>(self, ctxt: &ParamsCtxt<Ctrl, Ctxt>) ->FloatRepresentation,
        /// Returns [`DisplayNotation`]. This featres is mainly for GUI.
        pub fn display_notation<Ctrl, Ctxt>(self, ctxt: &ParamsCtxt<Ctrl, Ctxt>) -> DisplayNotation,
+       /// Returns the number of digits to display. This feature is mainly for GUI.
+       pub fn display_precision<Ctrl, Ctxt>(self, ctxt: &ParamsCtxt<Ctrl, Ctxt>) -> i64,
     }
 
     /// Returns unit that describes phisical meaning of the value. e.g. "Hz" or "ms".
@@ -502,6 +526,166 @@ impl Node {
         self.0.as_inode_kind(ns).unwrap().name(ns)
     }
 
+    /// Returns `true` if the node is a selector, i.e. other nodes refer to it as their selector.
+    pub fn is_selector<Ctrl, Ctxt>(self, ctxt: &ParamsCtxt<Ctrl, Ctxt>) -> bool
+    where
+        Ctxt: GenApiCtxt,
+    {
+        let ns = ctxt.node_store();
+        self.0.as_iselector_kind(ns).is_some_and(|kind| {
+            kind.selecting_nodes(ns)
+                .is_ok_and(|nodes| !nodes.is_empty())
+        })
+    }
+
+    /// Returns nodes selected by the node. Returns an empty vector if the node is not a selector.
+    pub fn selected_nodes<Ctrl, Ctxt>(self, ctxt: &ParamsCtxt<Ctrl, Ctxt>) -> Vec<Node>
+    where
+        Ctxt: GenApiCtxt,
+    {
+        let ns = ctxt.node_store();
+        self.0
+            .as_iselector_kind(ns)
+            .and_then(|kind| {
+                kind.selecting_nodes(ns)
+                    .ok()
+                    .map(|nodes| nodes.iter().map(|nid| Node(*nid)).collect())
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns selectors which select the node directly, i.e. nodes whose `pSelected` refers to
+    /// the node.
+    pub fn selectors<Ctrl, Ctxt>(self, ctxt: &ParamsCtxt<Ctrl, Ctxt>) -> Vec<Node>
+    where
+        Ctxt: GenApiCtxt,
+    {
+        let mut selectors = vec![];
+        ctxt.node_store().visit_nodes(|data| {
+            let (nid, selected) = match data {
+                NodeData::Integer(n) => (n.node_base().id(), n.p_selected()),
+                NodeData::IntReg(n) => (n.node_base().id(), n.p_selected()),
+                NodeData::MaskedIntReg(n) => (n.node_base().id(), n.p_selected()),
+                NodeData::Boolean(n) => (n.node_base().id(), n.p_selected()),
+                NodeData::Enumeration(n) => (n.node_base().id(), n.p_selected()),
+                _ => return,
+            };
+            if selected.contains(&self.0) {
+                selectors.push(Node(nid));
+            }
+        });
+        selectors
+    }
+
+    /// Returns nodes which the node refers to, e.g. through `pValue`, `pMax` or `pIsAvailable`,
+    /// with the elements through which they are referred.
+    pub fn dependencies<Ctrl, Ctxt>(
+        self,
+        ctxt: &ParamsCtxt<Ctrl, Ctxt>,
+    ) -> Vec<(DependencyKind, Node)>
+    where
+        Ctxt: GenApiCtxt,
+    {
+        ctxt.node_store()
+            .node(self.0)
+            .dependencies()
+            .into_iter()
+            .map(|dep| (dep.kind, Node(dep.node)))
+            .collect()
+    }
+
+    /// Returns `true` if the node is marked as streamable, i.e. the value of the node should be
+    /// persisted by [`ParamsCtxt::save_features`].
+    pub fn is_streamable<Ctrl, Ctxt>(self, ctxt: &ParamsCtxt<Ctrl, Ctxt>) -> bool
+    where
+        Ctxt: GenApiCtxt,
+    {
+        let ns = ctxt.node_store();
+        self.0.as_inode_kind(ns).unwrap().streamable()
+    }
+
+    /// Returns `true` if the node has a readable value.
+    ///
+    /// Returns `false` for nodes without value, e.g. categories and commands.
+    pub fn is_readable<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        if let Some(node) = self.as_integer(ctxt) {
+            node.is_readable(ctxt)
+        } else if let Some(node) = self.as_float(ctxt) {
+            node.is_readable(ctxt)
+        } else if let Some(node) = self.as_string(ctxt) {
+            node.is_readable(ctxt)
+        } else if let Some(node) = self.as_enumeration(ctxt) {
+            node.is_readable(ctxt)
+        } else if let Some(node) = self.as_boolean(ctxt) {
+            node.is_readable(ctxt)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Returns `true` if the node has a writable value or is an executable command.
+    ///
+    /// Returns `false` for nodes without value, e.g. categories.
+    pub fn is_writable<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        if let Some(node) = self.as_integer(ctxt) {
+            node.is_writable(ctxt)
+        } else if let Some(node) = self.as_float(ctxt) {
+            node.is_writable(ctxt)
+        } else if let Some(node) = self.as_string(ctxt) {
+            node.is_writable(ctxt)
+        } else if let Some(node) = self.as_enumeration(ctxt) {
+            node.is_writable(ctxt)
+        } else if let Some(node) = self.as_boolean(ctxt) {
+            node.is_writable(ctxt)
+        } else if let Some(node) = self.as_command(ctxt) {
+            node.is_writable(ctxt)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Returns `true` if the node is implemented on the device, i.e. `pIsImplemented` of the node
+    /// is not set or evaluates to `true`.
+    pub fn is_implemented<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        ctxt.enter2(|ctrl, ns, vc| {
+            let mut device = GenApiDevice::new(ctrl);
+            self.0
+                .as_inode_kind(ns)
+                .unwrap()
+                .node_base_precise()
+                .is_implemented(&mut device, ns, vc)
+        })
+    }
+
+    /// Returns `true` if the node is currently available, i.e. `pIsAvailable` of the node is not
+    /// set or evaluates to `true`.
+    pub fn is_available<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        ctxt.enter2(|ctrl, ns, vc| {
+            let mut device = GenApiDevice::new(ctrl);
+            self.0
+                .as_inode_kind(ns)
+                .unwrap()
+                .node_base_precise()
+                .is_available(&mut device, ns, vc)
+        })
+    }
+
     /// Returns display name of the node. This method is mainly for GUI.
     pub fn display_name<Ctrl, Ctxt>(self, ctxt: &ParamsCtxt<Ctrl, Ctxt>) -> &str
     where
@@ -0,0 +1,503 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains notifications of changes of node values.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use cameleon_genapi::{dependency::DependencyGraph, store::NodeStore, NodeId, ValueCtxt};
+
+use super::{
+    DefaultGenApiCtxt, FromXml, GenApiCtxt, NoCacheGenApiCtxt, Node, ParamsCtxt,
+    SharedDefaultGenApiCtxt, SharedNoCacheGenApiCtxt,
+};
+use crate::ControlResult;
+
+/// The reason why the value of a node may have changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeCause {
+    /// The node was written.
+    Written,
+    /// A node which the node reads or which invalidates the node changed.
+    Invalidated,
+    /// The node was refreshed by polling and its value changed.
+    Polled,
+}
+
+/// A notification passed to callbacks registered by [`ParamsCtxt::on_change`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChangeEvent {
+    /// The node whose value may have changed.
+    pub node: Node,
+    /// The reason of the change.
+    pub cause: ChangeCause,
+}
+
+/// An identifier of a callback registered by [`ParamsCtxt::on_change`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+type Callback = Box<dyn FnMut(&ChangeEvent) + Send>;
+
+/// Callbacks notified of changes of node values.
+///
+/// Clones of the notifier share the callbacks, so a notifier in a shared context like
+/// `NotifyingGenApiCtxt<SharedDefaultGenApiCtxt>` notifies changes made through any of its
+/// clones.
+#[derive(Clone, Default)]
+pub struct ChangeNotifier {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    subscriptions: Vec<Subscription>,
+    /// Built when the first notification is sent after a callback is registered.
+    graph: Option<DependencyGraph>,
+}
+
+struct Subscription {
+    id: SubscriptionId,
+    node: NodeId,
+    /// `None` while the callback is being called.
+    callback: Option<Callback>,
+    /// Events waiting for the callback to be called.
+    queue: Vec<ChangeEvent>,
+}
+
+impl ChangeNotifier {
+    /// Returns a notifier without callbacks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `f` to be called when the value of `node` may have changed.
+    pub fn subscribe<F>(&self, node: Node, f: F) -> SubscriptionId
+    where
+        F: FnMut(&ChangeEvent) + Send + 'static,
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let id = SubscriptionId(inner.next_id);
+        inner.next_id += 1;
+        inner.subscriptions.push(Subscription {
+            id,
+            node: node.into(),
+            callback: Some(Box::new(f)),
+            queue: vec![],
+        });
+        id
+    }
+
+    /// Returns `true` if any callback is registered.
+    pub fn has_subscriptions(&self) -> bool {
+        !self.inner.lock().unwrap().subscriptions.is_empty()
+    }
+
+    /// Removes the callback registered as `id`. Returns `false` if there is no such callback.
+    ///
+    /// If the callback is being called, it is dropped after the call returns.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.subscriptions.len();
+        inner.subscriptions.retain(|sub| sub.id != id);
+        inner.subscriptions.len() != len
+    }
+
+    /// Notifies that `nodes` changed because of `cause`.
+    ///
+    /// Nodes which read the changed nodes or are invalidated by them, directly or indirectly, are
+    /// notified with [`ChangeCause::Invalidated`].
+    ///
+    /// Callbacks are called without the lock of the notifier held, so they may write nodes and
+    /// register or remove callbacks. A callback is never called reentrantly: events for a
+    /// callback which is being called, e.g. caused by a write in the callback itself, are queued
+    /// and delivered after the call returns.
+    pub fn notify<I>(&self, store: &impl NodeStore, nodes: I, cause: ChangeCause)
+    where
+        I: IntoIterator<Item = NodeId>,
+    {
+        let mut ready = vec![];
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.subscriptions.is_empty() {
+                return;
+            }
+
+            let causes = inner.affected_causes(store, nodes, cause);
+            for sub in &mut inner.subscriptions {
+                if let Some(&cause) = causes.get(&sub.node) {
+                    sub.queue.push(ChangeEvent {
+                        node: Node(sub.node),
+                        cause,
+                    });
+                    if let Some(callback) = sub.callback.take() {
+                        ready.push((sub.id, callback));
+                    }
+                }
+            }
+        }
+
+        for (id, callback) in ready {
+            self.drain(id, callback);
+        }
+    }
+
+    /// Calls `callback` with the queued events of `id` until the queue is empty, then puts the
+    /// callback back.
+    fn drain(&self, id: SubscriptionId, mut callback: Callback) {
+        loop {
+            let events = {
+                let mut inner = self.inner.lock().unwrap();
+                // The callback was removed while it was being called.
+                let Some(sub) = inner.subscriptions.iter_mut().find(|sub| sub.id == id) else {
+                    return;
+                };
+                if sub.queue.is_empty() {
+                    sub.callback = Some(callback);
+                    return;
+                }
+                std::mem::take(&mut sub.queue)
+            };
+
+            for event in &events {
+                callback(event);
+            }
+        }
+    }
+}
+
+impl Inner {
+    /// Returns the causes of `nodes` and of the nodes affected by them.
+    fn affected_causes<I>(
+        &mut self,
+        store: &impl NodeStore,
+        nodes: I,
+        cause: ChangeCause,
+    ) -> HashMap<NodeId, ChangeCause>
+    where
+        I: IntoIterator<Item = NodeId>,
+    {
+        let graph = self
+            .graph
+            .get_or_insert_with(|| DependencyGraph::new(store));
+        let mut causes = HashMap::new();
+        for nid in nodes {
+            causes.insert(nid, cause);
+        }
+        let changed: Vec<NodeId> = causes.keys().copied().collect();
+        for nid in changed {
+            for affected in graph.affected_nodes(nid) {
+                causes.entry(affected).or_insert(ChangeCause::Invalidated);
+            }
+        }
+        causes
+    }
+}
+
+impl fmt::Debug for ChangeNotifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        f.debug_struct("ChangeNotifier")
+            .field("callbacks", &inner.subscriptions.len())
+            .finish()
+    }
+}
+
+/// A `GenApi` context which notifies changes of node values to the callbacks registered by
+/// [`ParamsCtxt::on_change`].
+///
+/// Wrap any context to enable change notifications, e.g.
+/// `NotifyingGenApiCtxt<SharedDefaultGenApiCtxt>` to notify changes made through the clones of a
+/// shared context.
+#[derive(Debug, Clone, Default)]
+pub struct NotifyingGenApiCtxt<Ctxt> {
+    /// The wrapped context.
+    pub ctxt: Ctxt,
+    /// Callbacks notified of changes of node values.
+    pub notifier: ChangeNotifier,
+}
+
+impl<Ctxt> GenApiCtxt for NotifyingGenApiCtxt<Ctxt>
+where
+    Ctxt: GenApiCtxt,
+{
+    type NS = Ctxt::NS;
+    type VS = Ctxt::VS;
+    type CS = Ctxt::CS;
+
+    fn enter<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&Self::NS, &mut ValueCtxt<Self::VS, Self::CS>) -> R,
+    {
+        self.ctxt.enter(f)
+    }
+
+    fn node_store(&self) -> &Self::NS {
+        self.ctxt.node_store()
+    }
+
+    fn clear_cache(&mut self) {
+        self.ctxt.clear_cache();
+    }
+
+    fn notifier(&self) -> Option<&ChangeNotifier> {
+        Some(&self.notifier)
+    }
+}
+
+impl<Ctxt> FromXml for NotifyingGenApiCtxt<Ctxt>
+where
+    Ctxt: FromXml + GenApiCtxt,
+{
+    fn from_xml(xml: &impl AsRef<str>) -> ControlResult<Self>
+    where
+        Self: Sized + GenApiCtxt,
+    {
+        Ok(Ctxt::from_xml(xml)?.into())
+    }
+}
+
+impl<Ctxt> From<Ctxt> for NotifyingGenApiCtxt<Ctxt> {
+    fn from(ctxt: Ctxt) -> Self {
+        Self {
+            ctxt,
+            notifier: ChangeNotifier::new(),
+        }
+    }
+}
+
+macro_rules! impl_from_notifying {
+    ($($from:ty => $to:ty,)*) => {
+        $(
+            impl From<NotifyingGenApiCtxt<$from>> for NotifyingGenApiCtxt<$to> {
+                fn from(from: NotifyingGenApiCtxt<$from>) -> Self {
+                    Self {
+                        ctxt: from.ctxt.into(),
+                        notifier: from.notifier,
+                    }
+                }
+            }
+
+            impl From<$from> for NotifyingGenApiCtxt<$to> {
+                fn from(from: $from) -> Self {
+                    Self::from(<$to>::from(from))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_notifying! {
+    DefaultGenApiCtxt => SharedDefaultGenApiCtxt,
+    DefaultGenApiCtxt => NoCacheGenApiCtxt,
+    DefaultGenApiCtxt => SharedNoCacheGenApiCtxt,
+    NoCacheGenApiCtxt => SharedNoCacheGenApiCtxt,
+}
+
+impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt>
+where
+    Ctxt: GenApiCtxt,
+{
+    /// Registers `f` to be called when the value of `node` may have changed, i.e. when `node` is
+    /// written, when a node which `node` reads or which invalidates `node` changes, or when
+    /// polling finds a new value.
+    ///
+    /// The callback is kept in the `GenApi` context, so it stays registered after this
+    /// `ParamsCtxt` is dropped. See [`ChangeNotifier::notify`] for how callbacks are called.
+    ///
+    /// Returns `None` if the context doesn't support change notifications, see
+    /// [`GenApiCtxt::notifier`]. Wrap the context with [`NotifyingGenApiCtxt`] to support them.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use cameleon::{genapi::{DefaultGenApiCtxt, NotifyingGenApiCtxt}, u3v};
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let camera = cameras.pop().unwrap();
+    /// let mut camera: cameleon::Camera<
+    ///     u3v::ControlHandle,
+    ///     u3v::StreamHandle,
+    ///     NotifyingGenApiCtxt<DefaultGenApiCtxt>,
+    /// > = camera.convert_into();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// let offset_x = params_ctxt.node("OffsetX").unwrap();
+    /// params_ctxt.on_change(offset_x, |event| println!("{:?}", event.cause));
+    ///
+    /// // The range of `OffsetX` depends on `Width`, so the callback is called.
+    /// params_ctxt.set_param("Width", &640.into()).unwrap();
+    /// ```
+    pub fn on_change<F>(&self, node: Node, f: F) -> Option<SubscriptionId>
+    where
+        F: FnMut(&ChangeEvent) + Send + 'static,
+    {
+        Some(self.ctxt.notifier()?.subscribe(node, f))
+    }
+
+    /// Removes the callback registered by [`Self::on_change`]. Returns `false` if there is no
+    /// such callback.
+    pub fn remove_on_change(&self, id: SubscriptionId) -> bool {
+        self.ctxt
+            .notifier()
+            .is_some_and(|notifier| notifier.unsubscribe(id))
+    }
+
+    /// Notifies `nodes` changed because of `cause`, if the context supports change
+    /// notifications.
+    pub(super) fn notify_changes<I>(&self, nodes: I, cause: ChangeCause)
+    where
+        I: IntoIterator<Item = NodeId>,
+    {
+        if let Some(notifier) = self.ctxt.notifier() {
+            notifier.notify(self.node_store(), nodes, cause);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::test_utils;
+
+    const NODES: &str = r#"
+        <Integer Name="SensorWidth">
+            <Value>100</Value>
+        </Integer>
+        <Integer Name="Width">
+            <Value>100</Value>
+        </Integer>
+        <Integer Name="OffsetX">
+            <Value>0</Value>
+            <Min>0</Min>
+            <pMax>OffsetXMax</pMax>
+        </Integer>
+        <IntSwissKnife Name="OffsetXMax">
+            <pVariable Name="SENSOR">SensorWidth</pVariable>
+            <pVariable Name="WIDTH">Width</pVariable>
+            <Formula>SENSOR - WIDTH</Formula>
+        </IntSwissKnife>
+        <Integer Name="Gain">
+            <Value>0</Value>
+        </Integer>
+    "#;
+
+    #[test]
+    fn test_on_change() {
+        let mut ctxt = test_utils::notifying_params_ctxt(NODES);
+        let offset_x = ctxt.node("OffsetX").unwrap();
+        let (tx, rx) = mpsc::channel();
+        let id = ctxt
+            .on_change(offset_x, move |event| tx.send(*event).unwrap())
+            .unwrap();
+
+        ctxt.set_param("OffsetX", &10.into()).unwrap();
+        ctxt.set_param("Width", &50.into()).unwrap();
+        ctxt.set_param("Gain", &1.into()).unwrap();
+        let events: Vec<_> = rx.try_iter().collect();
+        assert_eq!(
+            events,
+            [
+                ChangeEvent {
+                    node: offset_x,
+                    cause: ChangeCause::Written
+                },
+                ChangeEvent {
+                    node: offset_x,
+                    cause: ChangeCause::Invalidated
+                },
+            ]
+        );
+
+        assert!(ctxt.remove_on_change(id));
+        assert!(!ctxt.remove_on_change(id));
+        ctxt.set_param("OffsetX", &20.into()).unwrap();
+        assert!(rx.try_iter().next().is_none());
+    }
+
+    #[test]
+    fn test_without_notifier() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        let gain = ctxt.node("Gain").unwrap();
+        assert!(ctxt.on_change(gain, |_| {}).is_none());
+        ctxt.set_param("Gain", &1.into()).unwrap();
+        assert!(!ctxt.remove_on_change(SubscriptionId(0)));
+    }
+
+    #[test]
+    fn test_write_outside_params_ctxt() {
+        let mut ctxt = test_utils::notifying_params_ctxt(NODES);
+        let gain = ctxt.node("Gain").unwrap();
+        let (tx, rx) = mpsc::channel();
+        ctxt.on_change(gain, move |event| tx.send(*event).unwrap());
+
+        // Writes through `GenApiCtxt::enter` aren't recorded, so they aren't reported later.
+        ctxt.ctxt
+            .enter(|_, value_ctxt| value_ctxt.invalidate_cache_by(gain.into()));
+        ctxt.set_param("Width", &50.into()).unwrap();
+        assert!(rx.try_iter().next().is_none());
+    }
+
+    #[test]
+    fn test_write_in_callback() {
+        let ctxt = test_utils::notifying_params_ctxt(NODES);
+        let shared: ParamsCtxt<_, NotifyingGenApiCtxt<SharedDefaultGenApiCtxt>> = ParamsCtxt {
+            ctrl: ctxt.ctrl,
+            ctxt: ctxt.ctxt.into(),
+        };
+        let width = shared.node("Width").unwrap();
+        let gain = shared.node("Gain").unwrap();
+
+        // The callback writes through a clone of the shared context, which notifies again.
+        let mut inner = shared.clone();
+        let (tx, rx) = mpsc::channel();
+        shared.on_change(width, move |_| {
+            inner.set_param("Gain", &1.into()).unwrap();
+        });
+        shared.on_change(gain, move |event| tx.send(*event).unwrap());
+
+        let mut outer = shared;
+        outer.set_param("Width", &50.into()).unwrap();
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            [ChangeEvent {
+                node: gain,
+                cause: ChangeCause::Written
+            }]
+        );
+    }
+
+    #[test]
+    fn test_reentrant_callback() {
+        let ctxt = test_utils::notifying_params_ctxt(NODES);
+        let shared: ParamsCtxt<_, NotifyingGenApiCtxt<SharedDefaultGenApiCtxt>> = ParamsCtxt {
+            ctrl: ctxt.ctrl,
+            ctxt: ctxt.ctxt.into(),
+        };
+        let gain = shared.node("Gain").unwrap();
+
+        // A write of the subscribed node in its own callback is delivered after the call returns.
+        let mut inner = shared.clone();
+        let (tx, rx) = mpsc::channel();
+        shared.on_change(gain, move |event| {
+            tx.send(*event).unwrap();
+            let value = inner.param("Gain").unwrap();
+            if value == 1.into() {
+                inner.set_param("Gain", &2.into()).unwrap();
+            }
+        });
+
+        let mut outer = shared.clone();
+        outer.set_param("Gain", &1.into()).unwrap();
+        assert_eq!(rx.try_iter().count(), 2);
+        assert_eq!(outer.param("Gain").unwrap(), 2.into());
+    }
+}
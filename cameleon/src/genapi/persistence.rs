@@ -0,0 +1,468 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains saving and loading of feature values in the `GenApi` feature persistence
+//! format, which is known as `.pfs` files.
+//!
+//! A file consists of comment lines starting with `#` and feature lines of `Name\tValue`. Feature
+//! lines are written in the order they must be replayed, selectors are written right before the
+//! features they select, and features are written after the features which their value, range,
+//! availability or lock state depend on, in the same order as [`ParamsCtxt::apply_params`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, BufRead, Write},
+};
+
+use cameleon_genapi::{GenApiError, GenApiResult};
+
+use super::{CategoryNode, DeviceControl, GenApiCtxt, Node, ParamsCtxt};
+
+/// The GUID which identifies a feature persistence file.
+const FILE_GUID: &str = "{05D8C294-F295-4dfb-9D01-096BD04049F4}";
+
+/// The version of the feature persistence file format.
+const FILE_VERSION: &str = "3.0.0";
+
+/// An error which aborts [`ParamsCtxt::save_features`] or [`ParamsCtxt::load_features`].
+#[derive(Debug, thiserror::Error)]
+pub enum PersistenceError {
+    /// Failed to read or write the file.
+    #[error("input/output error: {0}")]
+    Io(#[from] io::Error),
+
+    /// Failed to access a feature while saving.
+    #[error("failed to save `{name}`: {source}")]
+    Feature {
+        /// The name of the feature.
+        name: String,
+        /// The error returned from the feature.
+        source: GenApiError,
+    },
+}
+
+/// An error of a feature line which failed to be loaded by [`ParamsCtxt::load_features`].
+#[derive(Debug, thiserror::Error)]
+#[error("line {line}: failed to load `{name}`: {error}")]
+pub struct FeatureError {
+    /// The line number of the feature, starting from 1.
+    pub line: usize,
+    /// The name of the feature.
+    pub name: String,
+    /// The error returned from the feature.
+    pub error: GenApiError,
+}
+
+impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    /// Writes the values of all features to `writer` in the `GenApi` feature persistence format.
+    ///
+    /// Features are collected by walking categories from `Root`. A feature is saved if it is
+    /// marked as streamable, is not hidden by `ExposeStatic`, and is readable and writable.
+    /// Features are ordered by their dependencies in the same way as
+    /// [`ParamsCtxt::apply_params`], so that replaying the file writes prerequisites first.
+    /// Features which have selectors are saved for each value of the selectors, and the
+    /// selectors are restored to their original values afterwards.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// let file = std::fs::File::create("camera.pfs").unwrap();
+    /// params_ctxt.save_features(std::io::BufWriter::new(file)).unwrap();
+    /// ```
+    pub fn save_features<W: Write>(&mut self, mut writer: W) -> Result<(), PersistenceError> {
+        let root = self
+            .node("Root")
+            .and_then(|node| node.as_category(self))
+            .ok_or_else(|| PersistenceError::Feature {
+                name: "Root".into(),
+                source: GenApiError::InvalidNode("no category named `Root`".into()),
+            })?;
+
+        let mut features = vec![];
+        self.collect_features(root, &mut HashSet::new(), &mut features);
+        let features: Vec<Node> = self
+            .write_order(&features)
+            .into_iter()
+            .map(|i| features[i])
+            .collect();
+        let owners = self.selector_owners(&features);
+
+        writeln!(writer, "# {}", FILE_GUID)?;
+        writeln!(
+            writer,
+            "# GenApi persistence file (version {})",
+            FILE_VERSION
+        )?;
+
+        let saver = Saver::new(features, &owners);
+        for &feature in saver.children(None) {
+            self.save_feature(feature, &saver, &mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Replays features written by [`Self::save_features`] from `reader`.
+    ///
+    /// Loading continues even if some features fail to be written, and the errors of the failed
+    /// features are returned. An error is returned only if `reader` fails.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// let file = std::fs::File::open("camera.pfs").unwrap();
+    /// for err in params_ctxt.load_features(std::io::BufReader::new(file)).unwrap() {
+    ///     println!("{}", err);
+    /// }
+    /// ```
+    pub fn load_features<R: BufRead>(
+        &mut self,
+        reader: R,
+    ) -> Result<Vec<FeatureError>, PersistenceError> {
+        let mut errors = vec![];
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = split_line(line);
+            if let Err(error) = self.load_feature(name, value) {
+                errors.push(FeatureError {
+                    line: i + 1,
+                    name: name.to_string(),
+                    error,
+                });
+            }
+        }
+        Ok(errors)
+    }
+
+    /// Collects persistable features under `category` in depth-first order.
+    fn collect_features(
+        &mut self,
+        category: CategoryNode,
+        visited: &mut HashSet<Node>,
+        features: &mut Vec<Node>,
+    ) {
+        for node in category.nodes(self) {
+            if !visited.insert(node) {
+                continue;
+            }
+            if let Some(category) = node.as_category(self) {
+                self.collect_features(category, visited, features);
+            } else if self.is_persistable(node) {
+                features.push(node);
+            }
+        }
+    }
+
+    fn is_persistable(&self, node: Node) -> bool {
+        let has_value = node.as_integer(self).is_some()
+            || node.as_float(self).is_some()
+            || node.as_string(self).is_some()
+            || node.as_enumeration(self).is_some()
+            || node.as_boolean(self).is_some();
+        has_value && node.is_streamable(self) && node.expose_static(self) != Some(false)
+    }
+
+    /// Maps each selected feature to the selector which iterates it.
+    ///
+    /// If a feature has multiple selectors, the last one in `features` owns it. Nested selectors
+    /// select each other, so the outer selectors still iterate the feature through the inner
+    /// one.
+    fn selector_owners(&self, features: &[Node]) -> HashMap<Node, Node> {
+        let persisted: HashSet<Node> = features.iter().copied().collect();
+        let mut owners = HashMap::new();
+        for &selector in features {
+            for selected in selector.selected_nodes(self) {
+                if persisted.contains(&selected) && !is_owned_by(&owners, selector, selected) {
+                    owners.insert(selected, selector);
+                }
+            }
+        }
+        owners
+    }
+
+    fn save_feature<W: Write>(
+        &mut self,
+        feature: Node,
+        saver: &Saver,
+        writer: &mut W,
+    ) -> Result<(), PersistenceError> {
+        let name = feature.name(self).to_string();
+        let with_name = |source| PersistenceError::Feature {
+            name: name.clone(),
+            source,
+        };
+
+        let is_accessible = feature.is_readable(self).map_err(with_name)?
+            && feature.is_writable(self).map_err(with_name)?;
+        let children = saver.children(Some(feature));
+
+        if children.is_empty() {
+            if is_accessible {
+                let value = self.param(&name).map_err(with_name)?;
+                writeln!(writer, "{}\t{}", name, value)?;
+            }
+            return Ok(());
+        }
+
+        // The selector can't be iterated, so the selected features are saved as they are.
+        if !is_accessible {
+            for &child in children {
+                self.save_feature(child, saver, writer)?;
+            }
+            return Ok(());
+        }
+
+        let original = self.param(&name).map_err(with_name)?;
+        for value in self.selector_values(feature).map_err(with_name)? {
+            // Values which can't be set in the current state of the device are skipped.
+            if self.set_param(&name, &value).is_err() {
+                continue;
+            }
+            writeln!(writer, "{}\t{}", name, value)?;
+            for &child in children {
+                self.save_feature(child, saver, writer)?;
+            }
+        }
+        self.set_param(&name, &original).map_err(with_name)?;
+        writeln!(writer, "{}\t{}", name, original)?;
+        Ok(())
+    }
+
+    fn load_feature(&mut self, name: &str, value: &str) -> GenApiResult<()> {
+        self.expect_node(name)?.set_value_string(self, value)
+    }
+}
+
+/// Features to be saved, grouped by the selectors which iterate them.
+struct Saver {
+    children: HashMap<Option<Node>, Vec<Node>>,
+}
+
+impl Saver {
+    fn new(features: Vec<Node>, owners: &HashMap<Node, Node>) -> Self {
+        let mut children: HashMap<_, Vec<_>> = HashMap::new();
+        for feature in features {
+            children
+                .entry(owners.get(&feature).copied())
+                .or_default()
+                .push(feature);
+        }
+        Self { children }
+    }
+
+    /// Returns features owned by `selector`, or features without selectors if `selector` is
+    /// `None`.
+    fn children(&self, selector: Option<Node>) -> &[Node] {
+        self.children.get(&selector).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Returns `true` if `node` is `selector` itself or one of the selectors which iterate
+/// `selector`.
+fn is_owned_by(owners: &HashMap<Node, Node>, selector: Node, node: Node) -> bool {
+    let mut current = selector;
+    // The chain can't be longer than the number of owners unless there is a cycle.
+    for _ in 0..=owners.len() {
+        if current == node {
+            return true;
+        }
+        match owners.get(&current) {
+            Some(&owner) => current = owner,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Splits a feature line into its name and value.
+///
+/// Names and values are separated by a tab, but files edited by hand may use spaces instead.
+fn split_line(line: &str) -> (&str, &str) {
+    if let Some((name, value)) = line.split_once('\t') {
+        (name.trim(), value)
+    } else {
+        let line = line.trim();
+        line.split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, value)| (name, value.trim_start()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{genapi::ParamValue, test_utils};
+
+    const NODES: &str = r#"
+        <Category Name="Root">
+            <pFeature>AnalogControl</pFeature>
+            <pFeature>Mode</pFeature>
+            <pFeature>Hidden</pFeature>
+            <pFeature>Volatile</pFeature>
+        </Category>
+        <Category Name="AnalogControl">
+            <pFeature>GainSelector</pFeature>
+            <pFeature>Gain</pFeature>
+        </Category>
+        <Integer Name="GainSelector">
+            <Streamable>Yes</Streamable>
+            <Value>0</Value>
+            <Min>0</Min>
+            <Max>1</Max>
+            <pSelected>Gain</pSelected>
+        </Integer>
+        <Integer Name="Gain">
+            <Streamable>Yes</Streamable>
+            <pIndex>GainSelector</pIndex>
+            <pValueIndexed Index="0">GainAll</pValueIndexed>
+            <pValueIndexed Index="1">GainRed</pValueIndexed>
+            <pValueDefault>GainAll</pValueDefault>
+        </Integer>
+        <Integer Name="GainAll">
+            <Value>10</Value>
+        </Integer>
+        <Integer Name="GainRed">
+            <Value>20</Value>
+        </Integer>
+        <Enumeration Name="Mode">
+            <Streamable>Yes</Streamable>
+            <EnumEntry Name="Continuous">
+                <Value>0</Value>
+            </EnumEntry>
+            <EnumEntry Name="SingleFrame">
+                <Value>1</Value>
+            </EnumEntry>
+            <Value>1</Value>
+        </Enumeration>
+        <Float Name="Hidden" ExposeStatic="No">
+            <Streamable>Yes</Streamable>
+            <Value>1.5</Value>
+        </Float>
+        <Float Name="Volatile">
+            <Value>2.5</Value>
+        </Float>
+    "#;
+
+    fn save(
+        ctxt: &mut ParamsCtxt<test_utils::FakeCtrl, crate::genapi::DefaultGenApiCtxt>,
+    ) -> String {
+        let mut buf = vec![];
+        ctxt.save_features(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_save_features() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        ctxt.set_param("GainSelector", &1.into()).unwrap();
+
+        let saved = save(&mut ctxt);
+        let features: Vec<_> = saved.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(
+            features,
+            [
+                "GainSelector\t0",
+                "Gain\t10",
+                "GainSelector\t1",
+                "Gain\t20",
+                "GainSelector\t1",
+                "Mode\tSingleFrame",
+            ]
+        );
+        assert!(saved.starts_with("# {05D8C294-F295-4dfb-9D01-096BD04049F4}\n"));
+        // The selector is restored.
+        assert_eq!(ctxt.param("GainSelector").unwrap(), 1.into());
+    }
+
+    #[test]
+    fn test_load_features() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        let file = "# comment\n\
+                    GainSelector\t0\n\
+                    Gain\t30\n\
+                    GainSelector 1\n\
+                    Gain\t40\n\
+                    Gain\tabc\n\
+                    Unknown\t1\n\
+                    Mode\tContinuous\n";
+
+        let errors = ctxt.load_features(file.as_bytes()).unwrap();
+        let failed: Vec<_> = errors.iter().map(|e| (e.line, e.name.as_str())).collect();
+        assert_eq!(failed, [(6, "Gain"), (7, "Unknown")]);
+
+        assert_eq!(
+            ctxt.param("Mode").unwrap(),
+            ParamValue::Enumeration("Continuous".into())
+        );
+        assert_eq!(ctxt.param("Gain").unwrap(), 40.into());
+        ctxt.set_param("GainSelector", &0.into()).unwrap();
+        assert_eq!(ctxt.param("Gain").unwrap(), 30.into());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        ctxt.set_param("GainSelector", &1.into()).unwrap();
+        ctxt.set_param("Gain", &25.into()).unwrap();
+        let saved = save(&mut ctxt);
+
+        let mut loaded = test_utils::params_ctxt(NODES);
+        assert!(loaded.load_features(saved.as_bytes()).unwrap().is_empty());
+        assert_eq!(save(&mut loaded), saved);
+    }
+
+    #[test]
+    fn test_save_in_dependency_order() {
+        let nodes = r#"
+            <Category Name="Root">
+                <pFeature>OffsetX</pFeature>
+                <pFeature>Width</pFeature>
+            </Category>
+            <Integer Name="OffsetX">
+                <Streamable>Yes</Streamable>
+                <Value>0</Value>
+                <Min>0</Min>
+                <pMax>OffsetXMax</pMax>
+            </Integer>
+            <IntSwissKnife Name="OffsetXMax">
+                <pVariable Name="WIDTH">Width</pVariable>
+                <Formula>100 - WIDTH</Formula>
+            </IntSwissKnife>
+            <Integer Name="Width">
+                <Streamable>Yes</Streamable>
+                <Value>100</Value>
+                <Min>1</Min>
+                <Max>100</Max>
+            </Integer>
+        "#;
+        let mut ctxt = test_utils::params_ctxt(nodes);
+        ctxt.set_param("Width", &40.into()).unwrap();
+        ctxt.set_param("OffsetX", &60.into()).unwrap();
+
+        // `Width` is written first because it limits `OffsetX`.
+        let saved = save(&mut ctxt);
+        let features: Vec<_> = saved.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(features, ["Width\t40", "OffsetX\t60"]);
+    }
+}
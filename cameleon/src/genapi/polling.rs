@@ -0,0 +1,376 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains polling of nodes which declare `PollingTime`.
+
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use cameleon_genapi::{
+    dependency::DependencyKind,
+    prelude::*,
+    store::{NodeData, NodeStore},
+    GenApiResult, NodeId,
+};
+use tracing::warn;
+
+use super::{ChangeCause, DeviceControl, GenApiCtxt, Node, ParamValue, ParamsCtxt};
+
+/// A background thread which periodically refreshes nodes declaring `PollingTime`, e.g.
+/// `DeviceTemperature`.
+///
+/// Each node is refreshed at its own interval: its cache is invalidated and its value is read
+/// again. A node whose `pBlockPolling` is set is skipped. If the value differs from the previous
+/// one, [`ChangeCause::Polled`] is notified to the callbacks registered by
+/// [`ParamsCtxt::on_change`].
+///
+/// The thread is stopped when the poller is dropped.
+#[derive(Debug)]
+pub struct Poller {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Poller {
+    /// Spawns a thread which polls the nodes of `ctxt`.
+    ///
+    /// The thread owns `ctxt`, so pass a context which shares the device and the `GenApi`
+    /// context with the application, e.g. `ParamsCtxt<SharedControlHandle,
+    /// NotifyingGenApiCtxt<SharedDefaultGenApiCtxt>>`. Accesses of the thread and the
+    /// application are serialized by the locks of the shared handles. Changes are notified only
+    /// if the context supports change notifications, see [`super::NotifyingGenApiCtxt`].
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use cameleon::{
+    /// #     genapi::{NotifyingGenApiCtxt, ParamsCtxt, Poller, SharedDefaultGenApiCtxt},
+    /// #     u3v,
+    /// # };
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let camera = cameras.pop().unwrap();
+    /// let mut camera: cameleon::Camera<
+    ///     u3v::SharedControlHandle,
+    ///     u3v::StreamHandle,
+    ///     NotifyingGenApiCtxt<SharedDefaultGenApiCtxt>,
+    /// > = camera.convert_into();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// // The poller shares the device and the context with `camera`.
+    /// let params_ctxt = ParamsCtxt {
+    ///     ctrl: camera.ctrl.clone(),
+    ///     ctxt: camera.ctxt.clone().unwrap(),
+    /// };
+    /// let temperature = params_ctxt.node("DeviceTemperature").unwrap();
+    /// params_ctxt.on_change(temperature, |_| println!("temperature changed"));
+    /// let poller = Poller::spawn(params_ctxt);
+    ///
+    /// // ...
+    ///
+    /// poller.stop();
+    /// ```
+    pub fn spawn<Ctrl, Ctxt>(mut ctxt: ParamsCtxt<Ctrl, Ctxt>) -> Self
+    where
+        Ctrl: DeviceControl + Send + 'static,
+        Ctxt: GenApiCtxt + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut schedule = Schedule::new(&ctxt, Instant::now());
+            loop {
+                let received = match schedule.next_due() {
+                    Some(due) => {
+                        stopped.recv_timeout(due.saturating_duration_since(Instant::now()))
+                    }
+                    None => stopped
+                        .recv()
+                        .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                };
+                match received {
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        schedule.poll_due(&mut ctxt, Instant::now());
+                    }
+                    Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// Stops the thread and waits for it to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            // The thread may have already finished.
+            let _ = stop.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                warn!("the polling thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt>
+where
+    Ctxt: GenApiCtxt,
+{
+    /// Returns nodes which declare `PollingTime`, with their polling intervals.
+    ///
+    /// A `PollingTime` of 0 is ignored, since polling such a node would keep the thread busy.
+    pub fn polled_nodes(&self) -> Vec<(Node, Duration)> {
+        let mut nodes = vec![];
+        self.node_store().visit_nodes(|data| {
+            let (nid, polling_time) = match data {
+                NodeData::Command(n) => (n.node_base().id(), n.polling_time()),
+                NodeData::Enumeration(n) => (n.node_base().id(), n.polling_time()),
+                NodeData::IntReg(n) => (n.node_base().id(), n.register_base().polling_time()),
+                NodeData::MaskedIntReg(n) => (n.node_base().id(), n.register_base().polling_time()),
+                NodeData::FloatReg(n) => (n.node_base().id(), n.register_base().polling_time()),
+                NodeData::StringReg(n) => (n.node_base().id(), n.register_base().polling_time()),
+                NodeData::Register(n) => (n.node_base().id(), n.register_base().polling_time()),
+                _ => return,
+            };
+            if let Some(ms) = polling_time.filter(|&ms| ms > 0) {
+                nodes.push((Node(nid), Duration::from_millis(ms)));
+            }
+        });
+        nodes
+    }
+}
+
+/// A node declaring `PollingTime` and its polling state.
+struct Polled {
+    node: Node,
+    interval: Duration,
+    due: Instant,
+    /// The node and the nodes behind its `pValue`, whose caches are invalidated before polling.
+    cached: Vec<NodeId>,
+    last: Option<ParamValue>,
+}
+
+/// Polling state of all nodes declaring `PollingTime` in a context.
+struct Schedule {
+    polled: Vec<Polled>,
+}
+
+impl Schedule {
+    fn new<Ctrl, Ctxt>(ctxt: &ParamsCtxt<Ctrl, Ctxt>, now: Instant) -> Self
+    where
+        Ctxt: GenApiCtxt,
+    {
+        let polled = ctxt
+            .polled_nodes()
+            .into_iter()
+            .map(|(node, interval)| Polled {
+                node,
+                interval,
+                due: now,
+                cached: value_nodes(ctxt, node),
+                last: None,
+            })
+            .collect();
+        Self { polled }
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.polled.iter().map(|polled| polled.due).min()
+    }
+
+    /// Polls the nodes due at `now`, and notifies the nodes whose value changed.
+    fn poll_due<Ctrl, Ctxt>(&mut self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, now: Instant)
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        let mut changed = vec![];
+        for polled in self.polled.iter_mut().filter(|polled| polled.due <= now) {
+            polled.due = now + polled.interval;
+            match refresh(ctxt, polled) {
+                Ok(Some(value)) => {
+                    if polled.last.as_ref().is_some_and(|last| *last != value) {
+                        changed.push(polled.node.into());
+                    }
+                    polled.last = Some(value);
+                }
+                Ok(None) => {}
+                Err(err) => warn!(node = polled.node.name(ctxt), %err, "failed to poll the node"),
+            }
+        }
+
+        if !changed.is_empty() {
+            ctxt.notify_changes(changed, ChangeCause::Polled);
+        }
+    }
+}
+
+/// Returns the node and the nodes which its value is read from through `pValue`.
+fn value_nodes<Ctrl, Ctxt>(ctxt: &ParamsCtxt<Ctrl, Ctxt>, node: Node) -> Vec<NodeId>
+where
+    Ctxt: GenApiCtxt,
+{
+    let mut nodes = vec![node];
+    let mut i = 0;
+    while let Some(&current) = nodes.get(i) {
+        for (kind, dep) in current.dependencies(ctxt) {
+            if kind == DependencyKind::Value && !nodes.contains(&dep) {
+                nodes.push(dep);
+            }
+        }
+        i += 1;
+    }
+    nodes.into_iter().map(NodeId::from).collect()
+}
+
+/// Reads the value of the polled node bypassing caches. Returns `None` if polling of the node is
+/// blocked.
+///
+/// The value of a command is whether the command is done.
+fn refresh<Ctrl, Ctxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    polled: &Polled,
+) -> GenApiResult<Option<ParamValue>>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    let node = polled.node;
+    if is_polling_blocked(ctxt, node)? {
+        return Ok(None);
+    }
+
+    ctxt.enter2(|_, _, value_ctxt| {
+        for &nid in &polled.cached {
+            value_ctxt.invalidate_cache_of(nid);
+        }
+    });
+    if let Some(command) = node.as_command(ctxt) {
+        Ok(Some(ParamValue::Boolean(command.is_done(ctxt)?)))
+    } else {
+        ctxt.node_param(node).map(Some)
+    }
+}
+
+fn is_polling_blocked<Ctrl, Ctxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    node: Node,
+) -> GenApiResult<bool>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    let block = node
+        .dependencies(ctxt)
+        .into_iter()
+        .find(|(kind, _)| *kind == DependencyKind::BlockPolling);
+    match block {
+        Some((_, block)) => match ctxt.node_param(block)? {
+            ParamValue::Integer(v) => Ok(v != 0),
+            ParamValue::Boolean(v) => Ok(v),
+            _ => Ok(false),
+        },
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::{genapi::ChangeEvent, test_utils};
+
+    const NODES: &str = r#"
+        <IntReg Name="DeviceTemperature">
+            <pBlockPolling>PollingBlocked</pBlockPolling>
+            <Address>0x10</Address>
+            <Length>4</Length>
+            <AccessMode>RO</AccessMode>
+            <pPort>Device</pPort>
+            <Cachable>WriteThrough</Cachable>
+            <PollingTime>100</PollingTime>
+            <Sign>Signed</Sign>
+            <Endianess>LittleEndian</Endianess>
+        </IntReg>
+        <Integer Name="PollingBlocked">
+            <Value>0</Value>
+        </Integer>
+        <Port Name="Device">
+        </Port>
+    "#;
+
+    #[test]
+    fn test_poll_due() {
+        let mut ctxt = test_utils::notifying_params_ctxt(NODES);
+        let temperature = ctxt.node("DeviceTemperature").unwrap();
+        assert_eq!(
+            ctxt.polled_nodes(),
+            [(temperature, Duration::from_millis(100))]
+        );
+
+        let (tx, rx) = mpsc::channel();
+        ctxt.on_change(temperature, move |event| tx.send(*event).unwrap())
+            .unwrap();
+        let start = Instant::now();
+        let mut schedule = Schedule::new(&ctxt, start);
+        schedule.poll_due(&mut ctxt, start);
+        assert_eq!(
+            schedule.next_due(),
+            Some(start + Duration::from_millis(100))
+        );
+
+        ctxt.ctrl.memory[0x10] = 40;
+        // Not due yet.
+        schedule.poll_due(&mut ctxt, start + Duration::from_millis(50));
+        assert!(rx.try_iter().next().is_none());
+
+        schedule.poll_due(&mut ctxt, start + Duration::from_millis(100));
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            [ChangeEvent {
+                node: temperature,
+                cause: ChangeCause::Polled
+            }]
+        );
+        assert_eq!(ctxt.param("DeviceTemperature").unwrap(), 40.into());
+
+        // Unchanged values are not notified.
+        schedule.poll_due(&mut ctxt, start + Duration::from_millis(200));
+        assert!(rx.try_iter().next().is_none());
+
+        ctxt.set_param("PollingBlocked", &1.into()).unwrap();
+        rx.try_iter().for_each(drop);
+        ctxt.ctrl.memory[0x10] = 50;
+        schedule.poll_due(&mut ctxt, start + Duration::from_millis(300));
+        assert!(rx.try_iter().next().is_none());
+    }
+
+    #[test]
+    fn test_zero_polling_time_ignored() {
+        let nodes = NODES.replace(
+            "<PollingTime>100</PollingTime>",
+            "<PollingTime>0</PollingTime>",
+        );
+        let ctxt = test_utils::notifying_params_ctxt(&nodes);
+        assert!(ctxt.polled_nodes().is_empty());
+        assert_eq!(Schedule::new(&ctxt, Instant::now()).next_due(), None);
+    }
+}
@@ -0,0 +1,442 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains access to features qualified by their selectors, e.g.
+//! `Gain[GainSelector=DigitalAll]`.
+
+use std::{fmt, str::FromStr};
+
+use cameleon_genapi::{GenApiError, GenApiResult};
+
+use super::{DeviceControl, GenApiCtxt, Node, ParamValue, ParamsCtxt};
+
+/// The maximum number of values an integer selector is enumerated with, e.g. the entries of a
+/// 16-bit `LUTIndex`.
+const MAX_SELECTOR_VALUES: usize = 1 << 16;
+
+/// A path to a feature qualified by the values of its selectors.
+///
+/// The string form is `Feature[Selector=Value]`. Multiple selectors are written as
+/// `Feature[Selector1=Value1][Selector2=Value2]` or `Feature[Selector1=Value1,Selector2=Value2]`,
+/// and are set in the written order.
+///
+/// # Examples
+/// ```rust
+/// use cameleon::genapi::FeaturePath;
+///
+/// let path: FeaturePath = "LUTValue[LUTSelector=Red][LUTIndex=3]".parse().unwrap();
+/// assert_eq!(path.feature(), "LUTValue");
+/// assert_eq!(path.selectors()[1], ("LUTIndex".to_string(), "3".to_string()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FeaturePath {
+    feature: String,
+    selectors: Vec<(String, String)>,
+}
+
+impl FeaturePath {
+    /// Returns a path to `feature` without selectors.
+    pub fn new(feature: impl Into<String>) -> Self {
+        Self {
+            feature: feature.into(),
+            selectors: vec![],
+        }
+    }
+
+    /// Appends a selector and its value to the path.
+    pub fn with_selector(mut self, selector: impl Into<String>, value: impl Into<String>) -> Self {
+        self.selectors.push((selector.into(), value.into()));
+        self
+    }
+
+    /// Returns the name of the feature.
+    pub fn feature(&self) -> &str {
+        &self.feature
+    }
+
+    /// Returns the selectors and their values in the order they are set.
+    pub fn selectors(&self) -> &[(String, String)] {
+        &self.selectors
+    }
+}
+
+impl FromStr for FeaturePath {
+    type Err = GenApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || GenApiError::InvalidData(format!("invalid feature path `{}`", s).into());
+
+        let s = s.trim();
+        let (feature, mut rest) = match s.find('[') {
+            Some(i) => (&s[..i], &s[i..]),
+            None => (s, ""),
+        };
+        let feature = feature.trim();
+        if feature.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut path = Self::new(feature);
+        while !rest.is_empty() {
+            let end = rest.find(']').ok_or_else(invalid)?;
+            if !rest.starts_with('[') {
+                return Err(invalid());
+            }
+            for selector in rest[1..end].split(',') {
+                let (name, value) = selector.split_once('=').ok_or_else(invalid)?;
+                let (name, value) = (name.trim(), value.trim());
+                if name.is_empty() || value.is_empty() {
+                    return Err(invalid());
+                }
+                path = path.with_selector(name, value);
+            }
+            rest = rest[end + 1..].trim_start();
+        }
+        Ok(path)
+    }
+}
+
+impl fmt::Display for FeaturePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.feature)?;
+        for (selector, value) in &self.selectors {
+            write!(f, "[{}={}]", selector, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    /// Sets the selectors in `path`, then calls `f` with the feature.
+    ///
+    /// The selectors are restored to their previous values in reverse order after `f` returns,
+    /// even if `f` fails.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// let path = "Gain[GainSelector=DigitalAll]".parse().unwrap();
+    /// let gain = params_ctxt
+    ///     .with_selected(&path, |ctxt, node| node.as_float(ctxt).unwrap().value(ctxt))
+    ///     .unwrap();
+    /// ```
+    pub fn with_selected<F, R>(&mut self, path: &FeaturePath, f: F) -> GenApiResult<R>
+    where
+        F: FnOnce(&mut Self, Node) -> GenApiResult<R>,
+    {
+        let feature = self.expect_node(path.feature())?;
+        let selectors = if path.selectors().is_empty() {
+            vec![]
+        } else {
+            self.all_selectors(feature)
+        };
+
+        let mut previous = vec![];
+        let mut result = Ok(());
+        for (name, value) in path.selectors() {
+            result = self.expect_node(name).and_then(|selector| {
+                if !selectors.contains(&selector) {
+                    return Err(GenApiError::InvalidNode(
+                        format!("`{}` is not a selector of `{}`", name, path.feature()).into(),
+                    ));
+                }
+                let original = selector.value_string(self)?;
+                selector.set_value_string(self, value)?;
+                previous.push((selector, original));
+                Ok(())
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+
+        let result = result.and_then(|()| f(self, feature));
+        let mut restored = Ok(());
+        for (selector, original) in previous.into_iter().rev() {
+            let res = selector.set_value_string(self, &original);
+            restored = restored.and(res);
+        }
+        let value = result?;
+        restored.map(|()| value)
+    }
+
+    /// Reads the value of the feature at `path` as a string.
+    ///
+    /// See [`FeaturePath`] for the syntax of `path` and [`Node::value_string`] for the format
+    /// of the value.
+    pub fn value_string_at(&mut self, path: &str) -> GenApiResult<String> {
+        self.with_selected(&path.parse()?, |ctxt, node| node.value_string(ctxt))
+    }
+
+    /// Writes `value` to the feature at `path`.
+    ///
+    /// See [`FeaturePath`] for the syntax of `path` and [`Node::set_value_string`] for the format
+    /// of `value`.
+    pub fn set_value_string_at(&mut self, path: &str, value: &str) -> GenApiResult<()> {
+        self.with_selected(&path.parse()?, |ctxt, node| {
+            node.set_value_string(ctxt, value)
+        })
+    }
+
+    /// Returns paths to the feature named `feature` for every combination of the values of its
+    /// selectors.
+    ///
+    /// Outer selectors come first in each path. The values of inner selectors are listed with the
+    /// outer selectors set, and the selectors are restored afterwards. If the feature has no
+    /// selectors, the only path is the feature itself. Fails if an integer selector takes more
+    /// than 65536 values.
+    pub fn selector_combinations(
+        &mut self,
+        feature: &str,
+    ) -> GenApiResult<impl Iterator<Item = FeaturePath>> {
+        let node = self.expect_node(feature)?;
+        let selectors = self.all_selectors(node);
+        let mut paths = vec![];
+        self.collect_combinations(FeaturePath::new(feature), &selectors, &mut paths)?;
+        Ok(paths.into_iter())
+    }
+
+    fn collect_combinations(
+        &mut self,
+        path: FeaturePath,
+        selectors: &[Node],
+        paths: &mut Vec<FeaturePath>,
+    ) -> GenApiResult<()> {
+        let (&selector, rest) = match selectors.split_first() {
+            Some(split) => split,
+            None => {
+                paths.push(path);
+                return Ok(());
+            }
+        };
+
+        let name = selector.name(self).to_string();
+        let values = self.selector_values(selector)?;
+        // The innermost selector doesn't affect the values of other selectors.
+        if rest.is_empty() {
+            for value in values {
+                paths.push(path.clone().with_selector(name.as_str(), value.to_string()));
+            }
+            return Ok(());
+        }
+
+        let original = selector.value_string(self)?;
+        let mut result = Ok(());
+        for value in values {
+            let value = value.to_string();
+            if selector.set_value_string(self, &value).is_err() {
+                continue;
+            }
+            let path = path.clone().with_selector(name.as_str(), value);
+            result = self.collect_combinations(path, rest, paths);
+            if result.is_err() {
+                break;
+            }
+        }
+        let restored = selector.set_value_string(self, &original);
+        result.and(restored)
+    }
+
+    /// Returns all values which `selector` can take in the current state of the device.
+    pub(super) fn selector_values(&mut self, selector: Node) -> GenApiResult<Vec<ParamValue>> {
+        let mut values = vec![];
+        if let Some(node) = selector.as_enumeration(self) {
+            for entry in node.entries(self) {
+                if entry.is_available(self)? && entry.is_implemented(self)? {
+                    values.push(ParamValue::Enumeration(entry.symbolic(self).to_string()));
+                }
+            }
+        } else if selector.as_boolean(self).is_some() {
+            values.extend([false, true].iter().map(|&v| ParamValue::Boolean(v)));
+        } else if let Some(node) = selector.as_integer(self) {
+            let (min, max) = (node.min(self)?, node.max(self)?);
+            let valid_values = node.valid_value_set(self);
+            if !valid_values.is_empty() {
+                values.extend(
+                    valid_values
+                        .into_iter()
+                        .filter(|v| (min..=max).contains(v))
+                        .map(ParamValue::Integer),
+                );
+                return Ok(values);
+            }
+
+            let inc = node.inc(self)?.filter(|&inc| inc > 0).unwrap_or(1);
+            if max >= min {
+                // Computed in `i128` because `max - min` can exceed `i64::MAX`.
+                let count = (i128::from(max) - i128::from(min)) / i128::from(inc) + 1;
+                if count > MAX_SELECTOR_VALUES as i128 {
+                    return Err(GenApiError::InvalidData(
+                        format!(
+                            "selector takes {} values, more than the limit {}",
+                            count, MAX_SELECTOR_VALUES
+                        )
+                        .into(),
+                    ));
+                }
+            }
+            let mut value = min;
+            while value <= max {
+                values.push(ParamValue::Integer(value));
+                value = match value.checked_add(inc) {
+                    Some(value) => value,
+                    None => break,
+                };
+            }
+        }
+        Ok(values)
+    }
+
+    /// Returns selectors which select `feature` directly or through other selectors, ordered
+    /// from outer to inner.
+    pub(super) fn all_selectors(&self, feature: Node) -> Vec<Node> {
+        let mut selectors: Vec<Node> = vec![];
+        let mut queue = vec![feature];
+        while let Some(node) = queue.pop() {
+            for selector in node.selectors(self) {
+                if selector != feature && !selectors.contains(&selector) {
+                    selectors.push(selector);
+                    queue.push(selector);
+                }
+            }
+        }
+
+        // A selector is outer than the selectors it selects.
+        let mut ordered = Vec::with_capacity(selectors.len());
+        while !selectors.is_empty() {
+            let outermost = selectors
+                .iter()
+                .position(|&inner| {
+                    !selectors
+                        .iter()
+                        .any(|outer| *outer != inner && outer.selected_nodes(self).contains(&inner))
+                })
+                // Selectors which select each other are ordered as they are found.
+                .unwrap_or(0);
+            ordered.push(selectors.remove(outermost));
+        }
+        ordered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    const NODES: &str = r#"
+        <Enumeration Name="LUTSelector">
+            <EnumEntry Name="Red">
+                <Value>0</Value>
+            </EnumEntry>
+            <EnumEntry Name="Blue">
+                <Value>1</Value>
+            </EnumEntry>
+            <Value>0</Value>
+            <pSelected>LUTIndex</pSelected>
+            <pSelected>LUTValue</pSelected>
+        </Enumeration>
+        <Integer Name="LUTIndex">
+            <Value>0</Value>
+            <Min>0</Min>
+            <Max>2</Max>
+            <Inc>2</Inc>
+            <pSelected>LUTValue</pSelected>
+        </Integer>
+        <Integer Name="LUTValue">
+            <pIndex>LUTIndex</pIndex>
+            <pValueIndexed Index="0">Value0</pValueIndexed>
+            <pValueIndexed Index="2">Value2</pValueIndexed>
+            <pValueDefault>Value0</pValueDefault>
+        </Integer>
+        <Integer Name="Value0">
+            <Value>10</Value>
+        </Integer>
+        <Integer Name="Value2">
+            <Value>20</Value>
+        </Integer>
+    "#;
+
+    #[test]
+    fn test_parse_path() {
+        let path: FeaturePath = "Gain[GainSelector=DigitalAll]".parse().unwrap();
+        assert_eq!(
+            path,
+            FeaturePath::new("Gain").with_selector("GainSelector", "DigitalAll")
+        );
+
+        let path: FeaturePath = " LUTValue [LUTSelector=Red, LUTIndex=3]".parse().unwrap();
+        assert_eq!(path.to_string(), "LUTValue[LUTSelector=Red][LUTIndex=3]");
+        assert_eq!(path, path.to_string().parse().unwrap());
+
+        for invalid in ["", "[A=1]", "Gain[A=1", "Gain[A]", "Gain[A=]", "Gain[A=1]x"].iter() {
+            assert!(invalid.parse::<FeaturePath>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_value_at() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        assert_eq!(ctxt.value_string_at("LUTValue[LUTIndex=2]").unwrap(), "20");
+
+        ctxt.set_value_string_at("LUTValue[LUTSelector=Blue][LUTIndex=2]", "30")
+            .unwrap();
+        assert_eq!(ctxt.value_string_at("LUTValue[LUTIndex=2]").unwrap(), "30");
+
+        // Selectors are restored.
+        assert_eq!(ctxt.value_string_at("LUTIndex").unwrap(), "0");
+        assert_eq!(ctxt.value_string_at("LUTSelector").unwrap(), "Red");
+
+        // Restored even if the access fails.
+        assert!(ctxt
+            .value_string_at("LUTValue[LUTIndex=2][Value0=1]")
+            .is_err());
+        assert!(ctxt
+            .set_value_string_at("LUTValue[LUTIndex=2]", "abc")
+            .is_err());
+        assert_eq!(ctxt.value_string_at("LUTIndex").unwrap(), "0");
+    }
+
+    #[test]
+    fn test_selector_combinations() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        let paths: Vec<_> = ctxt
+            .selector_combinations("LUTValue")
+            .unwrap()
+            .map(|path| path.to_string())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "LUTValue[LUTSelector=Red][LUTIndex=0]",
+                "LUTValue[LUTSelector=Red][LUTIndex=2]",
+                "LUTValue[LUTSelector=Blue][LUTIndex=0]",
+                "LUTValue[LUTSelector=Blue][LUTIndex=2]",
+            ]
+        );
+        assert_eq!(ctxt.value_string_at("LUTSelector").unwrap(), "Red");
+
+        let paths: Vec<_> = ctxt.selector_combinations("Value0").unwrap().collect();
+        assert_eq!(paths, [FeaturePath::new("Value0")]);
+    }
+
+    #[test]
+    fn test_selector_values_limit() {
+        let nodes = NODES.replace("<Max>2</Max>", "<Max>9223372036854775807</Max>");
+        let mut ctxt = test_utils::params_ctxt(&nodes);
+        let index = ctxt.node("LUTIndex").unwrap();
+        assert!(ctxt.selector_values(index).is_err());
+        assert!(ctxt.selector_combinations("LUTValue").is_err());
+    }
+}
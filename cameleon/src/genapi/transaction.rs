@@ -0,0 +1,192 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains writes of multiple features which are rolled back on failure.
+
+use cameleon_genapi::GenApiResult;
+use tracing::warn;
+
+use super::{DeviceControl, FeaturePath, GenApiCtxt, Node, ParamValue, ParamsCtxt};
+
+/// Writes of features in [`ParamsCtxt::transaction`].
+///
+/// Features are addressed by [`FeaturePath`], so a plain feature name and a selector-qualified
+/// path like `Gain[GainSelector=DigitalAll]` are both accepted.
+pub struct Transaction<'a, Ctrl, Ctxt> {
+    ctxt: &'a mut ParamsCtxt<Ctrl, Ctxt>,
+    /// Features written in the transaction and their values before the first write.
+    journal: Vec<(FeaturePath, ParamValue)>,
+}
+
+impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    /// Calls `f` with a [`Transaction`], and restores the features written through it if `f`
+    /// fails.
+    ///
+    /// The value of each feature is recorded before its first write in the transaction. If `f`
+    /// returns an error, the recorded values are written back in reverse order and the error is
+    /// returned. Failures of the restoration are logged and don't hide the original error.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// // If `Width` is invalid under the new `OffsetX`, `OffsetX` is restored.
+    /// params_ctxt
+    ///     .transaction(|tx| {
+    ///         tx.set_param("OffsetX", &16.into())?;
+    ///         tx.set_param("Width", &640.into())
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn transaction<F, R>(&mut self, f: F) -> GenApiResult<R>
+    where
+        F: FnOnce(&mut Transaction<'_, Ctrl, Ctxt>) -> GenApiResult<R>,
+    {
+        let mut tx = Transaction {
+            ctxt: self,
+            journal: vec![],
+        };
+        let result = f(&mut tx);
+        if result.is_err() {
+            tx.rollback();
+        }
+        result
+    }
+}
+
+impl<Ctrl, Ctxt> Transaction<'_, Ctrl, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    /// Reads the value of the feature at `path`.
+    pub fn param(&mut self, path: &str) -> GenApiResult<ParamValue> {
+        self.ctxt
+            .with_selected(&path.parse()?, |ctxt, node| ctxt.node_param(node))
+    }
+
+    /// Writes `value` to the feature at `path`. See [`ParamsCtxt::set_param`].
+    pub fn set_param(&mut self, path: &str, value: &ParamValue) -> GenApiResult<()> {
+        self.write(path, |ctxt, node| ctxt.set_node_param(node, value))
+    }
+
+    /// Parses `value` and writes it to the feature at `path`. See [`Node::set_value_string`].
+    pub fn set_value_string(&mut self, path: &str, value: &str) -> GenApiResult<()> {
+        self.write(path, |ctxt, node| node.set_value_string(ctxt, value))
+    }
+
+    fn write<F>(&mut self, path: &str, f: F) -> GenApiResult<()>
+    where
+        F: FnOnce(&mut ParamsCtxt<Ctrl, Ctxt>, Node) -> GenApiResult<()>,
+    {
+        let path: FeaturePath = path.parse()?;
+        let is_recorded = self.journal.iter().any(|(recorded, _)| *recorded == path);
+        let journal = &mut self.journal;
+        self.ctxt.with_selected(&path, |ctxt, node| {
+            let previous = if is_recorded {
+                None
+            } else {
+                Some(ctxt.node_param(node)?)
+            };
+            f(ctxt, node)?;
+            if let Some(previous) = previous {
+                journal.push((path.clone(), previous));
+            }
+            Ok(())
+        })
+    }
+
+    fn rollback(&mut self) {
+        while let Some((path, previous)) = self.journal.pop() {
+            let result = self
+                .ctxt
+                .with_selected(&path, |ctxt, node| ctxt.set_node_param(node, &previous));
+            if let Err(err) = result {
+                warn!(%path, %err, "failed to roll back the feature");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    const NODES: &str = r#"
+        <Integer Name="OffsetX">
+            <Value>0</Value>
+        </Integer>
+        <Integer Name="Width">
+            <Value>100</Value>
+        </Integer>
+        <Enumeration Name="PixelFormat">
+            <EnumEntry Name="Mono8">
+                <Value>0</Value>
+            </EnumEntry>
+            <Value>0</Value>
+        </Enumeration>
+        <Integer Name="GainSelector">
+            <Value>0</Value>
+            <Min>0</Min>
+            <Max>1</Max>
+            <pSelected>Gain</pSelected>
+        </Integer>
+        <Integer Name="Gain">
+            <pIndex>GainSelector</pIndex>
+            <pValueIndexed Index="0">Gain0</pValueIndexed>
+            <pValueIndexed Index="1">Gain1</pValueIndexed>
+            <pValueDefault>Gain0</pValueDefault>
+        </Integer>
+        <Integer Name="Gain0">
+            <Value>0</Value>
+        </Integer>
+        <Integer Name="Gain1">
+            <Value>0</Value>
+        </Integer>
+    "#;
+
+    #[test]
+    fn test_commit() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        let width = ctxt
+            .transaction(|tx| {
+                tx.set_param("OffsetX", &10.into())?;
+                tx.set_value_string("Width", "90")?;
+                tx.param("Width")
+            })
+            .unwrap();
+
+        assert_eq!(width, 90.into());
+        assert_eq!(ctxt.param("OffsetX").unwrap(), 10.into());
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut ctxt = test_utils::params_ctxt(NODES);
+        let result = ctxt.transaction(|tx| {
+            tx.set_param("OffsetX", &10.into())?;
+            tx.set_param("OffsetX", &20.into())?;
+            tx.set_param("Gain[GainSelector=1]", &5.into())?;
+            tx.set_param("Width", &50.into())?;
+            // `PixelFormat` doesn't have the entry.
+            tx.set_param("PixelFormat", &ParamValue::Enumeration("Mono16".into()))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(ctxt.param("OffsetX").unwrap(), 0.into());
+        assert_eq!(ctxt.param("Width").unwrap(), 100.into());
+        assert_eq!(ctxt.param("Gain1").unwrap(), 0.into());
+        assert_eq!(ctxt.param("GainSelector").unwrap(), 0.into());
+    }
+}
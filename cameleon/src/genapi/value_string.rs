@@ -0,0 +1,318 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains conversion between values of nodes and strings, which corresponds to
+//! `IValue::ToString` and `IValue::FromString` of `GenApi`.
+
+use std::convert::TryFrom;
+
+use cameleon_genapi::{
+    elem_type::{DisplayNotation, IntegerRepresentation},
+    GenApiError, GenApiResult,
+};
+
+use super::{DeviceControl, GenApiCtxt, Node, ParamsCtxt};
+
+/// The display precision used when a node has a negative precision.
+const DEFAULT_DISPLAY_PRECISION: usize = 6;
+
+impl Node {
+    /// Returns the value of the node as a string.
+    ///
+    /// Integers are formatted according to their [`IntegerRepresentation`], e.g. `0x1F` for
+    /// `HexNumber`, `192.168.0.1` for `IpV4Address` and `00:11:22:AA:BB:CC` for `MacAddress`.
+    /// Floats are formatted according to their [`DisplayNotation`] and display precision.
+    /// Enumerations are formatted as the symbolic of the current entry, and booleans as `1` or
+    /// `0`.
+    pub fn value_string<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<String>
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        if let Some(node) = self.as_enumeration(ctxt) {
+            let entry = node.current_entry(ctxt)?;
+            Ok(entry.symbolic(ctxt).to_string())
+        } else if let Some(node) = self.as_boolean(ctxt) {
+            Ok(if node.value(ctxt)? { "1" } else { "0" }.to_string())
+        } else if let Some(node) = self.as_integer(ctxt) {
+            let value = node.value(ctxt)?;
+            Ok(format_integer(value, node.representation(ctxt)))
+        } else if let Some(node) = self.as_float(ctxt) {
+            let value = node.value(ctxt)?;
+            Ok(format_float(
+                value,
+                node.display_notation(ctxt),
+                node.display_precision(ctxt),
+            ))
+        } else if let Some(node) = self.as_string(ctxt) {
+            node.value(ctxt)
+        } else {
+            Err(GenApiError::InvalidNode(
+                format!("`{}` doesn't have a value", self.name(ctxt)).into(),
+            ))
+        }
+    }
+
+    /// Parses `value` and sets it to the node.
+    ///
+    /// Accepts strings returned from [`Self::value_string`]. In addition, integers may be written
+    /// in hexadecimal with `0x` prefix regardless of their representation, and booleans may be
+    /// written as `true` or `false`.
+    pub fn set_value_string<Ctrl, Ctxt>(
+        self,
+        ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+        value: &str,
+    ) -> GenApiResult<()>
+    where
+        Ctrl: DeviceControl,
+        Ctxt: GenApiCtxt,
+    {
+        let invalid_value = |ctxt: &ParamsCtxt<Ctrl, Ctxt>| {
+            GenApiError::InvalidData(
+                format!("`{}` is not a valid value of `{}`", value, self.name(ctxt)).into(),
+            )
+        };
+
+        if let Some(node) = self.as_enumeration(ctxt) {
+            node.set_entry_by_symbolic(ctxt, value.trim())
+        } else if let Some(node) = self.as_boolean(ctxt) {
+            let value = parse_boolean(value).ok_or_else(|| invalid_value(ctxt))?;
+            node.set_value(ctxt, value)
+        } else if let Some(node) = self.as_integer(ctxt) {
+            let value = parse_integer(value, node.representation(ctxt))
+                .ok_or_else(|| invalid_value(ctxt))?;
+            node.set_value(ctxt, value)
+        } else if let Some(node) = self.as_float(ctxt) {
+            let value = value.trim().parse().map_err(|_| invalid_value(ctxt))?;
+            node.set_value(ctxt, value)
+        } else if let Some(node) = self.as_string(ctxt) {
+            node.set_value(ctxt, value.to_string())
+        } else {
+            Err(GenApiError::InvalidNode(
+                format!("`{}` doesn't have a value", self.name(ctxt)).into(),
+            ))
+        }
+    }
+}
+
+fn format_integer(value: i64, repr: IntegerRepresentation) -> String {
+    match repr {
+        IntegerRepresentation::HexNumber => format!("0x{:X}", value),
+        IntegerRepresentation::IpV4Address => (0..4)
+            .rev()
+            .map(|i| ((value >> (i * 8)) & 0xff).to_string())
+            .collect::<Vec<_>>()
+            .join("."),
+        IntegerRepresentation::MacAddress => (0..6)
+            .rev()
+            .map(|i| format!("{:02X}", (value >> (i * 8)) & 0xff))
+            .collect::<Vec<_>>()
+            .join(":"),
+        _ => value.to_string(),
+    }
+}
+
+fn parse_integer(value: &str, repr: IntegerRepresentation) -> Option<i64> {
+    let value = value.trim();
+    match repr {
+        IntegerRepresentation::IpV4Address if value.contains('.') => {
+            parse_octets(value.split('.'), 4, 10)
+        }
+        IntegerRepresentation::MacAddress if value.contains([':', '-']) => {
+            parse_octets(value.split([':', '-']), 6, 16)
+        }
+        _ => parse_number(value),
+    }
+}
+
+/// Parses `expected` octets in `radix` and packs them in big endian.
+fn parse_octets<'a>(
+    octets: impl Iterator<Item = &'a str>,
+    expected: usize,
+    radix: u32,
+) -> Option<i64> {
+    let mut value = 0;
+    let mut count = 0;
+    for octet in octets {
+        value = (value << 8) | i64::from(u8::from_str_radix(octet, radix).ok()?);
+        count += 1;
+    }
+    (count == expected).then_some(value)
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal number.
+fn parse_number(value: &str) -> Option<i64> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        // Hexadecimal numbers are bit patterns, so `0xFFFFFFFFFFFFFFFF` is `-1`.
+        Some(hex) if !negative => u64::from_str_radix(hex, 16).ok().map(|v| v as i64),
+        Some(hex) => i64::from_str_radix(hex, 16).ok().map(|v| -v),
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_boolean(value: &str) -> Option<bool> {
+    let value = value.trim();
+    if value == "1" || value.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if value == "0" || value.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Formats `value` in the same way as `std::ostream` of C++ does, which `GenApi` uses.
+fn format_float(value: f64, notation: DisplayNotation, precision: i64) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let precision = usize::try_from(precision).unwrap_or(DEFAULT_DISPLAY_PRECISION);
+
+    match notation {
+        DisplayNotation::Fixed => format!("{:.*}", precision, value),
+        DisplayNotation::Scientific => format_scientific(value, precision),
+        DisplayNotation::Automatic => {
+            // Same as `%g` of `printf`, `precision` is the number of significant digits.
+            let precision = precision.max(1);
+            let exp = decimal_exponent(value, precision);
+            if exp < -4 || exp >= precision as i32 {
+                let formatted = format_scientific(value, precision - 1);
+                let (mantissa, exp) = formatted.split_once('e').unwrap();
+                format!("{}e{}", trim_fraction(mantissa), exp)
+            } else {
+                let decimals = (precision as i32 - 1 - exp) as usize;
+                trim_fraction(&format!("{:.*}", decimals, value)).to_string()
+            }
+        }
+    }
+}
+
+/// Formats `value` like `%.*e` of `printf`, e.g. `1.500000e+03`.
+fn format_scientific(value: f64, precision: usize) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exp) = formatted.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exp.abs())
+}
+
+/// Returns the decimal exponent of `value` after rounding it to `precision` significant digits.
+fn decimal_exponent(value: f64, precision: usize) -> i32 {
+    let formatted = format!("{:.*e}", precision - 1, value);
+    formatted.split_once('e').unwrap().1.parse().unwrap()
+}
+
+/// Removes trailing zeros of the fractional part.
+fn trim_fraction(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn test_format_integer() {
+        use IntegerRepresentation::*;
+
+        assert_eq!(format_integer(-42, PureNumber), "-42");
+        assert_eq!(format_integer(0x1f, HexNumber), "0x1F");
+        assert_eq!(format_integer(0xc0a8_0001, IpV4Address), "192.168.0.1");
+        assert_eq!(
+            format_integer(0x0011_22aa_bbcc, MacAddress),
+            "00:11:22:AA:BB:CC"
+        );
+    }
+
+    #[test]
+    fn test_parse_integer() {
+        use IntegerRepresentation::*;
+
+        assert_eq!(parse_integer(" -42 ", PureNumber), Some(-42));
+        assert_eq!(parse_integer("0x1F", PureNumber), Some(0x1f));
+        assert_eq!(parse_integer("0xFFFFFFFFFFFFFFFF", HexNumber), Some(-1));
+        assert_eq!(parse_integer("192.168.0.1", IpV4Address), Some(0xc0a8_0001));
+        assert_eq!(parse_integer("192.168.0", IpV4Address), None);
+        assert_eq!(parse_integer("192.168.0.256", IpV4Address), None);
+        assert_eq!(
+            parse_integer("00-11-22-aa-bb-cc", MacAddress),
+            Some(0x0011_22aa_bbcc)
+        );
+        assert_eq!(parse_integer("1.5", PureNumber), None);
+    }
+
+    #[test]
+    fn test_format_float() {
+        use DisplayNotation::*;
+
+        assert_eq!(format_float(1500.0, Automatic, 6), "1500");
+        assert_eq!(format_float(0.1, Automatic, 6), "0.1");
+        assert_eq!(format_float(1234567.0, Automatic, 6), "1.23457e+06");
+        assert_eq!(format_float(0.00001, Automatic, 6), "1e-05");
+        assert_eq!(format_float(999999.5, Automatic, 6), "1e+06");
+        assert_eq!(format_float(0.0, Automatic, 6), "0");
+        assert_eq!(format_float(1.5, Fixed, 3), "1.500");
+        assert_eq!(format_float(-1500.0, Scientific, 2), "-1.50e+03");
+        assert_eq!(format_float(1.5, Fixed, -1), "1.500000");
+    }
+
+    #[test]
+    fn test_value_string() {
+        let mut ctxt = test_utils::params_ctxt(
+            r#"
+            <Integer Name="Address">
+                <Value>0</Value>
+                <Representation>IPV4Address</Representation>
+            </Integer>
+            <Float Name="Exposure">
+                <Value>0</Value>
+                <DisplayNotation>Fixed</DisplayNotation>
+                <DisplayPrecision>2</DisplayPrecision>
+            </Float>
+            <Boolean Name="Enabled">
+                <Value>false</Value>
+            </Boolean>
+            <Enumeration Name="Mode">
+                <EnumEntry Name="Off">
+                    <Value>0</Value>
+                </EnumEntry>
+                <EnumEntry Name="On">
+                    <Value>1</Value>
+                </EnumEntry>
+                <Value>0</Value>
+            </Enumeration>
+            "#,
+        );
+
+        let cases = [
+            ("Address", "10.0.0.1", "10.0.0.1"),
+            ("Exposure", "1.005e2", "100.50"),
+            ("Enabled", "true", "1"),
+            ("Mode", "On", "On"),
+        ];
+        for (name, input, expected) in cases.iter() {
+            let node = ctxt.node(name).unwrap();
+            node.set_value_string(&mut ctxt, input).unwrap();
+            assert_eq!(&node.value_string(&mut ctxt).unwrap(), expected);
+        }
+
+        let address = ctxt.node("Address").unwrap();
+        assert!(matches!(
+            address.set_value_string(&mut ctxt, "10.0.0"),
+            Err(GenApiError::InvalidData(_))
+        ));
+    }
+}
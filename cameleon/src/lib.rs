@@ -143,16 +143,23 @@
     clippy::module_name_repetitions
 )]
 
+pub mod async_camera;
 pub mod camera;
 pub mod genapi;
 pub mod payload;
+pub mod typed_camera;
 #[cfg(feature = "libusb")]
 pub mod u3v;
 
+pub use async_camera::AsyncCamera;
 pub use camera::{Camera, CameraInfo, DeviceControl, PayloadStream};
+pub use typed_camera::TypedCamera;
 
 use std::{borrow::Cow, num::TryFromIntError};
 
+#[cfg(test)]
+mod test_utils;
+
 /// A specialized `Result` type for `camera::Camera`.
 pub type CameleonResult<T> = std::result::Result<T, CameleonError>;
 
@@ -175,6 +182,10 @@ pub enum CameleonError {
     #[error("invalid `GenApi` xml: {0}")]
     InvalidGenApiXml(Cow<'static, str>),
 
+    /// No camera matches the given condition.
+    #[error("camera not found: {0}")]
+    CameraNotFound(Cow<'static, str>),
+
     /// An error when `GenApi` node operation failed.
     #[error("`GenApi` error: {0}")]
     GenApiError(#[from] cameleon_genapi::GenApiError),
@@ -208,9 +219,13 @@ impl DeviceIoError {
 #[derive(Debug, thiserror::Error)]
 pub enum ControlError {
     /// The device is busy, may be opened by another application.
-    #[error("device is busy")]
+    #[error("device is busy, it may be opened by another application")]
     Busy,
 
+    /// Try to write to the device opened in read-only mode.
+    #[error("device is opened in read-only mode")]
+    ReadOnly,
+
     /// The device is disconnected from the host.
     #[error("device is disconnected")]
     Disconnected,
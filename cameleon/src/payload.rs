@@ -9,9 +9,16 @@
 
 pub use cameleon_device::PixelFormat;
 
-use std::time;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time,
+};
 
 use async_channel::{Receiver, Sender};
+use futures_core::Stream;
 
 use super::{StreamError, StreamResult};
 
@@ -26,6 +33,19 @@ pub enum PayloadType {
     Chunk,
 }
 
+/// Represents how completely the payload was received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadStatus {
+    /// The payload is received completely.
+    Complete,
+    /// The device discarded some of the payload data.
+    DataDiscarded,
+    /// The device lost some of the payload data, e.g. due to inappropriate transfer settings.
+    DataOverrun,
+    /// The host received less data than the device reported as valid.
+    Truncated,
+}
+
 /// Image meta information.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ImageInfo {
@@ -54,6 +74,8 @@ pub struct Payload {
     pub(crate) payload: Vec<u8>,
     pub(crate) valid_payload_size: usize,
     pub(crate) timestamp: time::Duration,
+    pub(crate) status: PayloadStatus,
+    pub(crate) lost_before: u64,
 }
 
 impl Payload {
@@ -92,6 +114,30 @@ impl Payload {
         self.timestamp
     }
 
+    /// Returns [`PayloadStatus`] of the payload.
+    ///
+    /// The status is always [`PayloadStatus::Complete`] unless delivery of incomplete payloads is
+    /// enabled in the stream handle. For an incomplete payload, [`Self::payload`] contains only the
+    /// bytes actually received, and the height in [`ImageInfo`] is the actual height reported by
+    /// the device.
+    pub fn status(&self) -> PayloadStatus {
+        self.status
+    }
+
+    /// Returns `true` if the payload is received completely.
+    pub fn is_complete(&self) -> bool {
+        self.status == PayloadStatus::Complete
+    }
+
+    /// Returns the number of payloads that the device sent between the previously delivered
+    /// `payload` and this one, but that never reached the receiver.
+    ///
+    /// This counts gaps in [`Self::id`] sequence as well as payloads discarded because they were
+    /// broken or the receiver was full, so `0` means no payload was lost in between.
+    pub fn lost_before(&self) -> u64 {
+        self.lost_before
+    }
+
     /// Returns the payload as `Vec<u8>`.
     pub fn into_vec(mut self) -> Vec<u8> {
         self.payload.resize(self.valid_payload_size, 0);
@@ -100,13 +146,16 @@ impl Payload {
 }
 
 /// An Receiver of the `Payload` which is sent from a device.
+///
+/// The receiver also implements [`Stream`], which ends when the streaming is stopped.
 #[derive(Debug, Clone)]
 pub struct PayloadReceiver {
     /// Sends back `payload` to the device for reusing it.
     tx: Sender<Payload>,
 
     /// Receives `payload` from the device.
-    rx: Receiver<StreamResult<Payload>>,
+    /// Boxed so that the receiver can be polled as [`Stream`] without pinning.
+    rx: Pin<Box<Receiver<StreamResult<Payload>>>>,
 }
 
 impl PayloadReceiver {
@@ -137,32 +186,141 @@ impl PayloadReceiver {
     }
 }
 
+impl Stream for PayloadReceiver {
+    type Item = StreamResult<Payload>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.as_mut().poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rx.size_hint()
+    }
+}
+
 /// A sender of the [`Payload`] which is sent to the host.
 #[derive(Debug, Clone)]
 pub struct PayloadSender {
-    /// Receives from the device.
-    tx: Sender<StreamResult<Payload>>,
-    /// Sends back payload to reuse it.
-    rx: Receiver<Payload>,
+    inner: SenderInner,
+    /// Called with each error before the error is sent.
+    on_error: Option<ErrorHook>,
+}
+
+#[derive(Clone)]
+struct ErrorHook(Arc<dyn Fn(&StreamError) + Send + Sync>);
+
+impl fmt::Debug for ErrorHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ErrorHook").finish_non_exhaustive()
+    }
+}
+
+/// A callback which [`PayloadSender`] passes payloads to.
+type PayloadCallback = dyn FnMut(StreamResult<&Payload>) + Send;
+
+#[derive(Clone)]
+enum SenderInner {
+    /// Sends payloads to [`PayloadReceiver`].
+    Channel {
+        /// Receives from the device.
+        tx: Sender<StreamResult<Payload>>,
+        /// Sends back payload to reuse it.
+        rx: Receiver<Payload>,
+    },
+
+    /// Passes payloads to the callback on the caller's thread.
+    Callback {
+        f: Arc<Mutex<PayloadCallback>>,
+        /// The payload passed to the callback most recently, kept to reuse its buffer.
+        recycled: Arc<Mutex<Option<Payload>>>,
+    },
 }
 
 impl PayloadSender {
+    /// Returns a sender which calls `hook` with each error before sending it.
+    ///
+    /// `hook` is called even if the error can't be sent, e.g. because the channel is full.
+    pub(crate) fn inspect_errors<F>(self, hook: F) -> Self
+    where
+        F: Fn(&StreamError) + Send + Sync + 'static,
+    {
+        Self {
+            on_error: Some(ErrorHook(Arc::new(hook))),
+            ..self
+        }
+    }
+
+    fn call_error_hook(&self, payload: &StreamResult<Payload>) {
+        if let (Err(err), Some(hook)) = (payload, &self.on_error) {
+            (hook.0)(err);
+        }
+    }
+
     /// Sends [`Payload`] to the host.
     pub async fn send(&self, payload: StreamResult<Payload>) -> StreamResult<()> {
-        Ok(self.tx.send(payload).await?)
+        self.call_error_hook(&payload);
+        match &self.inner {
+            SenderInner::Channel { tx, .. } => Ok(tx.send(payload).await?),
+            SenderInner::Callback { .. } => self.try_send_inner(payload),
+        }
     }
 
     /// Tries to send [`Payload`] to the host.
     /// Returns `StreamError` if the channel is full or empty.
+    ///
+    /// If the sender is created by [`callback`], this method calls the callback and returns
+    /// after the callback returns.
     pub fn try_send(&self, payload: StreamResult<Payload>) -> StreamResult<()> {
-        Ok(self.tx.try_send(payload)?)
+        self.call_error_hook(&payload);
+        self.try_send_inner(payload)
+    }
+
+    fn try_send_inner(&self, payload: StreamResult<Payload>) -> StreamResult<()> {
+        match &self.inner {
+            SenderInner::Channel { tx, .. } => Ok(tx.try_send(payload)?),
+            SenderInner::Callback { f, recycled } => {
+                let mut f = f
+                    .lock()
+                    .map_err(|e| StreamError::Poisoned(e.to_string().into()))?;
+                match payload {
+                    Ok(payload) => {
+                        f(Ok(&payload));
+                        if let Ok(mut recycled) = recycled.lock() {
+                            *recycled = Some(payload);
+                        }
+                    }
+                    Err(err) => f(Err(err)),
+                }
+                Ok(())
+            }
+        }
     }
 
     /// Tries to receive [`Payload`].
     /// This method doesn't wait arrival of `payload` and immediately returns `StreamError` if
     /// the channel is empty.
     pub fn try_recv(&self) -> StreamResult<Payload> {
-        Ok(self.rx.try_recv()?)
+        match &self.inner {
+            SenderInner::Channel { rx, .. } => Ok(rx.try_recv()?),
+            SenderInner::Callback { recycled, .. } => recycled
+                .lock()
+                .map_err(|e| StreamError::Poisoned(e.to_string().into()))?
+                .take()
+                .ok_or_else(|| StreamError::ReceiveError("no payload to reuse".into())),
+        }
+    }
+}
+
+impl fmt::Debug for SenderInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Channel { tx, rx } => f
+                .debug_struct("Channel")
+                .field("tx", tx)
+                .field("rx", rx)
+                .finish(),
+            Self::Callback { .. } => f.debug_struct("Callback").finish_non_exhaustive(),
+        }
     }
 }
 
@@ -172,16 +330,37 @@ pub fn channel(payload_cap: usize, buffer_cap: usize) -> (PayloadSender, Payload
     let (host_tx, device_rx) = async_channel::bounded(buffer_cap);
     (
         PayloadSender {
-            tx: device_tx,
-            rx: device_rx,
+            inner: SenderInner::Channel {
+                tx: device_tx,
+                rx: device_rx,
+            },
+            on_error: None,
         },
         PayloadReceiver {
             tx: host_tx,
-            rx: host_rx,
+            rx: Box::pin(host_rx),
         },
     )
 }
 
+/// Creates [`PayloadSender`] which passes [`Payload`] to `f` instead of sending it through a
+/// channel.
+///
+/// `f` is called on the thread that sends the payload, and the payload buffer is reused once `f`
+/// returns.
+pub fn callback<F>(f: F) -> PayloadSender
+where
+    F: FnMut(StreamResult<&Payload>) + Send + 'static,
+{
+    PayloadSender {
+        inner: SenderInner::Callback {
+            f: Arc::new(Mutex::new(f)),
+            recycled: Arc::default(),
+        },
+        on_error: None,
+    }
+}
+
 impl From<async_channel::RecvError> for StreamError {
     fn from(err: async_channel::RecvError) -> Self {
         StreamError::ReceiveError(err.to_string().into())
@@ -205,3 +384,61 @@ impl<T> From<async_channel::TrySendError<T>> for StreamError {
         StreamError::ReceiveError(err.to_string().into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(id: u64, buf: Vec<u8>) -> Payload {
+        Payload {
+            id,
+            payload_type: PayloadType::Chunk,
+            image_info: None,
+            valid_payload_size: buf.len(),
+            payload: buf,
+            timestamp: time::Duration::default(),
+            status: PayloadStatus::Complete,
+            lost_before: 0,
+        }
+    }
+
+    #[test]
+    fn test_callback() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let sender = {
+            let received = received.clone();
+            callback(move |payload: StreamResult<&Payload>| {
+                let id = payload.map(Payload::id).ok();
+                received.lock().unwrap().push(id);
+            })
+        };
+        // No payload has been passed to the callback yet.
+        assert!(sender.try_recv().is_err());
+
+        sender.try_send(Ok(payload(0, vec![1, 2, 3]))).unwrap();
+        sender.try_send(Err(StreamError::Timeout)).unwrap();
+        assert_eq!(*received.lock().unwrap(), [Some(0), None]);
+
+        // The buffer of the payload is recycled once the callback returns.
+        let recycled = sender.try_recv().unwrap();
+        assert_eq!(recycled.id(), 0);
+        assert_eq!(recycled.into_vec(), [1, 2, 3]);
+        assert!(sender.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_inspect_errors() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (sender, receiver) = channel(1, 1);
+        let sender = sender.inspect_errors(move |err| tx.send(err.to_string()).unwrap());
+
+        sender.try_send(Ok(payload(0, vec![]))).unwrap();
+        // The error is inspected even though the channel is full.
+        assert!(sender.try_send(Err(StreamError::Disconnected)).is_err());
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            [StreamError::Disconnected.to_string()]
+        );
+        assert!(receiver.try_recv().is_ok());
+    }
+}
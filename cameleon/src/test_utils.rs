@@ -0,0 +1,261 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Fake device handles shared by unit tests.
+
+use std::convert::TryInto;
+
+use crate::{
+    genapi::{DefaultGenApiCtxt, FromXml, NotifyingGenApiCtxt, ParamsCtxt},
+    payload::PayloadSender,
+    Camera, CameraInfo, ControlError, ControlResult, DeviceControl, PayloadStream, StreamResult,
+};
+
+/// Nodes which [`Camera`] requires to start and stop streaming.
+pub(crate) const STREAMING_NODES: &str = r#"
+    <Integer Name="TLParamsLocked">
+        <Value>0</Value>
+    </Integer>
+    <Command Name="AcquisitionStart">
+        <Value>0</Value>
+        <CommandValue>1</CommandValue>
+    </Command>
+    <Command Name="AcquisitionStop">
+        <Value>0</Value>
+        <CommandValue>1</CommandValue>
+    </Command>
+"#;
+
+/// Wraps `nodes` with `RegisterDescription`.
+pub(crate) fn genapi_xml(nodes: &str) -> String {
+    format!(
+        r#"<RegisterDescription
+            ModelName="CameleonModel"
+            VendorName="CameleonVendor"
+            StandardNameSpace="None"
+            SchemaMajorVersion="1"
+            SchemaMinorVersion="1"
+            SchemaSubMinorVersion="0"
+            MajorVersion="1"
+            MinorVersion="0"
+            SubMinorVersion="0"
+            ProductGuid="01234567-0123-0123-0123-0123456789ab"
+            VersionGuid="76543210-3210-3210-3210-ba9876543210"
+            xmlns="http://www.genicam.org/GenApi/Version_1_0">
+            {}
+        </RegisterDescription>"#,
+        nodes
+    )
+}
+
+/// A device control which serves `xml` and has `memory` as its registers.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct FakeCtrl {
+    pub(crate) opened: bool,
+    pub(crate) streaming_enabled: bool,
+    pub(crate) xml: String,
+    pub(crate) memory: Vec<u8>,
+}
+
+impl FakeCtrl {
+    pub(crate) fn new(nodes: &str) -> Self {
+        Self {
+            xml: genapi_xml(nodes),
+            memory: vec![0; 0x1000],
+            ..Self::default()
+        }
+    }
+
+    fn range(&self, address: u64, len: usize) -> ControlResult<std::ops::Range<usize>> {
+        let start: usize = address
+            .try_into()
+            .map_err(|_| ControlError::InvalidData("address out of range".into()))?;
+        if start + len > self.memory.len() {
+            return Err(ControlError::InvalidData("address out of range".into()));
+        }
+        Ok(start..start + len)
+    }
+}
+
+/// Address of `SBRM` in [`FakeCtrl::u3v`].
+#[cfg(feature = "libusb")]
+pub(crate) const SBRM_ADDRESS: u64 = 0x400;
+/// Address of `SIRM` of the first stream channel in [`FakeCtrl::u3v`].
+#[cfg(feature = "libusb")]
+pub(crate) const SIRM_ADDRESS: u64 = 0x800;
+/// Length of each `SIRM` in [`FakeCtrl::u3v`].
+#[cfg(feature = "libusb")]
+pub(crate) const SIRM_LENGTH: u32 = 0x40;
+
+#[cfg(feature = "libusb")]
+impl FakeCtrl {
+    /// Returns an opened control of a U3V device which has `ABRM`, `SBRM` and `SIRM`s of
+    /// `stream_channels` stream channels.
+    ///
+    /// Each stream channel requires a 4096 bytes payload aligned to 64 bytes.
+    pub(crate) fn u3v(stream_channels: u32) -> Self {
+        use cameleon_device::u3v::register_map::{abrm, sbrm, sirm};
+
+        let mut ctrl = Self {
+            opened: true,
+            memory: vec![0; 0x1000],
+            ..Self::default()
+        };
+        ctrl.write_le(abrm::SBRM_ADDRESS.0, &SBRM_ADDRESS.to_le_bytes());
+        // Only `SIRM` is available.
+        ctrl.write_le(
+            SBRM_ADDRESS + sbrm::U3VCP_CAPABILITY_REGISTER.0,
+            &1_u64.to_le_bytes(),
+        );
+        ctrl.write_le(
+            SBRM_ADDRESS + sbrm::NUMBER_OF_STREAM_CHANNELS.0,
+            &stream_channels.to_le_bytes(),
+        );
+        ctrl.write_le(
+            SBRM_ADDRESS + sbrm::SIRM_ADDRESS.0,
+            &SIRM_ADDRESS.to_le_bytes(),
+        );
+        ctrl.write_le(
+            SBRM_ADDRESS + sbrm::SIRM_LENGTH.0,
+            &SIRM_LENGTH.to_le_bytes(),
+        );
+        for index in 0..stream_channels {
+            let sirm_address = SIRM_ADDRESS + u64::from(index * SIRM_LENGTH);
+            // The alignment is `2^6` bytes.
+            ctrl.write_le(sirm_address + sirm::SI_INFO.0, &(6_u32 << 24).to_le_bytes());
+            ctrl.write_le(
+                sirm_address + sirm::REQUIRED_PAYLOAD_SIZE.0,
+                &4096_u64.to_le_bytes(),
+            );
+            ctrl.write_le(
+                sirm_address + sirm::REQUIRED_LEADER_SIZE.0,
+                &52_u32.to_le_bytes(),
+            );
+            ctrl.write_le(
+                sirm_address + sirm::REQUIRED_TRAILER_SIZE.0,
+                &32_u32.to_le_bytes(),
+            );
+        }
+        ctrl
+    }
+
+    /// Writes `bytes` to `address` of the memory.
+    pub(crate) fn write_le(&mut self, address: u64, bytes: &[u8]) {
+        let range = self.range(address, bytes.len()).unwrap();
+        self.memory[range].copy_from_slice(bytes);
+    }
+
+    /// Reads a `u32` at `address` of the memory.
+    pub(crate) fn read_u32(&self, address: u64) -> u32 {
+        let range = self.range(address, 4).unwrap();
+        u32::from_le_bytes(self.memory[range].try_into().unwrap())
+    }
+}
+
+impl DeviceControl for FakeCtrl {
+    fn open(&mut self) -> ControlResult<()> {
+        self.opened = true;
+        Ok(())
+    }
+    fn close(&mut self) -> ControlResult<()> {
+        self.opened = false;
+        Ok(())
+    }
+    fn is_opened(&self) -> bool {
+        self.opened
+    }
+    fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+        let range = self.range(address, buf.len())?;
+        buf.copy_from_slice(&self.memory[range]);
+        Ok(())
+    }
+    fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+        let range = self.range(address, data.len())?;
+        self.memory[range].copy_from_slice(data);
+        Ok(())
+    }
+    fn genapi(&mut self) -> ControlResult<String> {
+        if self.opened {
+            Ok(self.xml.clone())
+        } else {
+            Err(ControlError::NotOpened)
+        }
+    }
+    fn enable_streaming(&mut self) -> ControlResult<()> {
+        self.streaming_enabled = true;
+        Ok(())
+    }
+    fn disable_streaming(&mut self) -> ControlResult<()> {
+        self.streaming_enabled = false;
+        Ok(())
+    }
+}
+
+/// A payload stream which never sends payloads.
+#[derive(Debug, Default)]
+pub(crate) struct FakeStrm {
+    pub(crate) opened: bool,
+    pub(crate) running: bool,
+}
+
+impl PayloadStream for FakeStrm {
+    fn open(&mut self) -> StreamResult<()> {
+        self.opened = true;
+        Ok(())
+    }
+    fn close(&mut self) -> StreamResult<()> {
+        self.opened = false;
+        Ok(())
+    }
+    fn start_streaming_loop(
+        &mut self,
+        _: PayloadSender,
+        _: &mut dyn DeviceControl,
+    ) -> StreamResult<()> {
+        self.running = true;
+        Ok(())
+    }
+    fn stop_streaming_loop(&mut self) -> StreamResult<()> {
+        self.running = false;
+        Ok(())
+    }
+    fn is_loop_running(&self) -> bool {
+        self.running
+    }
+}
+
+pub(crate) fn camera_info() -> CameraInfo {
+    CameraInfo {
+        vendor_name: "vendor".into(),
+        model_name: "model".into(),
+        serial_number: "serial".into(),
+    }
+}
+
+/// Returns a closed camera whose `GenApi` XML contains `nodes`.
+pub(crate) fn camera(nodes: &str) -> Camera<FakeCtrl, FakeStrm> {
+    Camera::new(
+        FakeCtrl::new(nodes),
+        FakeStrm::default(),
+        None,
+        camera_info(),
+    )
+}
+
+/// Returns a parameter context of an opened device whose `GenApi` XML contains `nodes`.
+pub(crate) fn params_ctxt(nodes: &str) -> ParamsCtxt<FakeCtrl, DefaultGenApiCtxt> {
+    let ctrl = FakeCtrl {
+        opened: true,
+        ..FakeCtrl::new(nodes)
+    };
+    let ctxt = DefaultGenApiCtxt::from_xml(&ctrl.xml).unwrap();
+    ParamsCtxt { ctrl, ctxt }
+}
+
+/// Same as [`params_ctxt`], but the context supports change notifications.
+pub(crate) fn notifying_params_ctxt(
+    nodes: &str,
+) -> ParamsCtxt<FakeCtrl, NotifyingGenApiCtxt<DefaultGenApiCtxt>> {
+    ParamsCtxt::convert_from(params_ctxt(nodes))
+}
@@ -0,0 +1,457 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains [`TypedCamera`], a wrapper of [`Camera`] that tracks the state of the
+//! camera in its type.
+//!
+//! [`Camera`] checks its state at runtime, e.g. [`Camera::params_ctxt`] returns
+//! [`CameleonError::GenApiContextMissing`] if it's called before [`Camera::load_context`].
+//! [`TypedCamera`] moves the state to the type parameter, so that each method exists only in the
+//! states where it's valid.
+//!
+//! ```text
+//!          open             load_context / set_context      start_streaming
+//! Closed --------> Opened ------------------------------> Configured --------> Streaming
+//!    ^               |                                     |     ^                 |
+//!    +--- close -----+------------- close -----------------+     +- stop_streaming -+
+//! ```
+//!
+//! Unlike [`Camera`], [`TypedCamera`] stops streaming and closes the camera automatically when
+//! it's dropped.
+//!
+//! A failed transition returns [`StateError`] which holds the camera in the original state, so
+//! that the transition can be retried.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use cameleon::{u3v, TypedCamera};
+//!
+//! let camera = u3v::enumerate_cameras().unwrap().pop().unwrap();
+//! let camera = TypedCamera::new(camera).open().unwrap();
+//! let mut camera = camera.load_context().unwrap();
+//!
+//! // `params_ctxt` can't fail, the context is always loaded in `Configured` state.
+//! let mut params_ctxt = camera.params_ctxt();
+//! let gain = params_ctxt.node("Gain").unwrap().as_float(&params_ctxt).unwrap();
+//! gain.set_value(&mut params_ctxt, 1.0).unwrap();
+//!
+//! let (camera, payload_rx) = camera.start_streaming(3).unwrap();
+//! let payload = payload_rx.recv_blocking().unwrap();
+//!
+//! // Streaming is stopped and the camera is closed when `camera` is dropped.
+//! drop(camera);
+//! ```
+
+use std::{fmt, marker::PhantomData};
+
+use tracing::error;
+
+use super::{
+    camera::{Camera, CameraInfo, DeviceControl, PayloadStream},
+    genapi::{DefaultGenApiCtxt, FromXml, GenApiCtxt, ParamsCtxt},
+    payload::{Payload, PayloadReceiver},
+    CameleonError, CameleonResult, StreamResult,
+};
+
+/// States of [`TypedCamera`].
+pub mod state {
+    /// The camera is closed.
+    #[derive(Debug)]
+    pub enum Closed {}
+
+    /// The camera is opened, but `GenApi` context isn't loaded yet.
+    #[derive(Debug)]
+    pub enum Opened {}
+
+    /// The camera is opened and `GenApi` context is loaded.
+    #[derive(Debug)]
+    pub enum Configured {}
+
+    /// The camera is streaming.
+    #[derive(Debug)]
+    pub enum Streaming {}
+
+    /// A state of [`TypedCamera`](super::TypedCamera). This trait is sealed.
+    pub trait CameraState: private::Sealed {
+        /// `true` if the camera is opened in the state.
+        const IS_OPENED: bool;
+    }
+
+    /// States where the camera is opened.
+    pub trait OpenedState: CameraState {}
+
+    /// States where `GenApi` context is loaded.
+    pub trait ConfiguredState: OpenedState {}
+
+    impl CameraState for Closed {
+        const IS_OPENED: bool = false;
+    }
+    impl CameraState for Opened {
+        const IS_OPENED: bool = true;
+    }
+    impl CameraState for Configured {
+        const IS_OPENED: bool = true;
+    }
+    impl CameraState for Streaming {
+        const IS_OPENED: bool = true;
+    }
+
+    impl OpenedState for Opened {}
+    impl OpenedState for Configured {}
+    impl OpenedState for Streaming {}
+
+    impl ConfiguredState for Configured {}
+    impl ConfiguredState for Streaming {}
+
+    mod private {
+        pub trait Sealed {}
+        impl Sealed for super::Closed {}
+        impl Sealed for super::Opened {}
+        impl Sealed for super::Configured {}
+        impl Sealed for super::Streaming {}
+    }
+}
+
+use state::{CameraState, Closed, Configured, ConfiguredState, Opened, OpenedState, Streaming};
+
+/// A wrapper of [`Camera`] whose state `S` is tracked in its type.
+///
+/// See the [module level documentation](self) for details.
+pub struct TypedCamera<S, Ctrl, Strm, Ctxt = DefaultGenApiCtxt>
+where
+    S: CameraState,
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+    Ctxt: GenApiCtxt,
+{
+    /// Always `Some` except while the camera is moved to the next state.
+    camera: Option<Camera<Ctrl, Strm, Ctxt>>,
+    _state: PhantomData<S>,
+}
+
+/// An error returned when a state transition of [`TypedCamera`] fails.
+///
+/// Holds the camera in the state before the transition.
+pub struct StateError<T> {
+    /// The camera in the state before the transition.
+    pub camera: T,
+    /// The cause of the failure.
+    pub error: CameleonError,
+}
+
+impl<T> StateError<T> {
+    /// Returns the camera and the error.
+    pub fn into_parts(self) -> (T, CameleonError) {
+        (self.camera, self.error)
+    }
+}
+
+impl<T> fmt::Debug for StateError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StateError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for StateError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<T> std::error::Error for StateError<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<T> From<StateError<T>> for CameleonError {
+    fn from(err: StateError<T>) -> Self {
+        err.error
+    }
+}
+
+/// A result of a state transition of [`TypedCamera`].
+pub type StateResult<T, U> = std::result::Result<T, StateError<U>>;
+
+impl<S, Ctrl, Strm, Ctxt> TypedCamera<S, Ctrl, Strm, Ctxt>
+where
+    S: CameraState,
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+    Ctxt: GenApiCtxt,
+{
+    /// Returns basic information of the camera.
+    pub fn info(&self) -> &CameraInfo {
+        self.camera().info()
+    }
+
+    /// Returns the wrapped camera.
+    pub fn camera(&self) -> &Camera<Ctrl, Strm, Ctxt> {
+        self.camera.as_ref().unwrap()
+    }
+
+    /// Returns the wrapped camera without closing it.
+    pub fn into_inner(mut self) -> Camera<Ctrl, Strm, Ctxt> {
+        self.camera.take().unwrap()
+    }
+
+    fn from_camera(camera: Camera<Ctrl, Strm, Ctxt>) -> Self {
+        Self {
+            camera: Some(camera),
+            _state: PhantomData,
+        }
+    }
+
+    fn camera_mut(&mut self) -> &mut Camera<Ctrl, Strm, Ctxt> {
+        self.camera.as_mut().unwrap()
+    }
+
+    /// Moves the camera to the state `T` if `f` succeeds, otherwise returns the camera in the
+    /// current state.
+    #[allow(clippy::type_complexity)]
+    fn transition<T, F, R>(
+        mut self,
+        f: F,
+    ) -> StateResult<(TypedCamera<T, Ctrl, Strm, Ctxt>, R), Self>
+    where
+        T: CameraState,
+        F: FnOnce(&mut Camera<Ctrl, Strm, Ctxt>) -> CameleonResult<R>,
+    {
+        match f(self.camera_mut()) {
+            Ok(r) => Ok((TypedCamera::from_camera(self.into_inner()), r)),
+            Err(error) => Err(StateError {
+                camera: self,
+                error,
+            }),
+        }
+    }
+}
+
+impl<Ctrl, Strm, Ctxt> TypedCamera<Closed, Ctrl, Strm, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+    Ctxt: GenApiCtxt,
+{
+    /// Wraps a closed camera.
+    pub fn new(camera: Camera<Ctrl, Strm, Ctxt>) -> Self {
+        Self::from_camera(camera)
+    }
+
+    /// Opens the camera. See [`Camera::open`].
+    pub fn open(self) -> StateResult<TypedCamera<Opened, Ctrl, Strm, Ctxt>, Self> {
+        self.transition(Camera::open).map(|(camera, ())| camera)
+    }
+}
+
+impl<Ctrl, Strm, Ctxt> From<Camera<Ctrl, Strm, Ctxt>> for TypedCamera<Closed, Ctrl, Strm, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+    Ctxt: GenApiCtxt,
+{
+    fn from(camera: Camera<Ctrl, Strm, Ctxt>) -> Self {
+        Self::new(camera)
+    }
+}
+
+impl<S, Ctrl, Strm, Ctxt> TypedCamera<S, Ctrl, Strm, Ctxt>
+where
+    S: OpenedState,
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+    Ctxt: GenApiCtxt,
+{
+    /// Returns the device control handle of the camera.
+    pub fn ctrl(&mut self) -> &mut Ctrl {
+        &mut self.camera_mut().ctrl
+    }
+
+    /// Closes the camera, streaming is stopped before closing. See [`Camera::close`].
+    pub fn close(self) -> StateResult<TypedCamera<Closed, Ctrl, Strm, Ctxt>, Self> {
+        self.transition(Camera::close).map(|(camera, ())| camera)
+    }
+}
+
+impl<Ctrl, Strm, Ctxt> TypedCamera<Opened, Ctrl, Strm, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+    Ctxt: GenApiCtxt,
+{
+    /// Loads `GenApi` context from the camera. See [`Camera::load_context`].
+    pub fn load_context(self) -> StateResult<TypedCamera<Configured, Ctrl, Strm, Ctxt>, Self>
+    where
+        Ctxt: FromXml,
+    {
+        self.transition(Camera::load_context)
+            .map(|(camera, _)| camera)
+    }
+
+    /// Sets `GenApi` context to the camera.
+    pub fn set_context(mut self, ctxt: Ctxt) -> TypedCamera<Configured, Ctrl, Strm, Ctxt> {
+        self.camera_mut().ctxt = Some(ctxt);
+        TypedCamera::from_camera(self.into_inner())
+    }
+}
+
+impl<S, Ctrl, Strm, Ctxt> TypedCamera<S, Ctrl, Strm, Ctxt>
+where
+    S: ConfiguredState,
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+    Ctxt: GenApiCtxt,
+{
+    /// Returns the context of the camera params. See [`Camera::params_ctxt`].
+    pub fn params_ctxt(&mut self) -> ParamsCtxt<&mut Ctrl, &mut Ctxt> {
+        let camera = self.camera_mut();
+        ParamsCtxt {
+            ctrl: &mut camera.ctrl,
+            ctxt: camera
+                .ctxt
+                .as_mut()
+                .expect("`GenApi` context is always loaded in this state"),
+        }
+    }
+}
+
+impl<Ctrl, Strm, Ctxt> TypedCamera<Configured, Ctrl, Strm, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+    Ctxt: GenApiCtxt,
+{
+    /// Starts streaming and returns the receiver for the `Payload`. See
+    /// [`Camera::start_streaming`].
+    #[allow(clippy::type_complexity)]
+    pub fn start_streaming(
+        self,
+        cap: usize,
+    ) -> StateResult<(TypedCamera<Streaming, Ctrl, Strm, Ctxt>, PayloadReceiver), Self> {
+        self.transition(|camera| camera.start_streaming(cap))
+    }
+
+    /// Starts streaming and passes each `Payload` to `f`. See [`Camera::start_streaming_with`].
+    pub fn start_streaming_with<F>(
+        self,
+        f: F,
+    ) -> StateResult<TypedCamera<Streaming, Ctrl, Strm, Ctxt>, Self>
+    where
+        F: FnMut(StreamResult<&Payload>) + Send + 'static,
+    {
+        self.transition(|camera| camera.start_streaming_with(f))
+            .map(|(camera, ())| camera)
+    }
+}
+
+impl<Ctrl, Strm, Ctxt> TypedCamera<Streaming, Ctrl, Strm, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+    Ctxt: GenApiCtxt,
+{
+    /// Stops the streaming. See [`Camera::stop_streaming`].
+    pub fn stop_streaming(self) -> StateResult<TypedCamera<Configured, Ctrl, Strm, Ctxt>, Self> {
+        self.transition(Camera::stop_streaming)
+            .map(|(camera, ())| camera)
+    }
+}
+
+impl<S, Ctrl, Strm, Ctxt> Drop for TypedCamera<S, Ctrl, Strm, Ctxt>
+where
+    S: CameraState,
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+    Ctxt: GenApiCtxt,
+{
+    fn drop(&mut self) {
+        if let Some(camera) = &mut self.camera {
+            if S::IS_OPENED {
+                if let Err(e) = camera.close() {
+                    error!(?e, "failed to close the camera on drop");
+                }
+            }
+        }
+    }
+}
+
+impl<S, Ctrl, Strm, Ctxt> fmt::Debug for TypedCamera<S, Ctrl, Strm, Ctxt>
+where
+    S: CameraState + fmt::Debug,
+    Ctrl: DeviceControl + fmt::Debug,
+    Strm: PayloadStream + fmt::Debug,
+    Ctxt: GenApiCtxt + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TypedCamera")
+            .field("state", &std::any::type_name::<S>())
+            .field("camera", &self.camera)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{self, FakeCtrl, FakeStrm, STREAMING_NODES};
+
+    type Typed<S> = TypedCamera<S, FakeCtrl, FakeStrm>;
+
+    #[test]
+    fn test_transitions() {
+        let camera: Typed<Closed> = test_utils::camera(STREAMING_NODES).into();
+
+        let camera = camera.open().unwrap();
+        assert!(camera.camera().ctrl.opened);
+
+        let mut camera = camera.load_context().unwrap();
+        let ctxt = camera.params_ctxt();
+        assert!(ctxt.node("TLParamsLocked").is_some());
+
+        let (camera, _payload_rx) = camera.start_streaming(1).unwrap();
+        assert!(camera.camera().strm.running);
+
+        let camera = camera.stop_streaming().unwrap();
+        assert!(!camera.camera().strm.running);
+
+        let camera = camera.close().unwrap();
+        assert!(!camera.camera().ctrl.opened);
+    }
+
+    #[test]
+    fn test_failed_transition_keeps_state() {
+        let mut camera = test_utils::camera("");
+        camera.ctrl.xml = "broken".into();
+        let camera = Typed::new(camera).open().unwrap();
+
+        let err = camera.load_context().unwrap_err();
+        let (camera, _): (Typed<Opened>, _) = err.into_parts();
+        assert!(camera.camera().ctrl.opened);
+    }
+
+    #[test]
+    fn test_drop() {
+        let mut ctrl = FakeCtrl::new(STREAMING_NODES);
+        let mut strm = FakeStrm::default();
+        {
+            let camera: Camera<_, _> =
+                Camera::new(&mut ctrl, &mut strm, None, test_utils::camera_info());
+            let camera = TypedCamera::new(camera).open().unwrap();
+            let (_camera, _payload_rx) = camera.load_context().unwrap().start_streaming(1).unwrap();
+        }
+        // Dropping stops streaming and closes the camera.
+        assert!(!ctrl.opened);
+        assert!(!strm.running);
+        assert!(!strm.opened);
+
+        // `into_inner` leaves the camera as is.
+        let camera = Typed::new(test_utils::camera(STREAMING_NODES));
+        let camera = camera.open().unwrap().into_inner();
+        assert!(camera.ctrl.opened);
+    }
+}
@@ -0,0 +1,520 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains USB bandwidth allocation for multiple `U3V` cameras.
+//!
+//! Cameras connected to the same host controller share its bandwidth. [`allocate_bandwidth`]
+//! groups cameras by their host controller, computes the throughput each camera requires from
+//! `PayloadSize` and the frame rate, and then limits the throughput of each camera with
+//! `DeviceLinkThroughputLimitMode` and `DeviceLinkThroughputLimit`.
+//!
+//! # Examples
+//! ```no_run
+//! use cameleon::u3v;
+//!
+//! let mut cameras = u3v::enumerate_cameras().unwrap();
+//! for camera in &mut cameras {
+//!     camera.open().unwrap();
+//!     camera.load_context().unwrap();
+//! }
+//!
+//! let report = u3v::bandwidth::allocate_bandwidth(&mut cameras, &Default::default()).unwrap();
+//! if report.is_oversubscribed() {
+//!     println!("frame rates can't be achieved: {:?}", report);
+//! }
+//! ```
+
+use std::{collections::BTreeMap, convert::TryFrom, fs, path::Path};
+
+use cameleon_device::u3v::BusSpeed;
+use tracing::warn;
+
+use crate::{
+    genapi::{EnumerationNode, GenApiCtxt, GenApiError, IntegerNode, ParamsCtxt},
+    CameleonError, CameleonResult, Camera, CameraInfo, DeviceControl,
+};
+
+use super::{ControlHandle, StreamHandle};
+
+/// Configuration of [`allocate_bandwidth`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandwidthConfig {
+    /// Ratio of throughput added to what each camera requires, to absorb protocol overhead and
+    /// jitter, e.g. `0.1` means 10%.
+    pub margin: f64,
+
+    /// Usable bandwidth of a host controller in bytes per second.
+    ///
+    /// If `None`, it's estimated from the bus speed of the connected cameras. See
+    /// [`usable_bandwidth`].
+    pub capacity: Option<u64>,
+}
+
+impl Default for BandwidthConfig {
+    fn default() -> Self {
+        Self {
+            margin: 0.1,
+            capacity: None,
+        }
+    }
+}
+
+/// Throughput requirement of a camera, input of [`BandwidthPlan::new`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandwidthDemand {
+    /// Identifier of the host controller that the camera is connected to.
+    pub controller: String,
+    /// Bus speed that the camera is connected with.
+    pub speed: Option<BusSpeed>,
+    /// Required throughput in bytes per second, including the margin.
+    pub required: u64,
+}
+
+/// Allocation of a host controller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerAllocation {
+    /// Identifier of the host controller.
+    ///
+    /// This is the sysfs path of the controller on Linux, and the bus number otherwise.
+    pub controller: String,
+    /// Usable bandwidth of the controller in bytes per second.
+    pub capacity: u64,
+    /// Sum of throughput required by the cameras on the controller.
+    pub required: u64,
+    /// Indices of the cameras on the controller.
+    pub cameras: Vec<usize>,
+}
+
+impl ControllerAllocation {
+    /// Returns `true` if the cameras require more throughput than the controller provides.
+    #[must_use]
+    pub fn is_oversubscribed(&self) -> bool {
+        self.required > self.capacity
+    }
+}
+
+/// Allocation of a camera.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraAllocation {
+    /// Information of the camera.
+    pub info: CameraInfo,
+    /// Required throughput in bytes per second, including the margin.
+    pub required: u64,
+    /// Throughput limit allocated to the camera in bytes per second.
+    pub limit: u64,
+}
+
+/// Result of bandwidth allocation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BandwidthReport {
+    /// Allocations of each host controller.
+    pub controllers: Vec<ControllerAllocation>,
+    /// Allocations of each camera, in the same order as the cameras passed.
+    pub cameras: Vec<CameraAllocation>,
+}
+
+impl BandwidthReport {
+    /// Returns `true` if any host controller is oversubscribed.
+    ///
+    /// In that case, each camera on the controller is limited proportionally to its requirement,
+    /// and the current frame rates can't be achieved.
+    #[must_use]
+    pub fn is_oversubscribed(&self) -> bool {
+        self.controllers
+            .iter()
+            .any(ControllerAllocation::is_oversubscribed)
+    }
+}
+
+/// Allocation computed from [`BandwidthDemand`]s, without any access to cameras.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BandwidthPlan {
+    /// Allocations of each host controller.
+    pub controllers: Vec<ControllerAllocation>,
+    /// Throughput limit of each camera in bytes per second, in the same order as the demands.
+    pub limits: Vec<u64>,
+}
+
+impl BandwidthPlan {
+    /// Computes the allocation.
+    ///
+    /// If a controller has headroom, it's split equally and added to the requirement of each
+    /// camera on the controller. Otherwise, the capacity is split proportionally to the
+    /// requirements.
+    #[must_use]
+    pub fn new(demands: &[BandwidthDemand], capacity: Option<u64>) -> Self {
+        let mut groups: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (i, demand) in demands.iter().enumerate() {
+            groups.entry(&demand.controller).or_default().push(i);
+        }
+
+        let mut limits = vec![0; demands.len()];
+        let controllers = groups
+            .into_iter()
+            .map(|(controller, cameras)| {
+                let capacity = capacity.unwrap_or_else(|| {
+                    cameras
+                        .iter()
+                        .filter_map(|&i| demands[i].speed)
+                        .map(usable_bandwidth)
+                        .max()
+                        .unwrap_or_else(|| usable_bandwidth(BusSpeed::SuperSpeed))
+                });
+                let required: u64 = cameras.iter().map(|&i| demands[i].required).sum();
+
+                if required <= capacity {
+                    let headroom = (capacity - required) / cameras.len() as u64;
+                    for &i in &cameras {
+                        limits[i] = demands[i].required + headroom;
+                    }
+                } else {
+                    for &i in &cameras {
+                        limits[i] = (u128::from(capacity) * u128::from(demands[i].required)
+                            / u128::from(required)) as u64;
+                    }
+                }
+
+                ControllerAllocation {
+                    controller: controller.to_string(),
+                    capacity,
+                    required,
+                    cameras,
+                }
+            })
+            .collect();
+
+        Self {
+            controllers,
+            limits,
+        }
+    }
+}
+
+/// Returns a practical usable bandwidth of `speed` in bytes per second.
+///
+/// These values are lower than the signaling rates because of encoding and protocol overhead.
+#[must_use]
+pub fn usable_bandwidth(speed: BusSpeed) -> u64 {
+    match speed {
+        BusSpeed::LowSpeed => 150_000,
+        BusSpeed::FullSpeed => 1_000_000,
+        BusSpeed::HighSpeed => 40_000_000,
+        BusSpeed::SuperSpeed => 380_000_000,
+        BusSpeed::SuperSpeedPlus => 900_000_000,
+    }
+}
+
+/// Groups `cameras` by their host controller, and sets `DeviceLinkThroughputLimit` of each
+/// camera so that the cameras on the same controller share its bandwidth.
+///
+/// The required throughput of each camera is computed from `PayloadSize` and
+/// `AcquisitionFrameRate` (or `AcquisitionResultingFrameRate`). Call this method after the
+/// cameras are configured and before streaming starts, then check
+/// [`BandwidthReport::is_oversubscribed`].
+///
+/// Every camera is validated before any limit is written, so a camera missing one of the nodes
+/// above, or whose `DeviceLinkThroughputLimitMode` can't be turned `On`, leaves all cameras
+/// unchanged. Only a failed write itself can leave earlier cameras already limited.
+///
+/// All cameras must be opened, and their contexts must be loaded.
+pub fn allocate_bandwidth<Ctxt: GenApiCtxt>(
+    cameras: &mut [Camera<ControlHandle, StreamHandle, Ctxt>],
+    config: &BandwidthConfig,
+) -> CameleonResult<BandwidthReport> {
+    let mut demands = Vec::with_capacity(cameras.len());
+    let mut limit_nodes = Vec::with_capacity(cameras.len());
+    for camera in cameras.iter_mut() {
+        let device_info = camera.ctrl.device_info().clone();
+        let mut ctxt = camera.params_ctxt()?;
+        let throughput = required_throughput(&mut ctxt)?;
+        limit_nodes.push(throughput_limit_nodes(&mut ctxt)?);
+        demands.push(BandwidthDemand {
+            controller: host_controller(Path::new("/"), device_info.bus_number),
+            speed: device_info.connected_speed,
+            required: (throughput * (1.0 + config.margin)).ceil() as u64,
+        });
+    }
+
+    let plan = BandwidthPlan::new(&demands, config.capacity);
+    let mut allocations = Vec::with_capacity(cameras.len());
+    for (((camera, demand), &limit), &nodes) in cameras
+        .iter_mut()
+        .zip(&demands)
+        .zip(&plan.limits)
+        .zip(&limit_nodes)
+    {
+        let info = camera.info().clone();
+        let mut ctxt = camera.params_ctxt()?;
+        let limit = set_throughput_limit(&mut ctxt, nodes, limit)?;
+        allocations.push(CameraAllocation {
+            info,
+            required: demand.required,
+            limit,
+        });
+    }
+
+    let report = BandwidthReport {
+        controllers: plan.controllers,
+        cameras: allocations,
+    };
+    for controller in report.controllers.iter().filter(|c| c.is_oversubscribed()) {
+        warn!(
+            controller = %controller.controller,
+            capacity = controller.capacity,
+            required = controller.required,
+            "host controller is oversubscribed"
+        );
+    }
+    Ok(report)
+}
+
+/// Returns `PayloadSize` times the frame rate in bytes per second.
+fn required_throughput<Ctrl: DeviceControl, Ctxt: GenApiCtxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+) -> CameleonResult<f64> {
+    let payload_size = ctxt
+        .node("PayloadSize")
+        .and_then(|node| node.as_integer(ctxt))
+        .ok_or_else(|| CameleonError::InvalidGenApiXml("missing PayloadSize".into()))?
+        .value(ctxt)?;
+
+    let frame_rate = ["AcquisitionFrameRate", "AcquisitionResultingFrameRate"]
+        .iter()
+        .find_map(|name| ctxt.node(name).and_then(|node| node.as_float(ctxt)))
+        .ok_or_else(|| CameleonError::InvalidGenApiXml("missing AcquisitionFrameRate".into()))?
+        .value(ctxt)?;
+
+    Ok(payload_size as f64 * frame_rate)
+}
+
+/// Returns `DeviceLinkThroughputLimitMode` and `DeviceLinkThroughputLimit` after checking that
+/// the mode is writable and has the `On` entry, without writing anything.
+fn throughput_limit_nodes<Ctrl: DeviceControl, Ctxt: GenApiCtxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+) -> CameleonResult<(EnumerationNode, IntegerNode)> {
+    let mode = ctxt
+        .node("DeviceLinkThroughputLimitMode")
+        .and_then(|node| node.as_enumeration(ctxt))
+        .ok_or_else(|| {
+            CameleonError::InvalidGenApiXml("missing DeviceLinkThroughputLimitMode".into())
+        })?;
+    if !mode.is_writable(ctxt)? {
+        return Err(GenApiError::NotWritable.into());
+    }
+    if !mode
+        .entries(ctxt)
+        .iter()
+        .any(|entry| entry.symbolic(ctxt) == "On")
+    {
+        return Err(CameleonError::InvalidGenApiXml(
+            "DeviceLinkThroughputLimitMode has no `On` entry".into(),
+        ));
+    }
+
+    let limit = ctxt
+        .node("DeviceLinkThroughputLimit")
+        .and_then(|node| node.as_integer(ctxt))
+        .ok_or_else(|| {
+            CameleonError::InvalidGenApiXml("missing DeviceLinkThroughputLimit".into())
+        })?;
+    Ok((mode, limit))
+}
+
+/// Enables the throughput limit and sets `limit` to it, then returns the written value.
+///
+/// `limit` is rounded down to a value the node accepts so that the camera never exceeds its
+/// allocation. If the node accepts no value below `limit`, its minimum is written instead.
+fn set_throughput_limit<Ctrl: DeviceControl, Ctxt: GenApiCtxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    (mode, node): (EnumerationNode, IntegerNode),
+    limit: u64,
+) -> CameleonResult<u64> {
+    mode.set_entry_by_symbolic(ctxt, "On")?;
+    let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+    let (min, max) = (node.min(ctxt)?, node.max(ctxt)?);
+    let valid_values = node.valid_value_set(ctxt);
+    let value = if valid_values.is_empty() {
+        round_down(limit, min, max, node.inc(ctxt)?)
+    } else {
+        let in_range = || {
+            valid_values
+                .iter()
+                .copied()
+                .filter(|v| (min..=max).contains(v))
+        };
+        in_range()
+            .filter(|v| *v <= limit)
+            .max()
+            .or_else(|| in_range().min())
+            .ok_or_else(|| {
+                GenApiError::InvalidData(
+                    format!("no valid value in the range [{}, {}]", min, max).into(),
+                )
+            })?
+    };
+    node.set_value(ctxt, value)?;
+    Ok(value as u64)
+}
+
+/// Clamps `value` to `min` and `max`, then rounds it down to `min + i * inc`.
+fn round_down(value: i64, min: i64, max: i64, inc: Option<i64>) -> i64 {
+    let clamped = value.clamp(min, max.max(min));
+    match inc {
+        Some(inc) if inc > 0 => {
+            // Computed in `i128` because `clamped - min` can exceed `i64::MAX`.
+            let (min, inc) = (i128::from(min), i128::from(inc));
+            let steps = (i128::from(clamped) - min) / inc;
+            i64::try_from(min + steps * inc).unwrap()
+        }
+        _ => clamped,
+    }
+}
+
+/// Returns an identifier of the host controller that owns `bus_number`.
+///
+/// On Linux, the root hub of each bus is linked from `sys/bus/usb/devices/usb<bus>` under
+/// `root`, and its parent is the host controller. A `xHCI` controller owns two buses, one for
+/// `USB 2.0` and one for `USB 3.x`, which are grouped together by this. Falls back to the bus
+/// number if the link can't be resolved.
+pub(super) fn host_controller(root: &Path, bus_number: u8) -> String {
+    let root_hub = root.join(format!("sys/bus/usb/devices/usb{bus_number}"));
+    fs::canonicalize(root_hub)
+        .ok()
+        .and_then(|path| path.parent().map(|parent| parent.display().to_string()))
+        .unwrap_or_else(|| format!("bus {bus_number}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    const LIMIT_NODES: &str = r#"
+        <Enumeration Name="DeviceLinkThroughputLimitMode">
+            <EnumEntry Name="Off">
+                <Value>0</Value>
+            </EnumEntry>
+            <EnumEntry Name="On">
+                <Value>1</Value>
+            </EnumEntry>
+            <Value>0</Value>
+        </Enumeration>
+        <Integer Name="DeviceLinkThroughputLimit">
+            <Value>1000</Value>
+            <Min>1000</Min>
+            <Max>500000</Max>
+            <Inc>1000</Inc>
+        </Integer>
+    "#;
+
+    fn demand(controller: &str, required: u64) -> BandwidthDemand {
+        BandwidthDemand {
+            controller: controller.into(),
+            speed: Some(BusSpeed::SuperSpeed),
+            required,
+        }
+    }
+
+    #[test]
+    fn test_plan_with_headroom() {
+        let demands = [demand("a", 100), demand("a", 200), demand("b", 300)];
+        let plan = BandwidthPlan::new(&demands, Some(1000));
+
+        assert_eq!(plan.limits, vec![450, 550, 1000]);
+        assert_eq!(plan.controllers.len(), 2);
+        assert_eq!(plan.controllers[0].cameras, vec![0, 1]);
+        assert_eq!(plan.controllers[0].required, 300);
+        assert!(!plan.controllers[0].is_oversubscribed());
+    }
+
+    #[test]
+    fn test_plan_oversubscribed() {
+        let demands = [demand("a", 300), demand("a", 900)];
+        let plan = BandwidthPlan::new(&demands, Some(600));
+
+        assert_eq!(plan.limits, vec![150, 450]);
+        assert!(plan.controllers[0].is_oversubscribed());
+    }
+
+    #[test]
+    fn test_plan_capacity_from_speed() {
+        let mut demands = [demand("a", 100), demand("a", 100)];
+        demands[1].speed = Some(BusSpeed::HighSpeed);
+        let plan = BandwidthPlan::new(&demands, None);
+
+        assert_eq!(
+            plan.controllers[0].capacity,
+            usable_bandwidth(BusSpeed::SuperSpeed)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_host_controller() {
+        let root = std::env::temp_dir().join(format!("cameleon-bandwidth-{}", std::process::id()));
+        let pci = root.join("sys/devices/pci0000:00/0000:00:14.0");
+        let devices = root.join("sys/bus/usb/devices");
+        fs::create_dir_all(pci.join("usb1")).unwrap();
+        fs::create_dir_all(pci.join("usb2")).unwrap();
+        fs::create_dir_all(&devices).unwrap();
+        std::os::unix::fs::symlink(pci.join("usb1"), devices.join("usb1")).unwrap();
+        std::os::unix::fs::symlink(pci.join("usb2"), devices.join("usb2")).unwrap();
+
+        let controller = fs::canonicalize(&pci).unwrap().display().to_string();
+        assert_eq!(host_controller(&root, 1), controller);
+        assert_eq!(host_controller(&root, 2), controller);
+        assert_eq!(host_controller(&root, 3), "bus 3");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_set_throughput_limit() {
+        let mut ctxt = test_utils::params_ctxt(LIMIT_NODES);
+        let nodes = throughput_limit_nodes(&mut ctxt).unwrap();
+        // Rounded down so that the allocation isn't exceeded.
+        assert_eq!(
+            set_throughput_limit(&mut ctxt, nodes, 12_999).unwrap(),
+            12_000
+        );
+        assert_eq!(
+            set_throughput_limit(&mut ctxt, nodes, u64::MAX).unwrap(),
+            500_000
+        );
+        assert_eq!(set_throughput_limit(&mut ctxt, nodes, 0).unwrap(), 1000);
+
+        let mode = nodes.0.current_entry(&mut ctxt).unwrap();
+        assert_eq!(mode.symbolic(&ctxt), "On");
+    }
+
+    #[test]
+    fn test_round_down() {
+        assert_eq!(round_down(55, 16, 101, Some(8)), 48);
+        assert_eq!(round_down(48, 16, 101, Some(8)), 48);
+        assert_eq!(round_down(0, 16, 101, Some(8)), 16);
+        assert_eq!(round_down(200, 16, 101, Some(8)), 96);
+        assert_eq!(round_down(200, 16, 101, None), 101);
+        assert_eq!(
+            round_down(i64::MAX, i64::MIN, i64::MAX, Some(2)),
+            i64::MAX - 1
+        );
+    }
+
+    #[test]
+    fn test_throughput_limit_nodes_without_on() {
+        let nodes = LIMIT_NODES.replace(r#"<EnumEntry Name="On">"#, r#"<EnumEntry Name="Auto">"#);
+        let mut ctxt = test_utils::params_ctxt(&nodes);
+        assert!(throughput_limit_nodes(&mut ctxt).is_err());
+
+        // Nothing is written by the validation.
+        let mode = ctxt
+            .node("DeviceLinkThroughputLimitMode")
+            .unwrap()
+            .as_enumeration(&ctxt)
+            .unwrap();
+        assert_eq!(
+            mode.current_entry(&mut ctxt).unwrap().symbolic(&ctxt),
+            "Off"
+        );
+    }
+}
@@ -17,7 +17,11 @@ use cameleon_device::{
 };
 use tracing::error;
 
-use super::register_map::{self, Abrm, ManifestTable, Sbrm, Sirm};
+use super::{
+    device_lock::DeviceLock,
+    register_map::{self, Abrm, ManifestTable, Sbrm, Sirm},
+    transfer_tuning::TransferTuning,
+};
 
 use crate::{
     camera::DeviceControl, genapi::CompressionType, ControlError, ControlResult, DeviceIoError,
@@ -35,8 +39,6 @@ const INITIAL_MAXIMUM_CMD_LENGTH: u32 = 128;
 /// This value is temporarily used until the device's bootstrap register value is read.
 const INITIAL_MAXIMUM_ACK_LENGTH: u32 = 128;
 
-const PAYLOAD_TRANSFER_SIZE: u32 = 1024 * 64;
-
 /// This handle provides low level API to read and write data from the device.  
 /// See [`ControlHandle::abrm`] and [`register_map`] which provide more
 /// convenient way to communicate with `u3v` specific registers.
@@ -76,6 +78,10 @@ pub struct ControlHandle {
     /// Device information.
     info: u3v::DeviceInfo,
 
+    open_mode: OpenMode,
+    /// Lock held while the handle is opened in [`OpenMode::Exclusive`].
+    lock: Option<DeviceLock>,
+
     /// Cache for `Abrm`.
     abrm: Option<Abrm>,
     /// Cache for `Sbrm`.
@@ -139,6 +145,18 @@ impl ControlHandle {
         &self.info
     }
 
+    /// Returns how the handle opens the device.
+    #[must_use]
+    pub fn open_mode(&self) -> OpenMode {
+        self.open_mode
+    }
+
+    /// Sets how the handle opens the device. The mode takes effect the next time the handle is
+    /// opened.
+    pub fn set_open_mode(&mut self, mode: OpenMode) {
+        self.open_mode = mode;
+    }
+
     /// Returns [`Abrm`].
     pub fn abrm(&mut self) -> ControlResult<Abrm> {
         if let Some(abrm) = self.abrm {
@@ -197,6 +215,8 @@ impl ControlHandle {
             next_req_id: 0,
             buffer: Vec::new(),
             info: device.device_info.clone(),
+            open_mode: OpenMode::default(),
+            lock: None,
             abrm: None,
             sbrm: None,
             sirm: None,
@@ -333,12 +353,31 @@ impl DeviceControl for ControlHandle {
             return Ok(());
         }
 
+        let lock = match self.open_mode {
+            OpenMode::Exclusive => unwrap_or_log!(DeviceLock::acquire(&self.info.guid)),
+            OpenMode::Monitor => None,
+        };
+
         unwrap_or_log!(self.inner.open());
-        // Clean up control channel state.
-        unwrap_or_log!(self.inner.set_halt(self.config.timeout_duration));
-        unwrap_or_log!(self.inner.clear_halt());
-        unwrap_or_log!(self.initialize_config());
+        let result = match self.open_mode {
+            OpenMode::Exclusive => {
+                // Clean up control channel state.
+                self.inner
+                    .set_halt(self.config.timeout_duration)
+                    .and_then(|()| self.inner.clear_halt())
+                    .map_err(ControlError::from)
+                    .and_then(|()| self.initialize_config())
+            }
+            // Resetting the channel may break a transaction of the process owning the device.
+            OpenMode::Monitor => self.initialize_config(),
+        };
+        if let Err(error) = result {
+            error!(?error);
+            self.inner.close().ok();
+            return Err(error);
+        }
 
+        self.lock = lock;
         Ok(())
     }
 
@@ -350,11 +389,15 @@ impl DeviceControl for ControlHandle {
         if self.is_opened() {
             unwrap_or_log!(self.inner.close());
         }
+        self.lock = None;
         Ok(())
     }
 
     fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
         unwrap_or_log!(self.assert_open());
+        if self.open_mode == OpenMode::Monitor {
+            unwrap_or_log!(Err(ControlError::ReadOnly));
+        }
 
         let cmd = unwrap_or_log!(cmd::WriteMem::new(address, data));
         let maximum_cmd_length = self.config.maximum_cmd_length;
@@ -449,52 +492,7 @@ impl DeviceControl for ControlHandle {
 
     fn enable_streaming(&mut self) -> ControlResult<()> {
         let sirm = unwrap_or_log!(self.sirm());
-
-        // It's forbidden to set SIRM registers while stream is enabled.
-        // This is necessary in case we lost control of a device before properly closing it, which
-        // can happen if the process closes in some way without gracefully terminating.
-        if unwrap_or_log!(sirm.is_stream_enable(self)) {
-            unwrap_or_log!(sirm.disable_stream(self));
-        }
-
-        let payload_alignment = unwrap_or_log!(sirm.payload_size_alignment(self));
-        macro_rules! align {
-            ($expr:expr, $ty: ty) => {
-                // Payload alignment is always power of two.
-                ($expr + (payload_alignment as $ty - 1)) & !(payload_alignment as $ty - 1)
-            };
-        }
-
-        let required_leader_size = unwrap_or_log!(sirm.required_leader_size(self));
-        let required_payload_size = unwrap_or_log!(sirm.required_payload_size(self));
-        let required_trailer_size = unwrap_or_log!(sirm.required_leader_size(self));
-
-        let payload_transfer_size = align!(PAYLOAD_TRANSFER_SIZE, u32);
-        let payload_transfer_count = (required_payload_size / payload_transfer_size as u64) as u32;
-        let payload_final_transfer1_size =
-            align!(required_payload_size % payload_transfer_size as u64, u64) as u32;
-        let payload_final_transfer2_size = 0;
-
-        let maximum_leader_size = if required_leader_size == 0 {
-            payload_transfer_size
-        } else {
-            align!(required_leader_size, u32)
-        };
-        let maximum_trailer_size = if required_trailer_size == 0 {
-            payload_transfer_size
-        } else {
-            align!(required_trailer_size, u32)
-        };
-
-        unwrap_or_log!(sirm.set_payload_transfer_size(self, payload_transfer_size));
-        unwrap_or_log!(sirm.set_payload_transfer_count(self, payload_transfer_count));
-        unwrap_or_log!(sirm.set_payload_final_transfer1_size(self, payload_final_transfer1_size));
-        unwrap_or_log!(sirm.set_payload_final_transfer2_size(self, payload_final_transfer2_size));
-        unwrap_or_log!(sirm.set_maximum_leader_size(self, maximum_leader_size));
-        unwrap_or_log!(sirm.set_maximum_trailer_size(self, maximum_trailer_size));
-        unwrap_or_log!(sirm.enable_stream(self));
-
-        Ok(())
+        enable_stream_channel(self, sirm)
     }
 
     fn disable_streaming(&mut self) -> ControlResult<()> {
@@ -503,6 +501,26 @@ impl DeviceControl for ControlHandle {
     }
 }
 
+/// Configures transfer sizes of the stream channel corresponding to `sirm` with
+/// [`TransferTuning::Fixed`], then enables it.
+pub(super) fn enable_stream_channel<Ctrl: DeviceControl + ?Sized>(
+    ctrl: &mut Ctrl,
+    sirm: Sirm,
+) -> ControlResult<()> {
+    // It's forbidden to set SIRM registers while stream is enabled.
+    // This is necessary in case we lost control of a device before properly closing it, which
+    // can happen if the process closes in some way without gracefully terminating.
+    if unwrap_or_log!(sirm.is_stream_enable(ctrl)) {
+        unwrap_or_log!(sirm.disable_stream(ctrl));
+    }
+
+    let plan = unwrap_or_log!(TransferTuning::Fixed.plan(ctrl, sirm));
+    unwrap_or_log!(plan.write(ctrl, sirm));
+    unwrap_or_log!(sirm.enable_stream(ctrl));
+
+    Ok(())
+}
+
 impl Drop for ControlHandle {
     fn drop(&mut self) {
         if let Err(e) = self.close() {
@@ -511,6 +529,28 @@ impl Drop for ControlHandle {
     }
 }
 
+/// How [`ControlHandle`] opens the device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Opens the device exclusively.
+    ///
+    /// An advisory lock keyed by the device GUID is taken when the handle is opened, and
+    /// [`DeviceControl::open`] fails with [`ControlError::Busy`] if another process has already
+    /// opened the device. The lock is released when the handle is closed.
+    ///
+    /// If the lock file can't be created, e.g. because the runtime directory isn't writable, a
+    /// warning is logged and the device is opened without the lock.
+    #[default]
+    Exclusive,
+
+    /// Opens the device for monitoring without taking the lock.
+    ///
+    /// Registers can be read, but [`DeviceControl::write`] fails with [`ControlError::ReadOnly`].
+    /// Note that the OS may still refuse to share the USB interface with another process, in
+    /// which case [`DeviceControl::open`] fails with [`ControlError::Busy`].
+    Monitor,
+}
+
 /// Thread safe version of [`ControlHandle`].
 #[derive(Clone)]
 pub struct SharedControlHandle(Arc<Mutex<ControlHandle>>);
@@ -561,7 +601,12 @@ impl SharedControlHandle {
         #[must_use]
         pub fn retry_count(&self) -> u16,
         /// Thread safe version of [`ControlHandle::set_retry_count`].
-        pub fn set_retry_count(&self, count: u16) -> ()
+        pub fn set_retry_count(&self, count: u16) -> (),
+        /// Thread safe version of [`ControlHandle::open_mode`].
+        #[must_use]
+        pub fn open_mode(&self) -> OpenMode,
+        /// Thread safe version of [`ControlHandle::set_open_mode`].
+        pub fn set_open_mode(&self, mode: OpenMode) -> ()
     );
 
     /// Returns the device info of the handle.
@@ -0,0 +1,168 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains an advisory lock that prevents multiple processes from opening the same
+//! device at the same time.
+//!
+//! The lock is a file named after the device GUID in the runtime directory, locked with `flock`
+//! on Unix and `LockFileEx` on Windows. The lock is released by the OS when the process exits, so
+//! a crashed process never leaves the device locked.
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use tracing::{error, warn};
+
+use crate::{ControlError, ControlResult, DeviceIoError};
+
+/// An exclusive lock of a device, released when dropped.
+#[derive(Debug)]
+pub(super) struct DeviceLock {
+    file: File,
+}
+
+impl DeviceLock {
+    /// Locks the device with `guid`.
+    ///
+    /// Returns [`ControlError::Busy`] if another process holds the lock. If the lock file can't
+    /// be created, e.g. because of the permission of the runtime directory, the device is opened
+    /// without the lock and `Ok(None)` is returned.
+    pub(super) fn acquire(guid: &str) -> ControlResult<Option<Self>> {
+        Self::acquire_in(&lock_dir(), guid)
+    }
+
+    pub(super) fn acquire_in(dir: &Path, guid: &str) -> ControlResult<Option<Self>> {
+        let path = lock_path(dir, guid);
+        let mut file = match open_lock_file(&path) {
+            Ok(file) => file,
+            Err(err) => {
+                warn!(
+                    path = %path.display(),
+                    %err,
+                    "failed to open the device lock file, the device is opened without the lock"
+                );
+                return Ok(None);
+            }
+        };
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut holder = String::new();
+                file.read_to_string(&mut holder).ok();
+                error!(
+                    path = %path.display(),
+                    holder_pid = holder.trim(),
+                    "the device is opened by another process"
+                );
+                return Err(ControlError::Busy);
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(ControlError::Io(DeviceIoError::msg(format!(
+                    "failed to lock `{}`: {}",
+                    path.display(),
+                    err
+                ))));
+            }
+        }
+
+        // Records the pid of the holder to help users find out which process opens the device.
+        // This is informational only, so errors are ignored.
+        if file.set_len(0).is_ok() && file.seek(SeekFrom::Start(0)).is_ok() {
+            write!(file, "{}", std::process::id()).ok();
+        }
+
+        Ok(Some(Self { file }))
+    }
+}
+
+impl Drop for DeviceLock {
+    fn drop(&mut self) {
+        self.file.unlock().ok();
+    }
+}
+
+/// Returns the directory containing lock files, `$XDG_RUNTIME_DIR/cameleon` if `XDG_RUNTIME_DIR`
+/// is set, or `cameleon` in the temporary directory otherwise.
+fn lock_dir() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(env::temp_dir)
+        .join("cameleon")
+}
+
+fn lock_path(dir: &Path, guid: &str) -> PathBuf {
+    let guid: String = guid
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    dir.join(format!("u3v-{}.lock", guid))
+}
+
+fn open_lock_file(path: &Path) -> std::io::Result<File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        // The file may be created by another user, a read-only descriptor is enough to lock it.
+        .or_else(|_| File::open(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_path() {
+        let dir = Path::new("/run/user/1000/cameleon");
+        assert_eq!(
+            lock_path(dir, "2676-abcd:0123"),
+            dir.join("u3v-2676ABCD0123.lock")
+        );
+    }
+
+    #[test]
+    fn test_exclusive() {
+        let dir = env::temp_dir().join(format!("cameleon-lock-test-{}", std::process::id()));
+
+        let lock = DeviceLock::acquire_in(&dir, "GUID0").unwrap();
+        assert!(lock.is_some());
+        // Each open file description holds its own lock, so this behaves as another process.
+        assert!(matches!(
+            DeviceLock::acquire_in(&dir, "GUID0"),
+            Err(ControlError::Busy)
+        ));
+        assert!(DeviceLock::acquire_in(&dir, "GUID1").unwrap().is_some());
+
+        drop(lock);
+        assert!(DeviceLock::acquire_in(&dir, "GUID0").unwrap().is_some());
+
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_unavailable_lock_dir() {
+        let file = env::temp_dir().join(format!("cameleon-lock-file-{}", std::process::id()));
+        File::create(&file).unwrap();
+
+        // The lock directory can't be created under a regular file, so the device is opened
+        // without the lock.
+        assert!(DeviceLock::acquire_in(&file.join("cameleon"), "GUID0")
+            .unwrap()
+            .is_none());
+
+        fs::remove_file(file).ok();
+    }
+}
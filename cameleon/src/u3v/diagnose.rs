@@ -0,0 +1,433 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains diagnostics of the host environment for `U3V` cameras.
+//!
+//! [`diagnose`] inspects `sysfs` and `/dev` directly instead of opening devices through `libusb`,
+//! so that it can find problems that prevent devices from being opened at all.
+//!
+//! # Examples
+//! ```no_run
+//! use cameleon::u3v;
+//!
+//! for finding in u3v::diagnose() {
+//!     println!("{}", finding);
+//! }
+//! ```
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use cameleon_device::u3v::BusSpeed;
+
+use super::transfer_tuning::usbfs_memory_mb;
+
+/// `usbfs_memory_mb` recommended for image streaming.
+const RECOMMENDED_USBFS_MEMORY_MB: u64 = 1000;
+
+/// Vendor IDs covered by `misc/u3v.rules` in the repository.
+///
+/// The rules aren't packaged with the crate, so they're kept in sync by a test.
+const VENDORS_IN_UDEV_RULES: &[u16] = &[0x2676, 0x2bdf, 0x199e, 0x2ba2, 0x1e10, 0x2e03];
+
+/// A `U3V` device found in `sysfs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbDeviceEntry {
+    /// Vendor ID of the device.
+    pub vendor_id: u16,
+    /// Product ID of the device.
+    pub product_id: u16,
+    /// Number of the bus the device is connected to.
+    pub bus_number: u8,
+    /// Address of the device on the bus.
+    pub address: u8,
+    /// Bus speed negotiated with the host, `None` if unknown.
+    pub speed: Option<BusSpeed>,
+}
+
+/// A problem found by [`diagnose`].
+///
+/// `Display` implementation of this type describes the problem and how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// No `U3V` device is found in `sysfs`.
+    NoDeviceFound,
+
+    /// The current user can't read or write the device node, so the device can't be opened.
+    DeviceNodeNotAccessible {
+        /// The device.
+        device: UsbDeviceEntry,
+        /// Path to the device node.
+        path: PathBuf,
+        /// Permission bits of the device node, `None` if the node doesn't exist.
+        mode: Option<u32>,
+        /// `true` if `misc/u3v.rules` already covers the vendor of the device.
+        covered_by_udev_rules: bool,
+    },
+
+    /// `usbfs_memory_mb` is too small to stream large payloads.
+    UsbfsMemoryTooSmall {
+        /// Current value in MiB.
+        current_mb: u64,
+        /// Recommended value in MiB.
+        recommended_mb: u64,
+    },
+
+    /// The device is connected with a bus speed slower than `SuperSpeed`.
+    SlowBusSpeed {
+        /// The device.
+        device: UsbDeviceEntry,
+    },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoDeviceFound => write!(
+                f,
+                "no U3V device is found. make sure that the camera is connected and powered"
+            ),
+
+            Self::DeviceNodeNotAccessible {
+                device,
+                path,
+                mode,
+                covered_by_udev_rules,
+            } => {
+                write!(f, "{} is not accessible", path.display())?;
+                if let Some(mode) = mode {
+                    write!(f, " (mode {:o})", mode & 0o777)?;
+                }
+                if *covered_by_udev_rules {
+                    write!(
+                        f,
+                        ". copy misc/u3v.rules to /etc/udev/rules.d/ and reconnect the camera"
+                    )
+                } else {
+                    write!(
+                        f,
+                        ". add the line below to /etc/udev/rules.d/u3v.rules and reconnect the camera\n\
+                         SUBSYSTEM==\"usb\", ATTRS{{idVendor}}==\"{:04x}\", MODE:=\"0666\", TAG+=\"uaccess\", TAG+=\"udev-acl\"",
+                        device.vendor_id
+                    )
+                }
+            }
+
+            Self::UsbfsMemoryTooSmall {
+                current_mb,
+                recommended_mb,
+            } => write!(
+                f,
+                "usbfs_memory_mb is {current_mb} MB, which is too small for image streaming. \
+                 run `echo {recommended_mb} > /sys/module/usbcore/parameters/usbfs_memory_mb` as root"
+            ),
+
+            Self::SlowBusSpeed { device } => write!(
+                f,
+                "device {:04x}:{:04x} on bus {} is connected at {:?}. \
+                 connect it to a USB 3 port with a USB 3 cable",
+                device.vendor_id,
+                device.product_id,
+                device.bus_number,
+                device.speed.map_or("unknown speed".into(), |s| format!("{s:?}"))
+            ),
+        }
+    }
+}
+
+/// Inspects the host environment and returns problems that may prevent `U3V` cameras from
+/// working.
+///
+/// Returns an empty `Vec` if nothing is found. Only Linux is supported for now, nothing is
+/// reported on other platforms.
+#[must_use]
+pub fn diagnose() -> Vec<Finding> {
+    if cfg!(target_os = "linux") {
+        diagnose_at(Path::new("/"))
+    } else {
+        vec![]
+    }
+}
+
+/// Same as [`diagnose`], but reads `sys` and `dev` under `root` instead of `/`.
+///
+/// This is useful to test against a fake `sysfs`.
+#[must_use]
+pub fn diagnose_at(root: &Path) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    let devices = u3v_devices(root);
+    if devices.is_empty() {
+        findings.push(Finding::NoDeviceFound);
+    }
+
+    for device in devices {
+        let path = root.join(format!(
+            "dev/bus/usb/{:03}/{:03}",
+            device.bus_number, device.address
+        ));
+        if !is_accessible(&path) {
+            findings.push(Finding::DeviceNodeNotAccessible {
+                mode: mode(&path),
+                path,
+                covered_by_udev_rules: VENDORS_IN_UDEV_RULES.contains(&device.vendor_id),
+                device: device.clone(),
+            });
+        }
+
+        if !matches!(
+            device.speed,
+            Some(BusSpeed::SuperSpeed | BusSpeed::SuperSpeedPlus)
+        ) {
+            findings.push(Finding::SlowBusSpeed { device });
+        }
+    }
+
+    if let Some(current_mb) = usbfs_memory_mb(root) {
+        if current_mb != 0 && current_mb < RECOMMENDED_USBFS_MEMORY_MB {
+            findings.push(Finding::UsbfsMemoryTooSmall {
+                current_mb,
+                recommended_mb: RECOMMENDED_USBFS_MEMORY_MB,
+            });
+        }
+    }
+
+    findings
+}
+
+/// Scans `sys/bus/usb/devices` under `root` for `U3V` devices.
+fn u3v_devices(root: &Path) -> Vec<UsbDeviceEntry> {
+    let Ok(entries) = fs::read_dir(root.join("sys/bus/usb/devices")) else {
+        return vec![];
+    };
+
+    let mut devices: Vec<_> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| is_u3v_device(path))
+        .filter_map(|path| {
+            Some(UsbDeviceEntry {
+                vendor_id: read_hex(&path.join("idVendor"))?,
+                product_id: read_hex(&path.join("idProduct"))?,
+                bus_number: read_attr(&path.join("busnum"))?.parse().ok()?,
+                address: read_attr(&path.join("devnum"))?.parse().ok()?,
+                speed: read_attr(&path.join("speed")).and_then(|speed| parse_speed(&speed)),
+            })
+        })
+        .collect();
+    devices.sort_by_key(|dev| (dev.bus_number, dev.address));
+    devices
+}
+
+/// Returns `true` if `path` is a USB device that has a `U3V` control interface.
+fn is_u3v_device(path: &Path) -> bool {
+    // Miscellaneous device class with IAD, see `U3V` specification.
+    let is_iad_device = read_attr(&path.join("bDeviceClass")).as_deref() == Some("ef")
+        && read_attr(&path.join("bDeviceSubClass")).as_deref() == Some("02")
+        && read_attr(&path.join("bDeviceProtocol")).as_deref() == Some("01");
+    if !is_iad_device {
+        return false;
+    }
+
+    // Interfaces are listed as `<device>:<config>.<interface>` directories.
+    let Ok(entries) = fs::read_dir(path) else {
+        return false;
+    };
+    entries.filter_map(Result::ok).any(|entry| {
+        let iface = entry.path();
+        read_attr(&iface.join("bInterfaceClass")).as_deref() == Some("ef")
+            && read_attr(&iface.join("bInterfaceSubClass")).as_deref() == Some("05")
+    })
+}
+
+fn parse_speed(speed: &str) -> Option<BusSpeed> {
+    match speed {
+        "1.5" => Some(BusSpeed::LowSpeed),
+        "12" => Some(BusSpeed::FullSpeed),
+        "480" => Some(BusSpeed::HighSpeed),
+        "5000" => Some(BusSpeed::SuperSpeed),
+        "10000" | "20000" => Some(BusSpeed::SuperSpeedPlus),
+        _ => None,
+    }
+}
+
+fn read_attr(path: &Path) -> Option<String> {
+    Some(fs::read_to_string(path).ok()?.trim().to_string())
+}
+
+fn read_hex(path: &Path) -> Option<u16> {
+    u16::from_str_radix(&read_attr(path)?, 16).ok()
+}
+
+#[cfg(unix)]
+fn mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(fs::metadata(path).ok()?.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn mode(_: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn is_accessible(path: &Path) -> bool {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: `path` is a valid nul-terminated string.
+    unsafe { libc::access(path.as_ptr(), libc::R_OK | libc::W_OK) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_accessible(path: &Path) -> bool {
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .is_ok()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    struct FakeRoot(PathBuf);
+
+    impl FakeRoot {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "cameleon-diagnose-{}-{}",
+                name,
+                std::process::id()
+            ));
+            fs::create_dir_all(root.join("sys/bus/usb/devices")).unwrap();
+            Self(root)
+        }
+
+        fn write(&self, path: &str, content: &str) {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        fn add_device(&self, name: &str, vendor_id: &str, bus: u8, dev: u8, speed: &str) {
+            let dir = format!("sys/bus/usb/devices/{name}");
+            for (attr, value) in [
+                ("bDeviceClass", "ef"),
+                ("bDeviceSubClass", "02"),
+                ("bDeviceProtocol", "01"),
+                ("idVendor", vendor_id),
+                ("idProduct", "0001"),
+                ("busnum", &bus.to_string()),
+                ("devnum", &dev.to_string()),
+                ("speed", speed),
+            ] {
+                self.write(&format!("{dir}/{attr}"), &format!("{value}\n"));
+            }
+            self.write(&format!("{dir}/{name}:1.0/bInterfaceClass"), "ef\n");
+            self.write(&format!("{dir}/{name}:1.0/bInterfaceSubClass"), "05\n");
+            self.write(&format!("dev/bus/usb/{bus:03}/{dev:03}"), "");
+        }
+
+        fn remove(&self, path: &str) {
+            fs::remove_file(self.0.join(path)).unwrap();
+        }
+    }
+
+    impl Drop for FakeRoot {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn test_no_device() {
+        let root = FakeRoot::new("no-device");
+        assert_eq!(diagnose_at(&root.0), vec![Finding::NoDeviceFound]);
+    }
+
+    #[test]
+    fn test_healthy_device() {
+        let root = FakeRoot::new("healthy");
+        root.add_device("2-1", "2676", 2, 3, "5000");
+        root.write("sys/module/usbcore/parameters/usbfs_memory_mb", "1000\n");
+        // Non U3V devices are ignored.
+        root.write("sys/bus/usb/devices/1-1/bDeviceClass", "00\n");
+
+        assert!(diagnose_at(&root.0).is_empty());
+    }
+
+    #[test]
+    fn test_slow_bus_and_usbfs_memory() {
+        let root = FakeRoot::new("slow");
+        root.add_device("1-2", "2676", 1, 5, "480");
+        root.write("sys/module/usbcore/parameters/usbfs_memory_mb", "16\n");
+
+        let findings = diagnose_at(&root.0);
+        assert_eq!(findings.len(), 2);
+        assert!(matches!(
+            &findings[0],
+            Finding::SlowBusSpeed { device } if device.speed == Some(BusSpeed::HighSpeed)
+        ));
+        assert_eq!(
+            findings[1],
+            Finding::UsbfsMemoryTooSmall {
+                current_mb: 16,
+                recommended_mb: 1000
+            }
+        );
+    }
+
+    #[test]
+    fn test_inaccessible_device_node() {
+        let root = FakeRoot::new("access");
+        root.add_device("2-1", "1234", 2, 7, "5000");
+        root.add_device("2-2", "2676", 2, 8, "5000");
+        root.remove("dev/bus/usb/002/007");
+        root.remove("dev/bus/usb/002/008");
+
+        let findings = diagnose_at(&root.0);
+        assert_eq!(findings.len(), 2);
+        match &findings[0] {
+            Finding::DeviceNodeNotAccessible {
+                device,
+                path,
+                mode,
+                covered_by_udev_rules,
+            } => {
+                assert_eq!(device.vendor_id, 0x1234);
+                assert_eq!(path, &root.0.join("dev/bus/usb/002/007"));
+                assert_eq!(*mode, None);
+                assert!(!covered_by_udev_rules);
+            }
+            finding => panic!("unexpected finding: {:?}", finding),
+        }
+        assert!(findings[0]
+            .to_string()
+            .contains("ATTRS{idVendor}==\"1234\""));
+        assert!(matches!(
+            findings[1],
+            Finding::DeviceNodeNotAccessible {
+                covered_by_udev_rules: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_vendors_in_udev_rules() {
+        let rules = include_str!("../../../misc/u3v.rules");
+        let vendors: Vec<u16> = rules
+            .split("ATTRS{idVendor}==\"")
+            .skip(1)
+            .map(|rest| u16::from_str_radix(&rest[..4], 16).unwrap())
+            .collect();
+        assert_eq!(vendors, VENDORS_IN_UDEV_RULES);
+    }
+}
@@ -45,24 +45,38 @@
 //! ```
 #![allow(clippy::missing_panics_doc)]
 
+pub mod bandwidth;
 pub mod control_handle;
+pub mod diagnose;
+pub mod reconnect;
 pub mod register_map;
 pub mod stream_handle;
+pub mod transfer_tuning;
+pub mod watcher;
 
-pub use control_handle::{ControlHandle, SharedControlHandle};
-pub use stream_handle::{StreamHandle, StreamParams};
+mod device_lock;
+
+pub use control_handle::{ControlHandle, OpenMode, SharedControlHandle};
+pub use diagnose::{diagnose, Finding};
+pub use reconnect::{ReconnectConfig, ReconnectEvent, Supervisor};
+pub use stream_handle::{StreamHandle, StreamParams, StreamStatistics, StreamThreadConfig};
+pub use transfer_tuning::{benchmark_transfer_sizes, AutoTransferConfig, TransferTuning};
+pub use watcher::{DeviceEvent, DeviceWatcher, Enumeration, WatcherConfig};
 
 pub use cameleon_device::u3v::DeviceInfo;
 
 use cameleon_device::u3v;
 
 use super::{
-    genapi::DefaultGenApiCtxt, CameleonResult, Camera, CameraInfo, ControlError, DeviceIoError,
-    StreamError,
+    genapi::DefaultGenApiCtxt, CameleonError, CameleonResult, Camera, CameraInfo, ControlError,
+    DeviceIoError, StreamError,
 };
 
 /// Enumerate all U3V compatible cameras connected to the host.
 ///
+/// Devices that can't be opened, e.g. because of lack of permission, are skipped. If a camera is
+/// missing from the result, [`diagnose()`] may tell the reason.
+///
 /// # Examples
 ///
 /// ```no_run
@@ -79,29 +93,147 @@ pub fn enumerate_cameras() -> CameleonResult<Vec<Camera<ControlHandle, StreamHan
     let mut cameras: Vec<Camera<ControlHandle, StreamHandle>> = Vec::with_capacity(devices.len());
 
     for dev in devices {
-        let ctrl = ControlHandle::new(&dev)?;
         let strm = if let Some(strm) = StreamHandle::new(&dev)? {
             strm
         } else {
             continue;
         };
-        let ctxt = None;
+        cameras.push(build_camera(&dev, strm)?);
+    }
 
-        let dev_info = dev.device_info;
-        let camera_info = CameraInfo {
-            vendor_name: dev_info.vendor_name,
-            model_name: dev_info.model_name,
-            serial_number: dev_info.serial_number,
-        };
+    Ok(cameras)
+}
+
+/// Enumerate all U3V compatible cameras connected to the host, together with handles of their
+/// secondary stream channels.
+///
+/// [`Camera::strm`] receives from the stream channel `0`, and the returned `Vec<StreamHandle>`
+/// contains handles of the other channels ordered by their channel index. It's empty if the
+/// camera has a single stream channel.
+///
+/// # Examples
+///
+/// ```no_run
+/// use cameleon::u3v;
+///
+/// let mut cameras = u3v::enumerate_cameras_with_streams().unwrap();
+/// let (mut camera, mut streams) = cameras.pop().unwrap();
+///
+/// camera.open().unwrap();
+/// camera.load_context().unwrap();
+///
+/// // Starts the secondary stream channels first, `Camera::start_streaming` starts acquisition.
+/// let mut receivers = vec![];
+/// for strm in &mut streams {
+///     use cameleon::PayloadStream;
+///     strm.open().unwrap();
+///     receivers.push(strm.start_streaming(&mut camera.ctrl, 3).unwrap());
+/// }
+/// let payload_rx = camera.start_streaming(3).unwrap();
+/// ```
+#[allow(clippy::type_complexity)]
+pub fn enumerate_cameras_with_streams(
+) -> CameleonResult<Vec<(Camera<ControlHandle, StreamHandle>, Vec<StreamHandle>)>> {
+    let devices = u3v::enumerate_devices().map_err(ControlError::from)?;
+
+    let mut cameras = Vec::with_capacity(devices.len());
 
-        let camera: Camera<ControlHandle, StreamHandle, DefaultGenApiCtxt> =
-            Camera::new(ctrl, strm, ctxt, camera_info);
-        cameras.push(camera)
+    for dev in devices {
+        let mut strms = StreamHandle::new_all(&dev)?.into_iter();
+        let strm = if let Some(strm) = strms.next() {
+            strm
+        } else {
+            continue;
+        };
+        cameras.push((build_camera(&dev, strm)?, strms.collect()));
     }
 
     Ok(cameras)
 }
 
+/// A condition to select a camera in [`open_by`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    /// Selects the camera by its serial number.
+    Serial(String),
+
+    /// Selects the camera by its user defined name.
+    UserName(String),
+
+    /// Selects the camera by its GUID.
+    Guid(String),
+
+    /// Selects the camera by the USB port it's connected to, in the format of
+    /// [`DeviceInfo::port_path`], e.g. `2-1.3`.
+    PortPath(String),
+}
+
+impl Selector {
+    /// Returns `true` if `info` matches the selector.
+    #[must_use]
+    pub fn matches(&self, info: &DeviceInfo) -> bool {
+        match self {
+            Self::Serial(serial) => &info.serial_number == serial,
+            Self::UserName(name) => info.user_defined_name.as_ref() == Some(name),
+            Self::Guid(guid) => info.guid.eq_ignore_ascii_case(guid),
+            Self::PortPath(path) => &info.port_path() == path,
+        }
+    }
+}
+
+impl std::fmt::Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Serial(serial) => write!(f, "serial number `{serial}`"),
+            Self::UserName(name) => write!(f, "user defined name `{name}`"),
+            Self::Guid(guid) => write!(f, "GUID `{guid}`"),
+            Self::PortPath(path) => write!(f, "port path `{path}`"),
+        }
+    }
+}
+
+/// Finds the camera that matches `selector` and opens it.
+///
+/// Returns [`CameleonError::CameraNotFound`] if no camera matches. If multiple cameras match, the
+/// first one in the order of [`enumerate_cameras`] is returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// use cameleon::u3v::{self, Selector};
+///
+/// // Opens the camera connected to the 3rd port of the hub on the 1st port of bus 2.
+/// let mut camera = u3v::open_by(Selector::PortPath("2-1.3".into())).unwrap();
+/// camera.load_context().unwrap();
+/// ```
+pub fn open_by(selector: Selector) -> CameleonResult<Camera<ControlHandle, StreamHandle>> {
+    let mut camera = enumerate_cameras()?
+        .into_iter()
+        .find(|camera| selector.matches(camera.ctrl.device_info()))
+        .ok_or_else(|| CameleonError::CameraNotFound(format!("no camera has {selector}").into()))?;
+    camera.open()?;
+    Ok(camera)
+}
+
+fn build_camera(
+    dev: &u3v::Device,
+    strm: StreamHandle,
+) -> CameleonResult<Camera<ControlHandle, StreamHandle>> {
+    let ctrl = ControlHandle::new(dev)?;
+    let ctxt = None;
+
+    let dev_info = &dev.device_info;
+    let camera_info = CameraInfo {
+        vendor_name: dev_info.vendor_name.clone(),
+        model_name: dev_info.model_name.clone(),
+        serial_number: dev_info.serial_number.clone(),
+    };
+
+    let camera: Camera<ControlHandle, StreamHandle, DefaultGenApiCtxt> =
+        Camera::new(ctrl, strm, ctxt, camera_info);
+    Ok(camera)
+}
+
 impl From<u3v::Error> for ControlError {
     fn from(err: u3v::Error) -> ControlError {
         use u3v::Error::{BufferIo, InvalidDevice, InvalidPacket, LibUsb};
@@ -147,3 +279,44 @@ impl From<u3v::Error> for StreamError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cameleon_device::u3v::BusSpeed;
+
+    fn device_info() -> DeviceInfo {
+        DeviceInfo {
+            gencp_version: semver::Version::new(1, 0, 0),
+            u3v_version: semver::Version::new(1, 0, 0),
+            guid: "2676ABCD0123".into(),
+            vendor_name: "vendor".into(),
+            model_name: "model".into(),
+            family_name: None,
+            device_version: "1.0".into(),
+            manufacturer_info: String::new(),
+            serial_number: "12345".into(),
+            user_defined_name: Some("left".into()),
+            supported_speed: BusSpeed::SuperSpeed,
+            connected_speed: Some(BusSpeed::SuperSpeed),
+            bus_number: 2,
+            port_numbers: vec![1, 3],
+        }
+    }
+
+    #[test]
+    fn test_selector() {
+        let info = device_info();
+        assert_eq!(info.port_path(), "2-1.3");
+
+        assert!(Selector::Serial("12345".into()).matches(&info));
+        assert!(Selector::UserName("left".into()).matches(&info));
+        assert!(Selector::Guid("2676abcd0123".into()).matches(&info));
+        assert!(Selector::PortPath("2-1.3".into()).matches(&info));
+
+        assert!(!Selector::Serial("1234".into()).matches(&info));
+        assert!(!Selector::UserName("right".into()).matches(&info));
+        assert!(!Selector::PortPath("2-1".into()).matches(&info));
+    }
+}
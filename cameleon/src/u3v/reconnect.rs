@@ -0,0 +1,744 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains [`Supervisor`] which reconnects a camera automatically when it drops off
+//! the bus.
+//!
+//! [`Supervisor`] takes ownership of an opened camera and watches the device with
+//! [`DeviceWatcher`]. When the camera is disconnected, it waits for a device with the same GUID or
+//! serial number to come back, and then
+//!
+//! 1. opens the device and reloads `GenApi` context,
+//! 2. restores the parameters, see below,
+//! 3. resumes streaming if the camera was streaming.
+//!
+//! Parameters are restored in two steps. First, the features saved by
+//! [`ParamsCtxt::save_features`] are loaded. The features are saved when the supervisor is
+//! created, so the configuration made before that is kept, and saved again whenever
+//! [`Supervisor::with_camera`] or [`Supervisor::execute`] writes a node, including writes by
+//! [`ParamsCtxt::apply_params`] and [`ParamsCtxt::load_features`]. Then, the values written
+//! through [`Supervisor::set_param`] are replayed, which also covers features that
+//! [`ParamsCtxt::save_features`] doesn't save, e.g. features not marked as streamable.
+//!
+//! A disconnection is detected from the removal of the device, from a failed request made through
+//! the supervisor, or from [`StreamError::Disconnected`](crate::StreamError) reported by the
+//! streaming loop.
+//!
+//! Payloads are delivered to the same [`PayloadReceiver`] across reconnections. While the camera
+//! is disconnected, the receiver may receive [`StreamError::Disconnected`](crate::StreamError).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use cameleon::u3v::{self, reconnect::{ReconnectConfig, Supervisor}};
+//!
+//! let mut camera = u3v::enumerate_cameras().unwrap().pop().unwrap();
+//! camera.open().unwrap();
+//! camera.load_context().unwrap();
+//!
+//! let supervisor = Supervisor::new(camera, ReconnectConfig::default()).unwrap();
+//! // Written values are restored after reconnection.
+//! supervisor.set_param("ExposureTime", 10000.0).unwrap();
+//!
+//! let payload_rx = supervisor.start_streaming(3).unwrap();
+//! loop {
+//!     match payload_rx.recv_blocking() {
+//!         Ok(payload) => payload_rx.send_back(payload),
+//!         // Errors are reported while the camera is disconnected, streaming resumes once it's back.
+//!         Err(err) => println!("{}", err),
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::HashSet,
+    sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use async_channel::{Receiver, Sender};
+use tracing::{info, warn};
+
+use crate::{
+    camera::{DeviceControl, PayloadStream},
+    genapi::{DefaultGenApiCtxt, FromXml, GenApiCtxt, GenApiError, ParamValue, ParamsCtxt},
+    payload::{self, PayloadReceiver, PayloadSender},
+    CameleonError, CameleonResult, Camera, ControlError, StreamError, StreamResult,
+};
+
+use super::{
+    enumerate_cameras,
+    watcher::{DeviceEvent, DeviceWatcher, WatcherConfig},
+    ControlHandle, DeviceInfo, Selector, StreamHandle, StreamParams,
+};
+
+/// The interval at which the supervisor thread checks device events.
+const TICK: Duration = Duration::from_millis(50);
+
+/// Configuration of [`Supervisor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReconnectConfig {
+    /// Configuration of the [`DeviceWatcher`] used to detect removal and arrival of the device.
+    pub watcher: WatcherConfig,
+
+    /// Interval of reconnection attempts while the camera is disconnected.
+    ///
+    /// A reconnection is also attempted as soon as the device arrives.
+    pub retry_interval: Duration,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            watcher: WatcherConfig::default(),
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// An event emitted by [`Supervisor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReconnectEvent {
+    /// The camera is disconnected.
+    Disconnected(DeviceInfo),
+
+    /// The camera is reconnected and its parameters and streaming are restored.
+    Reconnected(DeviceInfo),
+}
+
+/// Owns a camera and reconnects it automatically when it drops off the bus.
+///
+/// See the [module level documentation](self) for details.
+pub struct Supervisor<Ctxt = DefaultGenApiCtxt> {
+    state: Arc<Mutex<State<Ctxt>>>,
+    events: Receiver<ReconnectEvent>,
+    events_tx: Sender<ReconnectEvent>,
+    /// Notified when the streaming loop reports [`StreamError::Disconnected`].
+    stream_lost_tx: Sender<()>,
+    stop_tx: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl<Ctxt> Supervisor<Ctxt>
+where
+    Ctxt: GenApiCtxt + FromXml + Send + 'static,
+{
+    /// Starts supervising `camera`.
+    ///
+    /// `camera` must be opened and its `GenApi` context must be loaded.
+    pub fn new(
+        camera: Camera<ControlHandle, StreamHandle, Ctxt>,
+        config: ReconnectConfig,
+    ) -> CameleonResult<Self> {
+        if !camera.ctrl.is_opened() {
+            return Err(ControlError::NotOpened.into());
+        }
+        if camera.ctxt.is_none() {
+            return Err(CameleonError::GenApiContextMissing);
+        }
+
+        let identity = camera.ctrl.device_info().clone();
+        let watcher = DeviceWatcher::new(config.watcher.clone())?;
+        let (stream_lost_tx, stream_lost) = async_channel::unbounded();
+        let mut state = State {
+            camera,
+            identity,
+            connected: true,
+            snapshot: None,
+            journal: ParamJournal::default(),
+            streaming: None,
+            stream_lost,
+            last_attempt: None,
+        };
+        state.save_snapshot();
+        let state = Arc::new(Mutex::new(state));
+        let (events_tx, events) = async_channel::unbounded();
+        let (stop_tx, stop_rx) = mpsc::channel();
+
+        let thread_state = state.clone();
+        let thread_events_tx = events_tx.clone();
+        let handle = thread::Builder::new()
+            .name("cameleon-reconnect".into())
+            .spawn(move || {
+                supervise(
+                    &thread_state,
+                    &watcher,
+                    &config,
+                    &stop_rx,
+                    &thread_events_tx,
+                );
+            })
+            .map_err(|e| ControlError::from(cameleon_device::u3v::Error::from(e)))?;
+
+        Ok(Self {
+            state,
+            events,
+            events_tx,
+            stream_lost_tx,
+            stop_tx: Some(stop_tx),
+            handle: Some(handle),
+        })
+    }
+
+    /// Writes `value` to the node named `name`, and records it to replay after reconnection.
+    ///
+    /// Returns [`ControlError::Disconnected`] while the camera is disconnected.
+    pub fn set_param(&self, name: &str, value: impl Into<ParamValue>) -> CameleonResult<()> {
+        let value = value.into();
+        self.with_state(|state| {
+            let mut ctxt = state.camera.params_ctxt()?;
+            ctxt.set_param(name, &value)?;
+            let is_selector = ctxt.node(name).is_some_and(|node| node.is_selector(&ctxt));
+            state.journal.record(name, value, is_selector);
+            Ok(())
+        })
+    }
+
+    /// Executes the command node named `name`.
+    ///
+    /// Commands are not replayed after reconnection, but features changed by the command are
+    /// restored in the same way as [`Self::with_camera`].
+    pub fn execute(&self, name: &str) -> CameleonResult<()> {
+        self.with_camera(|camera| {
+            let mut ctxt = camera.params_ctxt()?;
+            let node = ctxt
+                .node(name)
+                .and_then(|node| node.as_command(&ctxt))
+                .ok_or_else(|| {
+                    GenApiError::InvalidNode(format!("no command node named `{}`", name).into())
+                })?;
+            Ok(node.execute(&mut ctxt)?)
+        })
+    }
+
+    /// Runs `f` with the camera.
+    ///
+    /// Returns [`ControlError::Disconnected`] without calling `f` while the camera is
+    /// disconnected. If `f` fails because the camera is disconnected, the supervisor starts
+    /// reconnection.
+    ///
+    /// If `f` writes a node, the features are saved again after `f` returns, so that they're
+    /// restored after reconnection. This reads all the features from the device.
+    pub fn with_camera<F, R>(&self, f: F) -> CameleonResult<R>
+    where
+        F: FnOnce(&mut Camera<ControlHandle, StreamHandle, Ctxt>) -> CameleonResult<R>,
+    {
+        self.with_state(|state| {
+            let (result, written) = cameleon_genapi::record_written_nodes(|| f(&mut state.camera));
+            let disconnected = matches!(&result, Err(err) if is_disconnected(err));
+            if !written.is_empty() && !disconnected {
+                state.save_snapshot();
+            }
+            result
+        })
+    }
+
+    /// Starts streaming and returns the receiver for the `Payload`.
+    ///
+    /// The receiver keeps receiving payloads after reconnection.
+    pub fn start_streaming(&self, cap: usize) -> CameleonResult<PayloadReceiver> {
+        const DEFAULT_BUFFER_CAP: usize = 5;
+        let (sender, receiver) = payload::channel(cap, DEFAULT_BUFFER_CAP);
+        self.start_streaming_impl(sender)?;
+        Ok(receiver)
+    }
+
+    /// Starts streaming and passes each `Payload` to `f`.
+    ///
+    /// `f` keeps being called after reconnection. See also [`Camera::start_streaming_with`].
+    pub fn start_streaming_with<F>(&self, f: F) -> CameleonResult<()>
+    where
+        F: FnMut(StreamResult<&payload::Payload>) + Send + 'static,
+    {
+        self.start_streaming_impl(payload::callback(f))
+    }
+
+    /// Stops the streaming.
+    pub fn stop_streaming(&self) -> CameleonResult<()> {
+        self.with_state(|state| {
+            state.streaming = None;
+            state.camera.stop_streaming()
+        })
+    }
+
+    /// Returns `true` if the camera is currently connected.
+    #[must_use]
+    pub fn is_connected(&self) -> bool {
+        self.lock().connected
+    }
+
+    /// Returns the receiver of [`ReconnectEvent`].
+    #[must_use]
+    pub fn events(&self) -> &Receiver<ReconnectEvent> {
+        &self.events
+    }
+
+    /// Stops supervising and returns the camera.
+    ///
+    /// The returned camera is closed if it's disconnected at the time.
+    pub fn into_camera(self) -> Camera<ControlHandle, StreamHandle, Ctxt> {
+        let state = self.state.clone();
+        // Stops the supervisor thread, which holds the other reference to the state.
+        drop(self);
+        let state = Arc::try_unwrap(state)
+            .unwrap_or_else(|_| unreachable!("the supervisor thread has already stopped"));
+        state
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .camera
+    }
+
+    fn start_streaming_impl(&self, sender: PayloadSender) -> CameleonResult<()> {
+        let stream_lost_tx = self.stream_lost_tx.clone();
+        let sender = sender.inspect_errors(move |err| {
+            if matches!(err, StreamError::Disconnected) {
+                stream_lost_tx.try_send(()).ok();
+            }
+        });
+        self.with_state(|state| {
+            state.camera.start_streaming_impl(sender.clone())?;
+            state.streaming = Some(sender);
+            Ok(())
+        })
+    }
+
+    fn with_state<F, R>(&self, f: F) -> CameleonResult<R>
+    where
+        F: FnOnce(&mut State<Ctxt>) -> CameleonResult<R>,
+    {
+        let mut state = self.lock();
+        if !state.connected {
+            return Err(ControlError::Disconnected.into());
+        }
+
+        let result = f(&mut state);
+        if let Err(err) = &result {
+            if is_disconnected(err) && state.disconnect() {
+                self.events_tx
+                    .try_send(ReconnectEvent::Disconnected(state.identity.clone()))
+                    .ok();
+            }
+        }
+        result
+    }
+}
+
+impl<Ctxt> Supervisor<Ctxt> {
+    fn lock(&self) -> MutexGuard<'_, State<Ctxt>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn stop(&mut self) {
+        if let Some(stop_tx) = self.stop_tx.take() {
+            stop_tx.send(()).ok();
+        }
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+impl<Ctxt> Drop for Supervisor<Ctxt> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+struct State<Ctxt> {
+    camera: Camera<ControlHandle, StreamHandle, Ctxt>,
+    /// Information of the supervised device, used to find the device after reconnection.
+    identity: DeviceInfo,
+    connected: bool,
+    /// Features saved by [`ParamsCtxt::save_features`], loaded first after reconnection.
+    snapshot: Option<String>,
+    /// Values written through [`Supervisor::set_param`] since `snapshot` was saved.
+    journal: ParamJournal,
+    /// The sender streaming was started with, kept to resume streaming after reconnection.
+    streaming: Option<PayloadSender>,
+    /// Receives a notification when the streaming loop reports [`StreamError::Disconnected`].
+    stream_lost: Receiver<()>,
+    last_attempt: Option<Instant>,
+}
+
+impl<Ctxt> State<Ctxt>
+where
+    Ctxt: GenApiCtxt + FromXml,
+{
+    /// Releases handles of the disconnected camera. Returns `false` if it's already disconnected.
+    fn disconnect(&mut self) -> bool {
+        if !self.connected {
+            return false;
+        }
+        warn!(camera = ?self.camera.info(), "camera is disconnected");
+        self.connected = false;
+        self.last_attempt = None;
+        self.camera.strm.stop_streaming_loop().ok();
+        self.camera.strm.close().ok();
+        self.camera.ctrl.close().ok();
+        // The streaming loop is stopped, so the remaining notifications are about this
+        // disconnection.
+        while self.stream_lost.try_recv().is_ok() {}
+        true
+    }
+
+    /// Saves the features of the camera to `snapshot`.
+    ///
+    /// The journal entries of the saved features are dropped since the snapshot has their latest
+    /// values. If the features can't be saved, the previous snapshot is kept.
+    fn save_snapshot(&mut self) {
+        let mut snapshot = vec![];
+        let saved = match self.camera.params_ctxt() {
+            Ok(mut ctxt) => ctxt
+                .save_features(&mut snapshot)
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        match saved.and_then(|()| String::from_utf8(snapshot).map_err(|err| err.to_string())) {
+            Ok(snapshot) => {
+                self.journal.supersede(&snapshot);
+                self.snapshot = Some(snapshot);
+            }
+            Err(err) => warn!(%err, "failed to save features to restore after reconnection"),
+        }
+    }
+
+    /// Returns what [`Reconnection::run`] needs, so that the state isn't locked while the device
+    /// is opened and restored.
+    fn reconnection(&self) -> Reconnection {
+        Reconnection {
+            identity: self.identity.clone(),
+            timeout_duration: self.camera.ctrl.timeout_duration(),
+            retry_count: self.camera.ctrl.retry_count(),
+            buffer_capacity: self.camera.ctrl.buffer_capacity(),
+            strm_params: self.camera.strm.params().clone(),
+            snapshot: self.snapshot.clone(),
+            journal: self.journal.entries.clone(),
+            streaming: self.streaming.clone(),
+        }
+    }
+
+    /// Replaces the disconnected camera with `camera` returned by [`Reconnection::run`].
+    fn attach(&mut self, camera: Camera<ControlHandle, StreamHandle, Ctxt>) {
+        self.camera = camera;
+        self.connected = true;
+        info!(camera = ?self.camera.info(), "camera is reconnected");
+    }
+}
+
+/// A snapshot of [`State`] used to reconnect the camera without locking the state.
+///
+/// The journal and the streaming state can't change while the camera is disconnected, since
+/// [`Supervisor`] rejects requests until the camera is attached again.
+struct Reconnection {
+    identity: DeviceInfo,
+    timeout_duration: Duration,
+    retry_count: u16,
+    buffer_capacity: usize,
+    strm_params: StreamParams,
+    snapshot: Option<String>,
+    journal: Vec<JournalEntry>,
+    streaming: Option<PayloadSender>,
+}
+
+impl Reconnection {
+    /// Finds the device, opens it and restores parameters and streaming.
+    fn run<Ctxt>(self) -> CameleonResult<Camera<ControlHandle, StreamHandle, Ctxt>>
+    where
+        Ctxt: GenApiCtxt + FromXml,
+    {
+        let selectors = [
+            Selector::Guid(self.identity.guid.clone()),
+            Selector::Serial(self.identity.serial_number.clone()),
+        ];
+        let found = enumerate_cameras()?
+            .into_iter()
+            .find(|camera| {
+                let info = camera.ctrl.device_info();
+                selectors.iter().any(|selector| selector.matches(info))
+            })
+            .ok_or_else(|| {
+                CameleonError::CameraNotFound(
+                    format!("no camera has {} or {}", selectors[0], selectors[1]).into(),
+                )
+            })?;
+
+        let info = found.info().clone();
+        let mut camera = Camera::new(found.ctrl, found.strm, None, info);
+        // Carry over settings of the handles.
+        camera.ctrl.set_timeout_duration(self.timeout_duration);
+        camera.ctrl.set_retry_count(self.retry_count);
+        camera.ctrl.resize_buffer(self.buffer_capacity);
+        let params = camera.strm.params_mut();
+        params.timeout = self.strm_params.timeout;
+        params.deliver_incomplete_payload = self.strm_params.deliver_incomplete_payload;
+        params.thread = self.strm_params.thread.clone();
+        params.transfer_tuning = self.strm_params.transfer_tuning;
+
+        camera.open()?;
+        if let Err(err) = self.restore(&mut camera) {
+            camera.strm.stop_streaming_loop().ok();
+            camera.strm.close().ok();
+            camera.ctrl.close().ok();
+            return Err(err);
+        }
+        Ok(camera)
+    }
+
+    fn restore<Ctxt>(
+        &self,
+        camera: &mut Camera<ControlHandle, StreamHandle, Ctxt>,
+    ) -> CameleonResult<()>
+    where
+        Ctxt: GenApiCtxt + FromXml,
+    {
+        camera.load_context()?;
+
+        let mut ctxt: ParamsCtxt<_, _> = camera.params_ctxt()?;
+        if let Some(snapshot) = &self.snapshot {
+            // Reading from a string never fails.
+            let errors = ctxt.load_features(snapshot.as_bytes()).unwrap_or_default();
+            for error in errors {
+                let err = CameleonError::from(error.error);
+                if is_disconnected(&err) {
+                    return Err(err);
+                }
+                warn!(line = error.line, name = %error.name, %err, "failed to restore feature");
+            }
+        }
+        for entry in &self.journal {
+            if let Err(err) = ctxt.set_param(&entry.name, &entry.value) {
+                let err = CameleonError::from(err);
+                if is_disconnected(&err) {
+                    return Err(err);
+                }
+                warn!(name = %entry.name, value = ?entry.value, %err, "failed to restore parameter");
+            }
+        }
+
+        if let Some(sender) = &self.streaming {
+            camera.start_streaming_impl(sender.clone())?;
+        }
+        Ok(())
+    }
+}
+
+fn supervise<Ctxt>(
+    state: &Mutex<State<Ctxt>>,
+    watcher: &DeviceWatcher,
+    config: &ReconnectConfig,
+    stop_rx: &mpsc::Receiver<()>,
+    events_tx: &Sender<ReconnectEvent>,
+) where
+    Ctxt: GenApiCtxt + FromXml,
+{
+    loop {
+        match stop_rx.recv_timeout(TICK) {
+            Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+        }
+
+        let reconnection = {
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+
+            let mut arrived = false;
+            while let Ok(event) = watcher.try_recv() {
+                let is_same = is_same_device(&state.identity, event.device_info());
+                match event {
+                    DeviceEvent::Removed(_) if is_same && state.disconnect() => {
+                        events_tx
+                            .try_send(ReconnectEvent::Disconnected(state.identity.clone()))
+                            .ok();
+                    }
+                    DeviceEvent::Arrived(_) if is_same => arrived = true,
+                    _ => {}
+                }
+            }
+            if state.stream_lost.try_recv().is_ok() && state.disconnect() {
+                events_tx
+                    .try_send(ReconnectEvent::Disconnected(state.identity.clone()))
+                    .ok();
+            }
+
+            if state.connected {
+                continue;
+            }
+            let retry_due = state
+                .last_attempt
+                .is_none_or(|last| last.elapsed() >= config.retry_interval);
+            if !arrived && !retry_due {
+                continue;
+            }
+
+            state.last_attempt = Some(Instant::now());
+            state.reconnection()
+        };
+
+        // Enumerating and restoring the device takes a while, so it's done without the lock to
+        // keep `Supervisor::is_connected` and other requests responsive.
+        match reconnection.run() {
+            Ok(camera) => {
+                let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+                state.attach(camera);
+                events_tx
+                    .try_send(ReconnectEvent::Reconnected(state.identity.clone()))
+                    .ok();
+            }
+            Err(err) => info!(%err, "failed to reconnect the camera, will retry"),
+        }
+    }
+}
+
+fn is_same_device(identity: &DeviceInfo, info: &DeviceInfo) -> bool {
+    identity.guid.eq_ignore_ascii_case(&info.guid) || identity.serial_number == info.serial_number
+}
+
+fn is_disconnected(err: &CameleonError) -> bool {
+    match err {
+        CameleonError::ControlError(ControlError::Disconnected)
+        | CameleonError::StreamError(StreamError::Disconnected) => true,
+        CameleonError::GenApiError(GenApiError::Device(err)) => matches!(
+            err.downcast_ref::<ControlError>(),
+            Some(ControlError::Disconnected)
+        ),
+        _ => false,
+    }
+}
+
+/// Parameter values written through [`Supervisor::set_param`], in the order they were written.
+#[derive(Debug, Default)]
+struct ParamJournal {
+    entries: Vec<JournalEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct JournalEntry {
+    name: String,
+    value: ParamValue,
+    is_selector: bool,
+}
+
+impl ParamJournal {
+    /// Records a write.
+    ///
+    /// The previous write to the same node is dropped unless a selector was written after it,
+    /// since the previous write may target another register selected by the selector. A write to
+    /// a selector only replaces the previous write if nothing was written in between.
+    fn record(&mut self, name: &str, value: ParamValue, is_selector: bool) {
+        for (i, entry) in self.entries.iter().enumerate().rev() {
+            if entry.name == name {
+                self.entries.remove(i);
+                break;
+            }
+            if entry.is_selector || is_selector {
+                break;
+            }
+        }
+
+        self.entries.push(JournalEntry {
+            name: name.to_string(),
+            value,
+            is_selector,
+        });
+    }
+
+    /// Drops the writes of the features in `snapshot` saved by [`ParamsCtxt::save_features`],
+    /// since it has their latest values. Writes to selectors are kept, since the writes after
+    /// them may target the registers they select.
+    fn supersede(&mut self, snapshot: &str) {
+        let saved: HashSet<&str> = snapshot
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        self.entries
+            .retain(|entry| entry.is_selector || !saved.contains(entry.name.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(journal: &ParamJournal) -> Vec<(&str, &ParamValue)> {
+        journal
+            .entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), &entry.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_journal_replaces_previous_write() {
+        let mut journal = ParamJournal::default();
+        journal.record("ExposureTime", 100.0.into(), false);
+        journal.record("Gain", 1.0.into(), false);
+        journal.record("ExposureTime", 200.0.into(), false);
+
+        assert_eq!(
+            names(&journal),
+            vec![
+                ("Gain", &ParamValue::Float(1.0)),
+                ("ExposureTime", &ParamValue::Float(200.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_journal_keeps_writes_under_other_selector() {
+        let selector = |s: &str| ParamValue::Enumeration(s.into());
+        let mut journal = ParamJournal::default();
+        journal.record("GainSelector", selector("Red"), true);
+        journal.record("Gain", 1.0.into(), false);
+        journal.record("GainSelector", selector("Blue"), true);
+        journal.record("Gain", 2.0.into(), false);
+        journal.record("Gain", 3.0.into(), false);
+        // Replaces the previous selector write since nothing was written in between.
+        journal.record("GainSelector", selector("Green"), true);
+        journal.record("GainSelector", selector("All"), true);
+
+        assert_eq!(
+            names(&journal),
+            vec![
+                ("GainSelector", &selector("Red")),
+                ("Gain", &ParamValue::Float(1.0)),
+                ("GainSelector", &selector("Blue")),
+                ("Gain", &ParamValue::Float(3.0)),
+                ("GainSelector", &selector("All")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_journal_superseded_by_snapshot() {
+        let selector = |s: &str| ParamValue::Enumeration(s.into());
+        let mut journal = ParamJournal::default();
+        journal.record("ExposureTime", 100.0.into(), false);
+        journal.record("GainSelector", selector("Red"), true);
+        journal.record("Gain", 1.0.into(), false);
+        journal.record("DeviceUserID", ParamValue::String("cam0".into()), false);
+
+        let snapshot = "# GenApi persistence file (version 1.0.0)\n\
+                        ExposureTime\t200\n\
+                        GainSelector\tRed\n\
+                        Gain\t2\n";
+        journal.supersede(snapshot);
+        assert_eq!(
+            names(&journal),
+            vec![
+                ("GainSelector", &selector("Red")),
+                ("DeviceUserID", &ParamValue::String("cam0".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_disconnected() {
+        assert!(is_disconnected(&ControlError::Disconnected.into()));
+        assert!(is_disconnected(&StreamError::Disconnected.into()));
+        let genapi_err = GenApiError::Device(Box::new(ControlError::Disconnected));
+        assert!(is_disconnected(&genapi_err.into()));
+        assert!(!is_disconnected(&ControlError::Timeout.into()));
+    }
+}
@@ -436,6 +436,25 @@ impl Sbrm {
         Ok(self.sirm_address(device)?.map(Sirm::new))
     }
 
+    /// Return [`Sirm`] of the `index`-th stream channel if it's available.
+    ///
+    /// `Sirm`s of the stream channels are placed contiguously from [`Self::sirm_address`], and
+    /// each of them is [`Self::sirm_length`] long.
+    pub fn sirm_at<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+        index: u32,
+    ) -> ControlResult<Option<Sirm>> {
+        if index >= self.number_of_stream_channel(device)? {
+            return Ok(None);
+        }
+        let (Some(addr), Some(len)) = (self.sirm_address(device)?, self.sirm_length(device)?)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Sirm::new(addr + u64::from(index) * u64::from(len))))
+    }
+
     /// The initial address of `Sirm`.
     ///
     /// NOTE: Some device doesn't support this feature.
@@ -565,7 +584,12 @@ impl Sirm {
     ) -> ControlResult<usize> {
         let si_info: u32 = self.read_register(device, sirm::SI_INFO)?;
         // Upper 8 bits specifies the exp of the alignment.
-        Ok(1 << (si_info >> 24_i32))
+        let exp = si_info >> 24_i32;
+        1_usize.checked_shl(exp).ok_or_else(|| {
+            ControlError::InvalidDevice(
+                format!("payload size alignment 2^{exp} is too large").into(),
+            )
+        })
     }
 
     /// Enables stream.
@@ -1210,3 +1234,61 @@ impl_dump_bytes_for_numeric!(i8);
 impl_dump_bytes_for_numeric!(i16);
 impl_dump_bytes_for_numeric!(i32);
 impl_dump_bytes_for_numeric!(i64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeCtrl, SIRM_ADDRESS, SIRM_LENGTH};
+
+    #[test]
+    fn test_sirm_at() {
+        let mut ctrl = FakeCtrl::u3v(2);
+        let sbrm = Abrm::new(&mut ctrl).unwrap().sbrm(&mut ctrl).unwrap();
+        assert_eq!(sbrm.number_of_stream_channel(&mut ctrl).unwrap(), 2);
+
+        let first = sbrm.sirm_at(&mut ctrl, 0).unwrap().unwrap();
+        assert_eq!(first.sirm_addr, SIRM_ADDRESS);
+        assert_eq!(
+            sbrm.sirm(&mut ctrl).unwrap().unwrap().sirm_addr,
+            first.sirm_addr
+        );
+        let second = sbrm.sirm_at(&mut ctrl, 1).unwrap().unwrap();
+        assert_eq!(second.sirm_addr, SIRM_ADDRESS + u64::from(SIRM_LENGTH));
+
+        assert!(sbrm.sirm_at(&mut ctrl, 2).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_sirm_at_unavailable() {
+        let mut ctrl = FakeCtrl::u3v(2);
+        let sbrm_address = Abrm::new(&mut ctrl)
+            .unwrap()
+            .sbrm_address(&mut ctrl)
+            .unwrap();
+        // Clear the bit indicating `SIRM` is available.
+        ctrl.write_le(
+            sbrm_address + sbrm::U3VCP_CAPABILITY_REGISTER.0,
+            &0_u64.to_le_bytes(),
+        );
+
+        let sbrm = Sbrm::new(&mut ctrl, sbrm_address).unwrap();
+        assert!(sbrm.sirm_at(&mut ctrl, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_enable_stream_of_secondary_channel() {
+        let mut ctrl = FakeCtrl::u3v(2);
+        let sbrm = Abrm::new(&mut ctrl).unwrap().sbrm(&mut ctrl).unwrap();
+        let first = sbrm.sirm_at(&mut ctrl, 0).unwrap().unwrap();
+        let second = sbrm.sirm_at(&mut ctrl, 1).unwrap().unwrap();
+
+        second.enable_stream(&mut ctrl).unwrap();
+        assert!(second.is_stream_enable(&mut ctrl).unwrap());
+        assert!(!first.is_stream_enable(&mut ctrl).unwrap());
+        let si_control = SIRM_ADDRESS + u64::from(SIRM_LENGTH) + sirm::SI_CONTROL.0;
+        assert_eq!(ctrl.read_u32(si_control), 1);
+
+        second.disable_stream(&mut ctrl).unwrap();
+        assert!(!second.is_stream_enable(&mut ctrl).unwrap());
+    }
+}
@@ -16,11 +16,18 @@ use tracing::{error, info, warn};
 
 use crate::{
     camera::PayloadStream,
-    payload::{ImageInfo, Payload, PayloadSender, PayloadType},
-    ControlError, ControlResult, DeviceControl, DeviceIoError, StreamError, StreamResult,
+    payload::{
+        self, ImageInfo, Payload, PayloadReceiver, PayloadSender, PayloadStatus, PayloadType,
+    },
+    CameleonResult, ControlError, ControlResult, DeviceControl, DeviceIoError, StreamError,
+    StreamResult,
 };
 
-use super::register_map::Abrm;
+use super::{
+    control_handle::{enable_stream_channel, SharedControlHandle},
+    register_map::{Abrm, Sirm},
+    transfer_tuning::TransferTuning,
+};
 
 /// This type is used to receive stream packets from the device.
 pub struct StreamHandle {
@@ -28,6 +35,12 @@ pub struct StreamHandle {
     pub inner: Arc<Mutex<u3v::ReceiveChannel>>,
     /// Parameters for streaming.
     params: StreamParams,
+    /// Statistics of the current streaming loop.
+    statistics: Arc<Mutex<StreamStatistics>>,
+    /// Index of the stream channel that the handle receives from.
+    channel_index: u32,
+    /// Control handle that the streaming loop restarts the stream channel with.
+    recovery_ctrl: Option<SharedControlHandle>,
     cancellation_tx: Option<mpsc::SyncSender<()>>,
 }
 
@@ -44,11 +57,103 @@ macro_rules! unwrap_or_poisoned {
 impl StreamHandle {
     pub(super) fn new(device: &u3v::Device) -> ControlResult<Option<Self>> {
         let inner = device.stream_channel()?;
-        Ok(inner.map(|inner| Self {
+        Ok(inner.map(|inner| Self::with_channel(inner, 0)))
+    }
+
+    /// Returns handles of all stream channels of the device, ordered by their channel index.
+    pub(super) fn new_all(device: &u3v::Device) -> ControlResult<Vec<Self>> {
+        let channels = device.stream_channels()?;
+        Ok(channels
+            .into_iter()
+            .zip(0..)
+            .map(|(inner, index)| Self::with_channel(inner, index))
+            .collect())
+    }
+
+    fn with_channel(inner: u3v::ReceiveChannel, channel_index: u32) -> Self {
+        Self {
             inner: Arc::new(Mutex::new(inner)),
             params: StreamParams::default(),
+            statistics: Arc::default(),
+            channel_index,
+            recovery_ctrl: None,
             cancellation_tx: None,
-        }))
+        }
+    }
+
+    /// Return index of the stream channel that the handle receives from.
+    ///
+    /// The handle of [`crate::Camera`] always receives from the channel `0`.
+    #[must_use]
+    pub fn channel_index(&self) -> u32 {
+        self.channel_index
+    }
+
+    /// Sets the control handle that the streaming loop uses to restart the stream channel.
+    ///
+    /// When the stream gets misaligned with the blocks sent from the device, the streaming loop
+    /// drains the stream channel until a valid leader arrives. If no valid leader arrives, the
+    /// stream channel is restarted with `ctrl` by disabling and enabling it in `SIRM`. Without
+    /// the handle, the error is sent to the receiver instead, and the stream needs to be restarted
+    /// by the user.
+    ///
+    /// The handle takes effect when a streaming loop starts.
+    pub fn set_recovery_control(&mut self, ctrl: Option<SharedControlHandle>) {
+        self.recovery_ctrl = ctrl;
+    }
+
+    /// Enables the stream channel of the handle, and starts streaming loop.
+    ///
+    /// This is mainly for secondary stream channels obtained by
+    /// [`super::enumerate_cameras_with_streams`], whose streaming isn't managed by
+    /// [`crate::Camera`]. Note that the device also needs `AcquisitionStart` to be executed, which
+    /// is done by [`crate::Camera::start_streaming`].
+    pub fn start_streaming<Ctrl: DeviceControl>(
+        &mut self,
+        ctrl: &mut Ctrl,
+        cap: usize,
+    ) -> CameleonResult<PayloadReceiver> {
+        const DEFAULT_BUFFER_CAP: usize = 5;
+        if self.is_loop_running() {
+            return Err(StreamError::InStreaming.into());
+        }
+
+        let sirm = self.sirm(ctrl)?;
+        enable_stream_channel(ctrl, sirm)?;
+        let (sender, receiver) = payload::channel(cap, DEFAULT_BUFFER_CAP);
+        self.start_streaming_loop(sender, ctrl)?;
+        Ok(receiver)
+    }
+
+    /// Stops streaming loop, and disables the stream channel of the handle.
+    pub fn stop_streaming<Ctrl: DeviceControl>(&mut self, ctrl: &mut Ctrl) -> CameleonResult<()> {
+        self.stop_streaming_loop()?;
+        let sirm = self.sirm(ctrl)?;
+        sirm.disable_stream(ctrl)?;
+        Ok(())
+    }
+
+    fn sirm<Ctrl: DeviceControl + ?Sized>(&self, ctrl: &mut Ctrl) -> ControlResult<Sirm> {
+        sirm_at(ctrl, self.channel_index)
+    }
+
+    /// Rewrites the transfer sizes of the stream channel according to
+    /// [`StreamParams::transfer_tuning`].
+    fn apply_transfer_tuning(&self, ctrl: &mut dyn DeviceControl) -> ControlResult<()> {
+        let sirm = self.sirm(ctrl)?;
+        let plan = self.params.transfer_tuning.plan(ctrl, sirm)?;
+        info!(?plan, "tune transfer sizes");
+
+        // It's forbidden to set SIRM registers while stream is enabled.
+        let is_enabled = sirm.is_stream_enable(ctrl)?;
+        if is_enabled {
+            sirm.disable_stream(ctrl)?;
+        }
+        plan.write(ctrl, sirm)?;
+        if is_enabled {
+            sirm.enable_stream(ctrl)?;
+        }
+        Ok(())
     }
 
     /// Return params.
@@ -61,6 +166,13 @@ impl StreamHandle {
     pub fn params_mut(&mut self) -> &mut StreamParams {
         &mut self.params
     }
+
+    /// Return statistics of the current streaming loop.
+    ///
+    /// The statistics are reset every time a streaming loop starts.
+    pub fn statistics(&self) -> StreamStatistics {
+        *self.statistics.lock().unwrap()
+    }
 }
 
 impl PayloadStream for StreamHandle {
@@ -88,11 +200,20 @@ impl PayloadStream for StreamHandle {
         sender: PayloadSender,
         ctrl: &mut dyn DeviceControl,
     ) -> StreamResult<()> {
-        self.params = StreamParams::from_control(ctrl).map_err(|e| {
-            StreamError::Io(DeviceIoError::msg(format!(
-                "failed to setup streaming parameters: {e}"
-            )))
-        })?;
+        if self.params.transfer_tuning != TransferTuning::Fixed {
+            self.apply_transfer_tuning(ctrl).map_err(|e| {
+                StreamError::Io(DeviceIoError::msg(format!(
+                    "failed to tune transfer sizes: {e}"
+                )))
+            })?;
+        }
+        let transfer_params =
+            StreamParams::from_control_at(ctrl, self.channel_index).map_err(|e| {
+                StreamError::Io(DeviceIoError::msg(format!(
+                    "failed to setup streaming parameters: {e}"
+                )))
+            })?;
+        self.params.update_transfer_params(transfer_params);
 
         if self.is_loop_running() {
             return Err(StreamError::InStreaming);
@@ -100,17 +221,26 @@ impl PayloadStream for StreamHandle {
 
         // Sync channel of capacity 0 is a special rendez-vous mode, where every send() blocks.
         let (cancellation_tx, cancellation_rx) = mpsc::sync_channel(0);
-        self.cancellation_tx = Some(cancellation_tx);
+        *unwrap_or_poisoned!(self.statistics.lock())? = StreamStatistics::default();
 
         let strm_loop = StreamingLoop {
-            inner: self.inner.clone(),
             params: self.params.clone(),
+            statistics: self.statistics.clone(),
             sender,
             cancellation_rx,
         };
-        std::thread::spawn(|| {
-            strm_loop.run();
-        });
+        let inner = self.inner.clone();
+        let channel_index = self.channel_index;
+        let ctrl = self.recovery_ctrl.clone();
+        spawn_streaming_thread(&self.params.thread, move || {
+            let mut channel = inner.lock().unwrap();
+            strm_loop.run(&mut ChannelSource {
+                channel: &mut channel,
+                channel_index,
+                ctrl,
+            });
+        })?;
+        self.cancellation_tx = Some(cancellation_tx);
 
         info!("start streaming loop successfully");
         Ok(())
@@ -149,21 +279,190 @@ impl From<StreamHandle> for Box<dyn PayloadStream> {
     }
 }
 
+/// Statistics of a streaming loop.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamStatistics {
+    /// The number of payloads sent to the receiver.
+    pub delivered_payload_count: u64,
+
+    /// The number of payloads never received from the device, detected by gaps in the block
+    /// id sequence.
+    pub lost_payload_count: u64,
+
+    /// The number of payloads received from the device but discarded because the receiver was
+    /// full.
+    pub dropped_payload_count: u64,
+
+    /// The number of payloads received from the device but discarded because they were broken,
+    /// e.g. their trailer was invalid or they were incomplete.
+    pub discarded_payload_count: u64,
+
+    /// The number of times the stream was resynchronized with the device after a leader without
+    /// the magic number was received.
+    pub resync_count: u64,
+
+    /// The number of times the stream channel was restarted because no valid leader arrived while
+    /// resynchronizing, see [`StreamHandle::set_recovery_control`].
+    pub restart_count: u64,
+
+    /// Block id of the last leader received from the device.
+    pub last_block_id: Option<u64>,
+}
+
+/// Tracks continuity of block ids sent from the device.
+#[derive(Debug, Default)]
+struct BlockIdTracker {
+    next_block_id: Option<u64>,
+}
+
+impl BlockIdTracker {
+    /// Returns the number of block ids skipped right before `block_id`.
+    ///
+    /// A block id smaller than expected means the device restarted its sequence, so it's not
+    /// reported as a loss.
+    fn track(&mut self, block_id: u64) -> u64 {
+        let lost = match self.next_block_id {
+            Some(expected) if block_id > expected => block_id - expected,
+            _ => 0,
+        };
+        self.next_block_id = Some(block_id.wrapping_add(1));
+        lost
+    }
+}
+
+/// The bulk-in endpoint that the streaming loop receives blocks from.
+trait BlockSource {
+    /// Receives the leader, the payload and the trailer of a block into the buffers, and
+    /// returns the size of the received payload.
+    ///
+    /// The leader isn't received if `leader_buf` is `None`, i.e. the leader of the block has
+    /// already been received by [`Self::recv_transfer`].
+    fn recv_block(
+        &mut self,
+        params: &StreamParams,
+        leader_buf: Option<&mut [u8]>,
+        payload_buf: &mut [u8],
+        trailer_buf: &mut [u8],
+    ) -> StreamResult<usize>;
+
+    /// Receives a single transfer into `buf`, and returns its size.
+    fn recv_transfer(&mut self, buf: &mut [u8], timeout: Duration) -> StreamResult<usize>;
+
+    /// Halts and clears the endpoint, which makes the device discard the block being
+    /// transferred.
+    fn reset(&mut self, timeout: Duration) -> StreamResult<()>;
+
+    /// Restarts the stream channel by disabling and enabling it in `SIRM`.
+    fn restart(&mut self, timeout: Duration) -> StreamResult<()>;
+}
+
+/// The stream channel that a streaming loop receives from.
+struct ChannelSource<'a> {
+    channel: &'a mut u3v::ReceiveChannel,
+    channel_index: u32,
+    /// The control handle to restart the stream channel, see
+    /// [`StreamHandle::set_recovery_control`].
+    ctrl: Option<SharedControlHandle>,
+}
+
+impl BlockSource for ChannelSource<'_> {
+    fn recv_block(
+        &mut self,
+        params: &StreamParams,
+        leader_buf: Option<&mut [u8]>,
+        payload_buf: &mut [u8],
+        trailer_buf: &mut [u8],
+    ) -> StreamResult<usize> {
+        let mut async_pool = AsyncPool::new(self.channel);
+        let has_leader = leader_buf.is_some();
+        if let Some(leader_buf) = leader_buf {
+            read_leader(&mut async_pool, params, leader_buf)?;
+        }
+        read_payload(&mut async_pool, params, payload_buf)?;
+        read_trailer(&mut async_pool, params, trailer_buf)?;
+
+        // We've submitted the bulk transfers, now wait for them.
+        let mut is_leader = has_leader;
+        let mut last_buf_len = None;
+        let mut payload_len = 0;
+
+        while !async_pool.is_empty() {
+            let len = async_pool.poll(params.timeout)?;
+
+            if is_leader {
+                is_leader = false;
+            } else {
+                payload_len += len;
+            }
+
+            last_buf_len = Some(len);
+        }
+
+        Ok(payload_len - last_buf_len.unwrap())
+    }
+
+    fn recv_transfer(&mut self, buf: &mut [u8], timeout: Duration) -> StreamResult<usize> {
+        Ok(self.channel.recv(buf, timeout)?)
+    }
+
+    fn reset(&mut self, timeout: Duration) -> StreamResult<()> {
+        self.channel.set_halt(timeout)?;
+        self.channel.clear_halt()?;
+        Ok(())
+    }
+
+    fn restart(&mut self, timeout: Duration) -> StreamResult<()> {
+        let ctrl = self.ctrl.as_mut().ok_or_else(|| {
+            StreamError::Io(DeviceIoError::msg(
+                "can't restart the stream channel without a control handle",
+            ))
+        })?;
+        let to_stream_error = |e: ControlError| {
+            StreamError::Io(DeviceIoError::msg(format!(
+                "failed to restart the stream channel: {e}"
+            )))
+        };
+
+        let sirm = sirm_at(ctrl, self.channel_index).map_err(to_stream_error)?;
+        sirm.disable_stream(ctrl).map_err(to_stream_error)?;
+        self.channel.set_halt(timeout)?;
+        self.channel.clear_halt()?;
+        sirm.enable_stream(ctrl).map_err(to_stream_error)
+    }
+}
+
+/// The result of draining the endpoint in [`StreamingLoop::resync`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Drained {
+    /// A valid leader arrived, and it's copied to the leader buffer.
+    Leader,
+    /// No transfer arrived, so the next transfer starts a new block.
+    Idle,
+    /// No valid leader arrived in the transfers of a whole block.
+    Exhausted,
+}
+
 struct StreamingLoop {
-    inner: Arc<Mutex<u3v::ReceiveChannel>>,
     params: StreamParams,
+    statistics: Arc<Mutex<StreamStatistics>>,
     sender: PayloadSender,
     cancellation_rx: mpsc::Receiver<()>,
 }
 
 impl StreamingLoop {
-    fn run(self) {
+    fn run(self, source: &mut impl BlockSource) {
         let mut trailer_buf = vec![0; self.params.trailer_size];
         let mut payload_buf_opt = None;
         let mut leader_buf = vec![0; self.params.leader_size];
-        let inner = self.inner.lock().unwrap();
-
-        'outer: loop {
+        // Used to drain the endpoint while resynchronizing, allocated on the first resync.
+        let mut drain_buf = vec![];
+        // `true` if `leader_buf` holds a leader received while resynchronizing.
+        let mut has_leader = false;
+        let mut block_id_tracker = BlockIdTracker::default();
+        // The number of payloads lost since the last payload sent to the receiver.
+        let mut lost_before = 0;
+
+        loop {
             // Stop the loop when
             // 1. `cancellation_tx` sends signal.
             // 2. `cancellation_tx` is dropped.
@@ -188,60 +487,32 @@ impl StreamingLoop {
                 },
             };
 
-            let mut async_pool = AsyncPool::new(&inner);
-
-            if let Err(err) = read_leader(&mut async_pool, &self.params, &mut leader_buf) {
-                // Report and send error if the error is fatal.
-                if matches!(err, StreamError::Io(..) | StreamError::Disconnected) {
-                    error!(?err);
-                    self.sender.try_send(Err(err)).ok();
-                }
-                payload_buf_opt = Some(payload_buf);
-                continue;
-            };
-
-            if let Err(err) = read_payload(&mut async_pool, &self.params, &mut payload_buf) {
-                warn!(?err);
-                // Reuse `payload_buf`.
-                payload_buf_opt = Some(payload_buf);
-                self.sender.try_send(Err(err)).ok();
-                continue;
-            };
-
-            if let Err(err) = read_trailer(&mut async_pool, &self.params, &mut trailer_buf) {
-                warn!(?err);
-                // Reuse `payload_buf`.
-                payload_buf_opt = Some(payload_buf);
-                self.sender.try_send(Err(err)).ok();
-                continue;
+            let leader_buf_opt = if has_leader {
+                None
+            } else {
+                Some(leader_buf.as_mut_slice())
             };
-
-            // We've submitted the bulk transfers, now wait for them.
-            let mut first_buf_len = None;
-            let mut last_buf_len = None;
-            let mut payload_len = 0;
-
-            while !async_pool.is_empty() {
-                let len = match async_pool.poll(self.params.timeout) {
-                    Ok(len) => len,
-                    Err(err) => {
+            has_leader = false;
+            let payload_len = match source.recv_block(
+                &self.params,
+                leader_buf_opt,
+                &mut payload_buf,
+                &mut trailer_buf,
+            ) {
+                Ok(len) => len,
+                Err(err) => {
+                    // Report fatal errors louder.
+                    if matches!(err, StreamError::Io(..) | StreamError::Disconnected) {
+                        error!(?err);
+                    } else {
                         warn!(?err);
-                        // Can't reuse `payload_buf` because we're in a loop.
-                        self.sender.try_send(Err(err.into())).ok();
-                        continue 'outer;
                     }
-                };
-
-                if first_buf_len.is_none() {
-                    first_buf_len = Some(len);
-                } else {
-                    payload_len += len;
+                    // Reuse `payload_buf`.
+                    payload_buf_opt = Some(payload_buf);
+                    self.sender.try_send(Err(err)).ok();
+                    continue;
                 }
-
-                last_buf_len = Some(len);
-            }
-
-            let payload_len = payload_len - last_buf_len.unwrap();
+            };
 
             // We received the data from the bulk transfers, try to parse stuff now.
             let leader = match u3v_stream::Leader::parse(&leader_buf)
@@ -253,16 +524,39 @@ impl StreamingLoop {
                     // Reuse `payload_buf`.
                     payload_buf_opt = Some(payload_buf);
                     self.sender.try_send(Err(err)).ok();
+                    // A leader without the magic number means that the transfers are misaligned
+                    // with the blocks, e.g. because a transfer was lost.
+                    if !has_magic(&leader_buf, u3v_stream::Leader::LEADER_MAGIC) {
+                        if drain_buf.is_empty() {
+                            drain_buf = vec![0; self.params.maximum_transfer_size()];
+                        }
+                        has_leader = self.resync(source, &mut leader_buf, &mut drain_buf);
+                    }
                     continue;
                 }
             };
 
+            let block_id = leader.block_id();
+            let lost = block_id_tracker.track(block_id);
+            if lost != 0 {
+                warn!(block_id, lost, "block id gap detected");
+            }
+            lost_before += lost;
+            {
+                let mut statistics = self.statistics.lock().unwrap();
+                statistics.lost_payload_count += lost;
+                statistics.last_block_id = Some(block_id);
+            }
+
             let trailer = match u3v_stream::Trailer::parse(&trailer_buf)
                 .map_err(|e| StreamError::InvalidPayload(format!("invalid trailer: {e}").into()))
             {
                 Ok(trailer) => trailer,
                 Err(err) => {
                     warn!(?err);
+                    // The block id is already tracked, so count the block here.
+                    lost_before += 1;
+                    self.statistics.lock().unwrap().discarded_payload_count += 1;
                     // Reuse `payload_buf`.
                     payload_buf_opt = Some(payload_buf);
                     self.sender.try_send(Err(err)).ok();
@@ -275,13 +569,17 @@ impl StreamingLoop {
                 payload_buf,
                 read_payload_size: payload_len,
                 trailer,
+                deliver_incomplete: self.params.deliver_incomplete_payload,
             }
             .build();
 
-            let payload = match builder_result {
+            let mut payload = match builder_result {
                 Ok(payload) => payload,
                 Err(e) => {
                     warn!(?e);
+                    // The block id is already tracked, so count the block here.
+                    lost_before += 1;
+                    self.statistics.lock().unwrap().discarded_payload_count += 1;
                     // Can't reuse `payload_buf` because we moved it
                     // into PayloadBuilder above.
                     payload_buf_opt = None;
@@ -289,50 +587,148 @@ impl StreamingLoop {
                     continue;
                 }
             };
+            payload.lost_before = lost_before;
 
-            if let Err(err) = self.sender.try_send(Ok(payload)) {
+            // A callback sender runs the user's callback here, which may query the statistics.
+            let sent = self.sender.try_send(Ok(payload));
+            let mut statistics = self.statistics.lock().unwrap();
+            if let Err(err) = sent {
                 warn!(?err);
+                // The receiver can't notice the dropped payload, so report it with the next one.
+                lost_before += 1;
+                statistics.dropped_payload_count += 1;
+            } else {
+                lost_before = 0;
+                statistics.delivered_payload_count += 1;
+            }
+        }
+    }
+
+    /// Resynchronizes the stream with the device after a leader without the magic number is
+    /// received. Returns `true` if `leader_buf` holds a valid leader received while draining.
+    ///
+    /// Halting the bulk-in endpoint makes the device discard the block being transferred, then
+    /// transfers are drained until a valid leader arrives. If no valid leader arrives in the
+    /// transfers of a whole block, the stream channel is restarted.
+    fn resync(
+        &self,
+        source: &mut impl BlockSource,
+        leader_buf: &mut [u8],
+        drain_buf: &mut [u8],
+    ) -> bool {
+        let drained = source
+            .reset(self.params.timeout)
+            .and_then(|()| self.drain(source, leader_buf, drain_buf));
+
+        let (result, has_leader) = match drained {
+            Ok(Drained::Leader) => (Ok(()), true),
+            Ok(Drained::Idle) => (Ok(()), false),
+            Ok(Drained::Exhausted) => {
+                warn!("no valid leader arrived, restart the stream channel");
+                let result = source.restart(self.params.timeout).map(|()| {
+                    self.statistics.lock().unwrap().restart_count += 1;
+                });
+                (result, false)
+            }
+            Err(err) => (Err(err), false),
+        };
+
+        match result {
+            Ok(()) => {
+                info!("resynchronized the stream with the device");
+                self.statistics.lock().unwrap().resync_count += 1;
+                has_leader
+            }
+            Err(err) => {
+                error!(?err);
+                self.sender.try_send(Err(err)).ok();
+                false
+            }
+        }
+    }
+
+    /// Receives transfers until a valid leader arrives, and copies it to `leader_buf`.
+    fn drain(
+        &self,
+        source: &mut impl BlockSource,
+        leader_buf: &mut [u8],
+        drain_buf: &mut [u8],
+    ) -> StreamResult<Drained> {
+        for _ in 0..self.params.transfers_per_block() {
+            let len = match source.recv_transfer(drain_buf, self.params.timeout) {
+                Ok(len) => len,
+                Err(StreamError::Timeout) => return Ok(Drained::Idle),
+                Err(err) => return Err(err),
+            };
+            let transfer = &drain_buf[..len];
+            if len <= leader_buf.len() && u3v_stream::Leader::parse(transfer).is_ok() {
+                leader_buf[..len].copy_from_slice(transfer);
+                return Ok(Drained::Leader);
             }
         }
+        Ok(Drained::Exhausted)
     }
 }
 
+/// Returns `true` if `buf` starts with `magic`.
+fn has_magic(buf: &[u8], magic: u32) -> bool {
+    buf.starts_with(&magic.to_le_bytes())
+}
+
 struct PayloadBuilder<'a> {
     leader: u3v_stream::Leader<'a>,
     payload_buf: Vec<u8>,
     read_payload_size: usize,
     trailer: u3v_stream::Trailer<'a>,
+    deliver_incomplete: bool,
 }
 
 impl PayloadBuilder<'_> {
     fn build(self) -> StreamResult<Payload> {
-        let payload_status = self.trailer.payload_status();
-        if payload_status != u3v_stream::PayloadStatus::Success {
+        let mut status = match self.trailer.payload_status() {
+            u3v_stream::PayloadStatus::Success => PayloadStatus::Complete,
+            u3v_stream::PayloadStatus::DataDiscarded => PayloadStatus::DataDiscarded,
+            u3v_stream::PayloadStatus::DataOverrun => PayloadStatus::DataOverrun,
+        };
+        if status != PayloadStatus::Complete && !self.deliver_incomplete {
             return Err(StreamError::InvalidPayload(
-                format!("trailer status indicates error: {payload_status:?}").into(),
+                format!("trailer status indicates error: {status:?}").into(),
             ));
         }
 
-        if self.trailer.valid_payload_size() > self.read_payload_size as u64 {
-            let err_msg = format!("the actual read payload size is smaller than the size specified in the trailer: expected {}, but got {}",
-                                  self.trailer.valid_payload_size(),
-                                  self.read_payload_size);
-            return Err(StreamError::InvalidPayload(err_msg.into()));
+        let mut valid_payload_size = self.trailer.valid_payload_size() as usize;
+        if valid_payload_size > self.read_payload_size {
+            if !self.deliver_incomplete {
+                let err_msg = format!("the actual read payload size is smaller than the size specified in the trailer: expected {}, but got {}",
+                                      valid_payload_size,
+                                      self.read_payload_size);
+                return Err(StreamError::InvalidPayload(err_msg.into()));
+            }
+
+            valid_payload_size = self.read_payload_size;
+            if status == PayloadStatus::Complete {
+                status = PayloadStatus::Truncated;
+            }
         }
 
         match self.leader.payload_type() {
-            u3v_stream::PayloadType::Image => self.build_image_payload(),
-            u3v_stream::PayloadType::ImageExtendedChunk => self.build_image_extended_payload(),
-            u3v_stream::PayloadType::Chunk => self.build_chunk_payload(),
+            u3v_stream::PayloadType::Image => self.build_image_payload(valid_payload_size, status),
+            u3v_stream::PayloadType::ImageExtendedChunk => {
+                self.build_image_extended_payload(valid_payload_size, status)
+            }
+            u3v_stream::PayloadType::Chunk => self.build_chunk_payload(valid_payload_size, status),
         }
     }
 
-    fn build_image_payload(self) -> StreamResult<Payload> {
+    fn build_image_payload(
+        self,
+        valid_payload_size: usize,
+        status: PayloadStatus,
+    ) -> StreamResult<Payload> {
         let leader: u3v_stream::ImageLeader = self.specific_leader_as()?;
         let trailer: u3v_stream::ImageTrailer = self.specific_trailer_as()?;
 
         let id = self.leader.block_id();
-        let valid_payload_size = self.trailer.valid_payload_size() as usize;
 
         let image_info = Some(ImageInfo {
             width: leader.width() as usize,
@@ -350,10 +746,16 @@ impl PayloadBuilder<'_> {
             payload: self.payload_buf,
             valid_payload_size,
             timestamp: leader.timestamp(),
+            status,
+            lost_before: 0,
         })
     }
 
-    fn build_image_extended_payload(self) -> StreamResult<Payload> {
+    fn build_image_extended_payload(
+        self,
+        valid_payload_size: usize,
+        status: PayloadStatus,
+    ) -> StreamResult<Payload> {
         const CHUNK_ID_LEN: usize = 4;
         const CHUNK_SIZE_LEN: usize = 4;
 
@@ -361,7 +763,6 @@ impl PayloadBuilder<'_> {
         let trailer: u3v_stream::ImageExtendedChunkTrailer = self.specific_trailer_as()?;
 
         let id = self.leader.block_id();
-        let valid_payload_size = self.trailer.valid_payload_size() as usize;
 
         // Extract image size from the first chunk of the paload data.
         // Chunk data is designed to be decoded from the last byte to the first byte.
@@ -403,15 +804,20 @@ impl PayloadBuilder<'_> {
             payload: self.payload_buf,
             valid_payload_size,
             timestamp: leader.timestamp(),
+            status,
+            lost_before: 0,
         })
     }
 
-    fn build_chunk_payload(self) -> StreamResult<Payload> {
+    fn build_chunk_payload(
+        self,
+        valid_payload_size: usize,
+        status: PayloadStatus,
+    ) -> StreamResult<Payload> {
         let leader: u3v_stream::ChunkLeader = self.specific_leader_as()?;
         let _: u3v_stream::ChunkTrailer = self.specific_trailer_as()?;
 
         let id = self.leader.block_id();
-        let valid_payload_size = self.trailer.valid_payload_size() as usize;
 
         Ok(Payload {
             id,
@@ -420,6 +826,8 @@ impl PayloadBuilder<'_> {
             payload: self.payload_buf,
             valid_payload_size,
             timestamp: leader.timestamp(),
+            status,
+            lost_before: 0,
         })
     }
 
@@ -461,6 +869,50 @@ pub struct StreamParams {
 
     /// Timeout duration of each transaction between device.
     pub timeout: Duration,
+
+    /// If `true`, payloads that the device reports as incomplete or that are shorter than the
+    /// size specified in the trailer are delivered instead of being discarded.
+    ///
+    /// See [`Payload::status`] to tell such payloads apart from complete ones.
+    ///
+    /// This value is `false` by default and is kept as is when a streaming loop starts.
+    pub deliver_incomplete_payload: bool,
+
+    /// Configuration of the thread that runs the streaming loop.
+    ///
+    /// This value is kept as is when a streaming loop starts.
+    pub thread: StreamThreadConfig,
+
+    /// How to choose the transfer sizes of the stream channel.
+    ///
+    /// Unless this is [`TransferTuning::Fixed`], the transfer sizes in `SIRM` are rewritten
+    /// when a streaming loop starts. This value is kept as is when a streaming loop starts.
+    pub transfer_tuning: TransferTuning,
+}
+
+/// Configuration of the thread that runs the streaming loop.
+///
+/// All settings are applied when a streaming loop starts, and
+/// [`PayloadStream::start_streaming_loop`] fails if any of them can't be applied.
+/// `None` leaves the corresponding setting to the OS default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamThreadConfig {
+    /// Name of the thread.
+    pub name: Option<String>,
+
+    /// Stack size of the thread in bytes.
+    pub stack_size: Option<usize>,
+
+    /// Indices of CPUs that the thread is allowed to run on.
+    ///
+    /// Only supported on Linux.
+    pub cpu_affinity: Option<Vec<usize>>,
+
+    /// Real-time priority of the thread under `SCHED_FIFO` policy.
+    ///
+    /// The valid range is `1..=99`, and the process usually needs `CAP_SYS_NICE` to set it.
+    /// Only supported on Linux.
+    pub fifo_priority: Option<i32>,
 }
 
 impl StreamParams {
@@ -470,6 +922,30 @@ impl StreamParams {
     pub fn maximum_payload_size(&self) -> usize {
         self.payload_size * self.payload_count + self.payload_final1_size + self.payload_final2_size
     }
+
+    /// Returns the size of the largest transfer in a block.
+    fn maximum_transfer_size(&self) -> usize {
+        [
+            self.leader_size,
+            self.trailer_size,
+            self.payload_size,
+            self.payload_final1_size,
+            self.payload_final2_size,
+        ]
+        .iter()
+        .copied()
+        .max()
+        .unwrap()
+    }
+
+    /// Returns the number of transfers in a block.
+    fn transfers_per_block(&self) -> usize {
+        let finals = [self.payload_final1_size, self.payload_final2_size]
+            .iter()
+            .filter(|&&size| size != 0)
+            .count();
+        self.payload_count + finals + 2
+    }
 }
 
 impl StreamParams {
@@ -492,17 +968,24 @@ impl StreamParams {
             payload_final1_size,
             payload_final2_size,
             timeout,
+            deliver_incomplete_payload: false,
+            thread: StreamThreadConfig::default(),
+            transfer_tuning: TransferTuning::default(),
         }
     }
 
     /// Build `StreamParams` from [`DeviceControl`].
     pub fn from_control<Ctrl: DeviceControl + ?Sized>(ctrl: &mut Ctrl) -> ControlResult<Self> {
+        Self::from_control_at(ctrl, 0)
+    }
+
+    /// Build `StreamParams` of the `channel_index`-th stream channel from [`DeviceControl`].
+    pub fn from_control_at<Ctrl: DeviceControl + ?Sized>(
+        ctrl: &mut Ctrl,
+        channel_index: u32,
+    ) -> ControlResult<Self> {
         let abrm = Abrm::new(ctrl)?;
-        let sirm = abrm.sbrm(ctrl)?.sirm(ctrl)?.ok_or_else(|| {
-            let msg = "the U3V device doesn't have `SIRM`";
-            error!(msg);
-            ControlError::InvalidDevice(msg.into())
-        })?;
+        let sirm = sirm_at(ctrl, channel_index)?;
         let leader_size = sirm.maximum_leader_size(ctrl)? as usize;
         let trailer_size = sirm.maximum_trailer_size(ctrl)? as usize;
 
@@ -522,6 +1005,122 @@ impl StreamParams {
             timeout,
         ))
     }
+
+    /// Overwrites the transfer settings with the ones in `from`, keeping the other options as is.
+    fn update_transfer_params(&mut self, from: Self) {
+        self.leader_size = from.leader_size;
+        self.trailer_size = from.trailer_size;
+        self.payload_size = from.payload_size;
+        self.payload_count = from.payload_count;
+        self.payload_final1_size = from.payload_final1_size;
+        self.payload_final2_size = from.payload_final2_size;
+        self.timeout = from.timeout;
+    }
+}
+
+fn sirm_at<Ctrl: DeviceControl + ?Sized>(
+    ctrl: &mut Ctrl,
+    channel_index: u32,
+) -> ControlResult<Sirm> {
+    let abrm = Abrm::new(ctrl)?;
+    abrm.sbrm(ctrl)?
+        .sirm_at(ctrl, channel_index)?
+        .ok_or_else(|| {
+            let msg =
+                format!("the U3V device doesn't have `SIRM` of stream channel {channel_index}");
+            error!(msg);
+            ControlError::InvalidDevice(msg.into())
+        })
+}
+
+/// Spawns a thread configured with `config`, and runs `strm_loop` on it once the configuration
+/// is applied.
+fn spawn_streaming_thread<F>(config: &StreamThreadConfig, strm_loop: F) -> StreamResult<()>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let mut builder = std::thread::Builder::new();
+    if let Some(name) = &config.name {
+        builder = builder.name(name.clone());
+    }
+    if let Some(stack_size) = config.stack_size {
+        builder = builder.stack_size(stack_size);
+    }
+
+    let config = config.clone();
+    let (setup_tx, setup_rx) = mpsc::sync_channel(1);
+    builder
+        .spawn(move || {
+            let res = apply_thread_config(&config);
+            let is_ok = res.is_ok();
+            setup_tx.send(res).ok();
+            if is_ok {
+                strm_loop();
+            }
+        })
+        .map_err(|e| {
+            StreamError::Io(DeviceIoError::msg(format!(
+                "failed to spawn streaming thread: {e}"
+            )))
+        })?;
+
+    setup_rx.recv().map_err(|_| {
+        StreamError::Poisoned("streaming thread has terminated during its setup".into())
+    })?
+}
+
+#[cfg(target_os = "linux")]
+fn apply_thread_config(config: &StreamThreadConfig) -> StreamResult<()> {
+    use std::io;
+
+    if let Some(cpus) = &config.cpu_affinity {
+        // SAFETY: `cpu_set_t` is a plain bit set, and all-zero is a valid empty set.
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(StreamError::Io(DeviceIoError::msg(format!(
+                    "cpu index {cpu} is out of range"
+                ))));
+            }
+            // SAFETY: `cpu` is checked to be in the range of the set.
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+        // SAFETY: `set` is a valid `cpu_set_t`, and `0` designates the calling thread.
+        let ret = unsafe { libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) };
+        if ret != 0 {
+            let e = io::Error::last_os_error();
+            return Err(StreamError::Io(DeviceIoError::msg(format!(
+                "failed to set cpu affinity of streaming thread: {e}"
+            ))));
+        }
+    }
+
+    if let Some(priority) = config.fifo_priority {
+        let param = libc::sched_param {
+            sched_priority: priority,
+        };
+        // SAFETY: `param` is a valid `sched_param`, and `pthread_self` is always valid.
+        let ret =
+            unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+        if ret != 0 {
+            let e = io::Error::from_raw_os_error(ret);
+            return Err(StreamError::Io(DeviceIoError::msg(format!(
+                "failed to set `SCHED_FIFO` priority of streaming thread: {e}"
+            ))));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_thread_config(config: &StreamThreadConfig) -> StreamResult<()> {
+    if config.cpu_affinity.is_some() || config.fifo_priority.is_some() {
+        return Err(StreamError::Io(DeviceIoError::msg(
+            "cpu affinity and `SCHED_FIFO` priority are only supported on Linux",
+        )));
+    }
+    Ok(())
 }
 
 fn read_leader(
@@ -568,3 +1167,503 @@ fn read_trailer(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::{payload::PixelFormat, test_utils};
+
+    const LEADER_SIZE: usize = 56;
+    const TRAILER_SIZE: usize = 32;
+    const PAYLOAD_SIZE: usize = 16;
+
+    /// Returns bytes of an image leader of a 4x4 `Mono8` image.
+    fn image_leader(block_id: u64) -> Vec<u8> {
+        let mut buf = vec![];
+        buf.extend_from_slice(&0x4C56_3355_u32.to_le_bytes());
+        buf.extend_from_slice(&0_u16.to_le_bytes());
+        buf.extend_from_slice(&(LEADER_SIZE as u16).to_le_bytes());
+        buf.extend_from_slice(&block_id.to_le_bytes());
+        buf.extend_from_slice(&0_u16.to_le_bytes());
+        // Payload type, Image.
+        buf.extend_from_slice(&0x0001_u16.to_le_bytes());
+        // Timestamp.
+        buf.extend_from_slice(&100_u64.to_le_bytes());
+        buf.extend_from_slice(&u32::from(PixelFormat::Mono8).to_le_bytes());
+        // Width, height, x offset and y offset.
+        for v in [4_u32, 4, 0, 0] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        // X padding and reserved.
+        buf.extend_from_slice(&[0; 4]);
+        buf
+    }
+
+    /// Returns bytes of an image trailer.
+    fn image_trailer(block_id: u64, status: u16, valid_size: u64, actual_height: u32) -> Vec<u8> {
+        let mut buf = vec![];
+        buf.extend_from_slice(&0x5456_3355_u32.to_le_bytes());
+        buf.extend_from_slice(&0_u16.to_le_bytes());
+        buf.extend_from_slice(&(TRAILER_SIZE as u16).to_le_bytes());
+        buf.extend_from_slice(&block_id.to_le_bytes());
+        buf.extend_from_slice(&status.to_le_bytes());
+        buf.extend_from_slice(&0_u16.to_le_bytes());
+        buf.extend_from_slice(&valid_size.to_le_bytes());
+        buf.extend_from_slice(&actual_height.to_le_bytes());
+        buf
+    }
+
+    /// Returns the transfers of a complete block, i.e. a leader, a payload and a trailer.
+    fn complete_block(block_id: u64) -> Vec<Vec<u8>> {
+        vec![
+            image_leader(block_id),
+            vec![block_id as u8; PAYLOAD_SIZE],
+            image_trailer(block_id, 0, PAYLOAD_SIZE as u64, 4),
+        ]
+    }
+
+    fn params() -> StreamParams {
+        StreamParams::new(
+            LEADER_SIZE,
+            TRAILER_SIZE,
+            PAYLOAD_SIZE,
+            1,
+            0,
+            0,
+            Duration::from_millis(10),
+        )
+    }
+
+    /// A stream channel which sends `transfers`, and stops the streaming loop after sending all
+    /// of them.
+    struct FakeSource {
+        transfers: VecDeque<Vec<u8>>,
+        reset_count: usize,
+        restart_count: usize,
+        cancellation_tx: Option<mpsc::SyncSender<()>>,
+    }
+
+    impl FakeSource {
+        fn new(transfers: Vec<Vec<u8>>) -> Self {
+            Self {
+                transfers: transfers.into(),
+                reset_count: 0,
+                restart_count: 0,
+                cancellation_tx: None,
+            }
+        }
+    }
+
+    impl BlockSource for FakeSource {
+        fn recv_block(
+            &mut self,
+            params: &StreamParams,
+            leader_buf: Option<&mut [u8]>,
+            payload_buf: &mut [u8],
+            trailer_buf: &mut [u8],
+        ) -> StreamResult<usize> {
+            if let Some(leader_buf) = leader_buf {
+                self.recv_transfer(leader_buf, params.timeout)?;
+            }
+            let mut payload_len = 0;
+            for _ in 0..params.payload_count {
+                payload_len +=
+                    self.recv_transfer(&mut payload_buf[payload_len..], params.timeout)?;
+            }
+            self.recv_transfer(trailer_buf, params.timeout)?;
+            Ok(payload_len)
+        }
+
+        fn recv_transfer(&mut self, buf: &mut [u8], _timeout: Duration) -> StreamResult<usize> {
+            match self.transfers.pop_front() {
+                Some(transfer) => {
+                    let len = transfer.len().min(buf.len());
+                    buf[..len].copy_from_slice(&transfer[..len]);
+                    Ok(len)
+                }
+                None => {
+                    // Dropping the sender stops the loop.
+                    self.cancellation_tx = None;
+                    Err(StreamError::Timeout)
+                }
+            }
+        }
+
+        fn reset(&mut self, _timeout: Duration) -> StreamResult<()> {
+            self.reset_count += 1;
+            Ok(())
+        }
+
+        fn restart(&mut self, _timeout: Duration) -> StreamResult<()> {
+            self.restart_count += 1;
+            Ok(())
+        }
+    }
+
+    /// What the streaming loop sent to the receiver.
+    #[derive(Debug, PartialEq)]
+    enum Received {
+        Payload { id: u64, lost_before: u64 },
+        Error,
+    }
+
+    /// Runs the streaming loop until `source` sends all its blocks.
+    fn run_loop(
+        params: StreamParams,
+        source: &mut FakeSource,
+    ) -> (Vec<Received>, StreamStatistics) {
+        let received = Arc::new(Mutex::new(vec![]));
+        let sender = {
+            let received = received.clone();
+            payload::callback(move |payload| {
+                let payload = match payload {
+                    Ok(payload) => Received::Payload {
+                        id: payload.id(),
+                        lost_before: payload.lost_before(),
+                    },
+                    Err(_) => Received::Error,
+                };
+                received.lock().unwrap().push(payload);
+            })
+        };
+        let (cancellation_tx, cancellation_rx) = mpsc::sync_channel(0);
+        source.cancellation_tx = Some(cancellation_tx);
+        let statistics = Arc::default();
+        StreamingLoop {
+            params,
+            statistics: Arc::clone(&statistics),
+            sender,
+            cancellation_rx,
+        }
+        .run(source);
+
+        let mut received = std::mem::take(&mut *received.lock().unwrap());
+        // Drop the error sent when `source` ran out of blocks.
+        assert_eq!(received.pop(), Some(Received::Error));
+        let statistics = *statistics.lock().unwrap();
+        (received, statistics)
+    }
+
+    #[test]
+    fn test_streaming_loop_statistics_unlocked_in_callback() {
+        let mut source = FakeSource::new([complete_block(0), complete_block(1)].concat());
+        let statistics: Arc<Mutex<StreamStatistics>> = Arc::default();
+        let locked_in_callback = Arc::new(Mutex::new(vec![]));
+        let sender = {
+            let statistics = Arc::clone(&statistics);
+            let locked_in_callback = Arc::clone(&locked_in_callback);
+            payload::callback(move |_| {
+                let locked = statistics.try_lock().is_ok();
+                locked_in_callback.lock().unwrap().push(locked);
+            })
+        };
+        let (cancellation_tx, cancellation_rx) = mpsc::sync_channel(0);
+        source.cancellation_tx = Some(cancellation_tx);
+        StreamingLoop {
+            params: params(),
+            statistics: Arc::clone(&statistics),
+            sender,
+            cancellation_rx,
+        }
+        .run(&mut source);
+
+        // Two payloads and the error sent when `source` ran out of blocks.
+        assert_eq!(*locked_in_callback.lock().unwrap(), [true, true, true]);
+        assert_eq!(statistics.lock().unwrap().delivered_payload_count, 2);
+    }
+
+    #[test]
+    fn test_streaming_loop_counts_broken_payload_as_lost() {
+        // The device reports the payload `1` is discarded.
+        let mut broken = complete_block(1);
+        broken[2] = image_trailer(1, 0xa100, 0, 0);
+        let mut source = FakeSource::new([complete_block(0), broken, complete_block(2)].concat());
+
+        let (received, statistics) = run_loop(params(), &mut source);
+        assert_eq!(
+            received,
+            [
+                Received::Payload {
+                    id: 0,
+                    lost_before: 0
+                },
+                Received::Error,
+                Received::Payload {
+                    id: 2,
+                    lost_before: 1
+                },
+            ]
+        );
+        assert_eq!(statistics.delivered_payload_count, 2);
+        assert_eq!(statistics.discarded_payload_count, 1);
+        assert_eq!(statistics.lost_payload_count, 0);
+        assert_eq!(statistics.last_block_id, Some(2));
+    }
+
+    #[test]
+    fn test_streaming_loop_counts_invalid_trailer_as_lost() {
+        let mut broken = complete_block(1);
+        broken[2] = vec![0; TRAILER_SIZE];
+        let mut source = FakeSource::new([complete_block(0), broken, complete_block(3)].concat());
+
+        let (received, statistics) = run_loop(params(), &mut source);
+        assert_eq!(
+            received,
+            [
+                Received::Payload {
+                    id: 0,
+                    lost_before: 0
+                },
+                Received::Error,
+                Received::Payload {
+                    id: 3,
+                    lost_before: 2
+                },
+            ]
+        );
+        assert_eq!(statistics.discarded_payload_count, 1);
+        assert_eq!(statistics.lost_payload_count, 1);
+        // An invalid trailer doesn't reset the endpoint.
+        assert_eq!(source.reset_count, 0);
+        assert_eq!(statistics.resync_count, 0);
+    }
+
+    #[test]
+    fn test_streaming_loop_resyncs_misaligned_stream() {
+        // The leader of the block `1` is lost, so its payload is received as a leader.
+        let mut transfers = complete_block(0);
+        transfers.extend(complete_block(1).into_iter().skip(1));
+        // The leader of the block `2` is received as a trailer, and the rest of the block is
+        // drained.
+        transfers.extend(complete_block(2));
+        transfers.extend(complete_block(3));
+        let mut source = FakeSource::new(transfers);
+
+        let (received, statistics) = run_loop(params(), &mut source);
+        assert_eq!(
+            received,
+            [
+                Received::Payload {
+                    id: 0,
+                    lost_before: 0
+                },
+                Received::Error,
+                Received::Payload {
+                    id: 3,
+                    lost_before: 2
+                },
+            ]
+        );
+        assert_eq!(source.reset_count, 1);
+        assert_eq!(source.restart_count, 0);
+        assert_eq!(statistics.resync_count, 1);
+        assert_eq!(statistics.lost_payload_count, 2);
+    }
+
+    #[test]
+    fn test_streaming_loop_restarts_stream_without_leader() {
+        // No valid leader arrives within the transfers of a block after the reset.
+        let mut transfers = vec![vec![0; PAYLOAD_SIZE]; 6];
+        transfers.extend(complete_block(5));
+        let mut source = FakeSource::new(transfers);
+
+        let (received, statistics) = run_loop(params(), &mut source);
+        assert_eq!(
+            received,
+            [
+                Received::Error,
+                Received::Payload {
+                    id: 5,
+                    lost_before: 0
+                },
+            ]
+        );
+        assert_eq!(source.reset_count, 1);
+        assert_eq!(source.restart_count, 1);
+        assert_eq!(statistics.resync_count, 1);
+        assert_eq!(statistics.restart_count, 1);
+    }
+
+    #[test]
+    fn test_streaming_loop_doesnt_resync_valid_magic() {
+        // The leader has the magic number, but its payload type is unknown.
+        let mut broken = complete_block(1);
+        broken[0][18] = 0xff;
+        let mut source = FakeSource::new([complete_block(0), broken, complete_block(2)].concat());
+
+        let (received, statistics) = run_loop(params(), &mut source);
+        assert_eq!(received.len(), 3);
+        assert_eq!(source.reset_count, 0);
+        assert_eq!(statistics.resync_count, 0);
+    }
+
+    /// Builds a payload of an image block whose trailer reports `status`, `valid_size` and
+    /// `actual_height`, from `read_size` bytes received.
+    fn build_image(
+        status: u16,
+        valid_size: u64,
+        actual_height: u32,
+        read_size: usize,
+        deliver_incomplete: bool,
+    ) -> StreamResult<Payload> {
+        let leader_buf = image_leader(0);
+        let trailer_buf = image_trailer(0, status, valid_size, actual_height);
+        PayloadBuilder {
+            leader: u3v_stream::Leader::parse(&leader_buf).unwrap(),
+            payload_buf: vec![1; PAYLOAD_SIZE],
+            read_payload_size: read_size,
+            trailer: u3v_stream::Trailer::parse(&trailer_buf).unwrap(),
+            deliver_incomplete,
+        }
+        .build()
+    }
+
+    #[test]
+    fn test_build_complete_payload() {
+        let payload = build_image(0, 16, 4, PAYLOAD_SIZE, false).unwrap();
+        assert_eq!(payload.status(), PayloadStatus::Complete);
+        assert_eq!(payload.payload().len(), 16);
+        assert_eq!(payload.image_info().unwrap().height, 4);
+    }
+
+    #[test]
+    fn test_build_discarded_payload() {
+        assert!(matches!(
+            build_image(0xa100, 8, 2, PAYLOAD_SIZE, false),
+            Err(StreamError::InvalidPayload(_))
+        ));
+
+        let payload = build_image(0xa100, 8, 2, PAYLOAD_SIZE, true).unwrap();
+        assert_eq!(payload.status(), PayloadStatus::DataDiscarded);
+        assert!(!payload.is_complete());
+        assert_eq!(payload.payload().len(), 8);
+        let image_info = payload.image_info().unwrap();
+        assert_eq!(image_info.height, 2);
+        assert_eq!(image_info.image_size, 8);
+    }
+
+    #[test]
+    fn test_build_overrun_payload() {
+        assert!(build_image(0xa101, 16, 4, PAYLOAD_SIZE, false).is_err());
+
+        let payload = build_image(0xa101, 16, 4, PAYLOAD_SIZE, true).unwrap();
+        assert_eq!(payload.status(), PayloadStatus::DataOverrun);
+        assert_eq!(payload.payload().len(), 16);
+    }
+
+    #[test]
+    fn test_build_truncated_payload() {
+        // The trailer reports 16 bytes, but only 12 bytes are received.
+        assert!(build_image(0, 16, 4, 12, false).is_err());
+
+        let payload = build_image(0, 16, 3, 12, true).unwrap();
+        assert_eq!(payload.status(), PayloadStatus::Truncated);
+        assert_eq!(payload.payload().len(), 12);
+        assert_eq!(payload.image().unwrap().len(), 12);
+        assert_eq!(payload.image_info().unwrap().height, 3);
+
+        // A short payload which the device reports as discarded keeps its status.
+        let payload = build_image(0xa100, 16, 3, 12, true).unwrap();
+        assert_eq!(payload.status(), PayloadStatus::DataDiscarded);
+        assert_eq!(payload.payload().len(), 12);
+    }
+
+    #[test]
+    fn test_enable_secondary_stream_channel() {
+        let mut ctrl = test_utils::FakeCtrl::u3v(2);
+        let sirm = sirm_at(&mut ctrl, 1).unwrap();
+        enable_stream_channel(&mut ctrl, sirm).unwrap();
+        assert!(sirm.is_stream_enable(&mut ctrl).unwrap());
+        assert!(!sirm_at(&mut ctrl, 0)
+            .unwrap()
+            .is_stream_enable(&mut ctrl)
+            .unwrap());
+
+        // The transfer sizes are written to `SIRM` of the channel.
+        let params = StreamParams::from_control_at(&mut ctrl, 1).unwrap();
+        assert_eq!(params.maximum_payload_size(), 4096);
+        let params = StreamParams::from_control_at(&mut ctrl, 0).unwrap();
+        assert_eq!(params.maximum_payload_size(), 0);
+
+        sirm.disable_stream(&mut ctrl).unwrap();
+        assert!(!sirm.is_stream_enable(&mut ctrl).unwrap());
+
+        assert!(matches!(
+            sirm_at(&mut ctrl, 2),
+            Err(ControlError::InvalidDevice(_))
+        ));
+    }
+
+    #[test]
+    fn test_spawn_streaming_thread() {
+        let config = StreamThreadConfig {
+            name: Some("cameleon-stream".into()),
+            stack_size: Some(8 * 1024 * 1024),
+            ..StreamThreadConfig::default()
+        };
+        let (tx, rx) = mpsc::channel();
+        spawn_streaming_thread(&config, move || {
+            let name = std::thread::current().name().map(String::from);
+            tx.send((name, current_stack_size())).unwrap();
+        })
+        .unwrap();
+
+        let (name, stack_size) = rx.recv().unwrap();
+        assert_eq!(name.as_deref(), Some("cameleon-stream"));
+        if let Some(stack_size) = stack_size {
+            assert!(stack_size >= 8 * 1024 * 1024);
+        }
+    }
+
+    #[test]
+    fn test_spawn_streaming_thread_invalid_config() {
+        let config = StreamThreadConfig {
+            cpu_affinity: Some(vec![usize::MAX]),
+            ..StreamThreadConfig::default()
+        };
+        assert!(spawn_streaming_thread(&config, || panic!("the loop must not run")).is_err());
+
+        let config = StreamThreadConfig {
+            fifo_priority: Some(100),
+            ..StreamThreadConfig::default()
+        };
+        assert!(spawn_streaming_thread(&config, || panic!("the loop must not run")).is_err());
+    }
+
+    /// Returns the stack size of the current thread.
+    #[cfg(target_os = "linux")]
+    fn current_stack_size() -> Option<usize> {
+        // SAFETY: `attr` is initialized by `pthread_getattr_np` before it's read, and destroyed
+        // after use.
+        unsafe {
+            let mut attr: libc::pthread_attr_t = std::mem::zeroed();
+            if libc::pthread_getattr_np(libc::pthread_self(), &mut attr) != 0 {
+                return None;
+            }
+            let mut size = 0;
+            let ret = libc::pthread_attr_getstacksize(&attr, &mut size);
+            libc::pthread_attr_destroy(&mut attr);
+            (ret == 0).then_some(size)
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn current_stack_size() -> Option<usize> {
+        None
+    }
+
+    #[test]
+    fn test_block_id_tracker() {
+        let mut tracker = BlockIdTracker::default();
+        assert_eq!(tracker.track(10), 0);
+        assert_eq!(tracker.track(11), 0);
+        assert_eq!(tracker.track(14), 2);
+        assert_eq!(tracker.track(15), 0);
+
+        // The device restarted its sequence.
+        assert_eq!(tracker.track(0), 0);
+        assert_eq!(tracker.track(1), 0);
+    }
+}
@@ -0,0 +1,445 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains transfer size tuning of `U3V` stream channels.
+//!
+//! A payload of `U3V` stream is split into bulk transfers whose sizes are specified by `SIRM`
+//! registers. [`TransferTuning`] in [`super::StreamParams`] decides how these sizes are chosen.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use tracing::info;
+
+use crate::{
+    genapi::GenApiCtxt, CameleonResult, Camera, ControlError, ControlResult, DeviceControl,
+    DeviceIoError, StreamError,
+};
+
+use super::{register_map::Sirm, ControlHandle, StreamHandle};
+
+/// Payload transfer size used by [`TransferTuning::Fixed`].
+const FIXED_PAYLOAD_TRANSFER_SIZE: u32 = 1024 * 64;
+
+/// How to choose the transfer sizes of a stream channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransferTuning {
+    /// Uses 64 KiB payload transfers regardless of the host environment.
+    #[default]
+    Fixed,
+
+    /// Chooses the transfer sizes from the payload size and the host limits.
+    ///
+    /// See [`TransferPlan::auto`] for details.
+    Auto(AutoTransferConfig),
+}
+
+/// Host limits used by [`TransferTuning::Auto`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoTransferConfig {
+    /// Maximum size of a single bulk transfer that the host controller and its driver accept.
+    ///
+    /// The limit isn't detected from the host, so the default is
+    /// [`Self::DEFAULT_HOST_MAX_TRANSFER_SIZE`], which common host controllers accept. Use
+    /// [`benchmark_transfer_sizes`] to find the best value for the host.
+    pub host_max_transfer_size: u32,
+
+    /// Memory limit of `usbfs` in MiB. `0` means unlimited.
+    ///
+    /// If `None`, the limit is read from `/sys/module/usbcore/parameters/usbfs_memory_mb` on
+    /// Linux, and regarded as unlimited on other platforms.
+    pub usbfs_memory_mb: Option<u64>,
+}
+
+impl AutoTransferConfig {
+    /// Default value of [`Self::host_max_transfer_size`], which is 1 MiB.
+    pub const DEFAULT_HOST_MAX_TRANSFER_SIZE: u32 = 1024 * 1024;
+}
+
+impl Default for AutoTransferConfig {
+    fn default() -> Self {
+        Self {
+            host_max_transfer_size: Self::DEFAULT_HOST_MAX_TRANSFER_SIZE,
+            usbfs_memory_mb: None,
+        }
+    }
+}
+
+/// Transfer sizes to be written to `SIRM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferPlan {
+    /// Maximum leader size.
+    pub maximum_leader_size: u32,
+    /// Maximum trailer size.
+    pub maximum_trailer_size: u32,
+    /// Payload transfer size.
+    pub payload_transfer_size: u32,
+    /// Payload transfer count.
+    pub payload_transfer_count: u32,
+    /// Payload final transfer1 size.
+    pub payload_final_transfer1_size: u32,
+    /// Payload final transfer2 size.
+    pub payload_final_transfer2_size: u32,
+}
+
+/// Sizes that the device requires, read from `SIRM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferRequirement {
+    /// Required payload size.
+    pub payload_size: u64,
+    /// Required leader size.
+    pub leader_size: u32,
+    /// Required trailer size.
+    pub trailer_size: u32,
+    /// Required alignment of each transfer size. `0` is regarded as `1`.
+    ///
+    /// [`Self::from_sirm`] ensures it's a power of two as the specification requires.
+    pub alignment: u32,
+}
+
+impl TransferRequirement {
+    /// Reads the requirement from `sirm`.
+    ///
+    /// Returns [`ControlError::InvalidDevice`] if the alignment isn't a power of two that fits
+    /// in `u32`.
+    pub fn from_sirm<Ctrl: DeviceControl + ?Sized>(
+        ctrl: &mut Ctrl,
+        sirm: Sirm,
+    ) -> ControlResult<Self> {
+        let alignment = sirm.payload_size_alignment(ctrl)?;
+        let alignment = match u32::try_from(alignment) {
+            Ok(alignment) if alignment.is_power_of_two() => alignment,
+            _ => {
+                return Err(ControlError::InvalidDevice(
+                    format!("invalid payload size alignment: {alignment}").into(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            payload_size: sirm.required_payload_size(ctrl)?,
+            leader_size: sirm.required_leader_size(ctrl)?,
+            trailer_size: sirm.required_trailer_size(ctrl)?,
+            alignment,
+        })
+    }
+
+    fn alignment(&self) -> u64 {
+        u64::from(self.alignment.max(1))
+    }
+
+    /// Rounds `size` up to a multiple of the alignment.
+    fn align(&self, size: u64) -> u64 {
+        size.next_multiple_of(self.alignment())
+    }
+}
+
+impl TransferPlan {
+    /// Computes the plan of [`TransferTuning::Fixed`].
+    pub fn fixed(req: &TransferRequirement) -> Self {
+        let payload_transfer_size = req.align(u64::from(FIXED_PAYLOAD_TRANSFER_SIZE)) as u32;
+        Self::with_transfer_size(req, payload_transfer_size)
+    }
+
+    /// Computes the plan of [`TransferTuning::Auto`].
+    ///
+    /// The payload is split into the fewest transfers that don't exceed
+    /// `host_max_transfer_size`, and the transfers are made as even as possible so that the final
+    /// transfer isn't much smaller than the others.
+    ///
+    /// Since all transfers of a payload are submitted at once, this fails if the whole payload
+    /// doesn't fit in `usbfs_memory_mb` (`0` means unlimited).
+    pub fn auto(
+        req: &TransferRequirement,
+        host_max_transfer_size: u32,
+        usbfs_memory_mb: u64,
+    ) -> ControlResult<Self> {
+        let alignment = req.alignment();
+        // The largest aligned size within the host limit.
+        let max_transfer_size =
+            (u64::from(host_max_transfer_size) / alignment * alignment).max(alignment);
+
+        let transfer_count = req.payload_size.div_ceil(max_transfer_size).max(1);
+        let even_size = req.payload_size.div_ceil(transfer_count);
+        let payload_transfer_size = req.align(even_size).clamp(alignment, max_transfer_size);
+        let plan = Self::with_transfer_size(req, payload_transfer_size.try_into()?);
+
+        let total_size = u64::from(plan.maximum_leader_size)
+            + u64::from(plan.maximum_trailer_size)
+            + u64::from(plan.payload_transfer_size) * u64::from(plan.payload_transfer_count)
+            + u64::from(plan.payload_final_transfer1_size)
+            + u64::from(plan.payload_final_transfer2_size);
+        if usbfs_memory_mb != 0 && total_size > usbfs_memory_mb * 1024 * 1024 {
+            return Err(ControlError::Io(DeviceIoError::msg(format!(
+                "a payload requires {total_size} bytes of buffers, but `usbfs_memory_mb` is limited to {usbfs_memory_mb} MiB"
+            ))));
+        }
+
+        Ok(plan)
+    }
+
+    fn with_transfer_size(req: &TransferRequirement, payload_transfer_size: u32) -> Self {
+        let transfer_size = u64::from(payload_transfer_size);
+        let aligned_or_default = |size: u32| {
+            if size == 0 {
+                payload_transfer_size
+            } else {
+                req.align(u64::from(size)) as u32
+            }
+        };
+
+        Self {
+            maximum_leader_size: aligned_or_default(req.leader_size),
+            maximum_trailer_size: aligned_or_default(req.trailer_size),
+            payload_transfer_size,
+            payload_transfer_count: (req.payload_size / transfer_size) as u32,
+            payload_final_transfer1_size: req.align(req.payload_size % transfer_size) as u32,
+            payload_final_transfer2_size: 0,
+        }
+    }
+
+    /// Writes the plan to `sirm`.
+    ///
+    /// The stream channel must be disabled when this method is called.
+    pub fn write<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        ctrl: &mut Ctrl,
+        sirm: Sirm,
+    ) -> ControlResult<()> {
+        sirm.set_payload_transfer_size(ctrl, self.payload_transfer_size)?;
+        sirm.set_payload_transfer_count(ctrl, self.payload_transfer_count)?;
+        sirm.set_payload_final_transfer1_size(ctrl, self.payload_final_transfer1_size)?;
+        sirm.set_payload_final_transfer2_size(ctrl, self.payload_final_transfer2_size)?;
+        sirm.set_maximum_leader_size(ctrl, self.maximum_leader_size)?;
+        sirm.set_maximum_trailer_size(ctrl, self.maximum_trailer_size)
+    }
+}
+
+impl TransferTuning {
+    /// Computes the plan for the stream channel of `sirm`.
+    pub fn plan<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        ctrl: &mut Ctrl,
+        sirm: Sirm,
+    ) -> ControlResult<TransferPlan> {
+        let req = TransferRequirement::from_sirm(ctrl, sirm)?;
+        match self {
+            Self::Fixed => Ok(TransferPlan::fixed(&req)),
+            Self::Auto(config) => {
+                let usbfs_memory_mb = config
+                    .usbfs_memory_mb
+                    .or_else(|| usbfs_memory_mb(Path::new("/")))
+                    .unwrap_or(0);
+                TransferPlan::auto(&req, config.host_max_transfer_size, usbfs_memory_mb)
+            }
+        }
+    }
+}
+
+/// Reads `usbfs_memory_mb` under `root`, which is `/` except in tests.
+///
+/// Returns `None` if the value isn't available, e.g. on non-Linux platforms.
+pub(super) fn usbfs_memory_mb(root: &Path) -> Option<u64> {
+    let path = root.join("sys/module/usbcore/parameters/usbfs_memory_mb");
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Result of [`benchmark_transfer_sizes`] for a candidate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferBenchmark {
+    /// `host_max_transfer_size` used for the measurement.
+    pub host_max_transfer_size: u32,
+    /// Measured throughput in bytes per second.
+    pub throughput: f64,
+}
+
+/// Measures streaming throughput of each `host_max_transfer_size` in `candidates` with
+/// [`TransferTuning::Auto`], then sets the fastest one to `camera.strm`.
+///
+/// Each measurement streams until `payload_count` payloads are received or `timeout` elapses.
+/// The camera must be opened, and its context must be loaded. Results are returned in the order
+/// of `candidates`.
+pub fn benchmark_transfer_sizes<Ctxt: GenApiCtxt>(
+    camera: &mut Camera<ControlHandle, StreamHandle, Ctxt>,
+    candidates: &[u32],
+    payload_count: usize,
+    timeout: Duration,
+) -> CameleonResult<Vec<TransferBenchmark>> {
+    let original = camera.strm.params().transfer_tuning;
+    let mut results = Vec::with_capacity(candidates.len());
+
+    for &host_max_transfer_size in candidates {
+        camera.strm.params_mut().transfer_tuning = TransferTuning::Auto(AutoTransferConfig {
+            host_max_transfer_size,
+            ..AutoTransferConfig::default()
+        });
+
+        let receiver = camera.start_streaming(payload_count)?;
+        let started = Instant::now();
+        let mut received_bytes = 0;
+        let mut received = 0;
+        while received < payload_count && started.elapsed() < timeout {
+            if let Ok(payload) = receiver.try_recv() {
+                received_bytes += payload.payload().len();
+                received += 1;
+                receiver.send_back(payload);
+            } else {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+        let elapsed = started.elapsed();
+        camera.stop_streaming()?;
+
+        let throughput = received_bytes as f64 / elapsed.as_secs_f64();
+        info!(host_max_transfer_size, throughput, "measured transfer size");
+        results.push(TransferBenchmark {
+            host_max_transfer_size,
+            throughput,
+        });
+    }
+
+    camera.strm.params_mut().transfer_tuning = results
+        .iter()
+        .filter(|res| res.throughput > 0.0)
+        .max_by(|a, b| a.throughput.total_cmp(&b.throughput))
+        .map_or(original, |best| {
+            TransferTuning::Auto(AutoTransferConfig {
+                host_max_transfer_size: best.host_max_transfer_size,
+                ..AutoTransferConfig::default()
+            })
+        });
+
+    if results.iter().all(|res| res.throughput == 0.0) && !candidates.is_empty() {
+        return Err(StreamError::Timeout.into());
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use cameleon_device::u3v::register_map::sirm;
+
+    use super::*;
+    use crate::test_utils::{FakeCtrl, SIRM_ADDRESS};
+
+    fn req(payload_size: u64) -> TransferRequirement {
+        TransferRequirement {
+            payload_size,
+            leader_size: 52,
+            trailer_size: 0,
+            alignment: 512,
+        }
+    }
+
+    #[test]
+    fn test_fixed_plan() {
+        let plan = TransferPlan::fixed(&req(200_000));
+        assert_eq!(plan.payload_transfer_size, 65536);
+        assert_eq!(plan.payload_transfer_count, 3);
+        assert_eq!(plan.payload_final_transfer1_size, 3584);
+        assert_eq!(plan.maximum_leader_size, 512);
+        assert_eq!(plan.maximum_trailer_size, 65536);
+    }
+
+    #[test]
+    fn test_auto_plan_even_split() {
+        // 2.5 MiB payload with 1 MiB limit is split into 3 transfers of same size.
+        let plan = TransferPlan::auto(&req(2_621_440), 1024 * 1024, 0).unwrap();
+        assert_eq!(plan.payload_transfer_size, 873_984);
+        assert_eq!(plan.payload_transfer_count, 2);
+        assert_eq!(plan.payload_final_transfer1_size, 873_472);
+        assert!(plan.payload_transfer_size <= 1024 * 1024);
+        assert_eq!(plan.payload_transfer_size % 512, 0);
+        assert_eq!(plan.payload_final_transfer1_size % 512, 0);
+    }
+
+    #[test]
+    fn test_auto_plan_small_payload() {
+        let plan = TransferPlan::auto(&req(1000), 1024 * 1024, 16).unwrap();
+        assert_eq!(plan.payload_transfer_size, 1024);
+        assert_eq!(plan.payload_transfer_count, 0);
+        assert_eq!(plan.payload_final_transfer1_size, 1024);
+        assert_eq!(plan.maximum_trailer_size, 1024);
+    }
+
+    #[test]
+    fn test_auto_plan_unaligned_host_limit() {
+        let plan = TransferPlan::auto(&req(10_000), 1000, 0).unwrap();
+        assert_eq!(plan.payload_transfer_size, 512);
+        assert_eq!(plan.payload_transfer_count, 19);
+        assert_eq!(plan.payload_final_transfer1_size, 512);
+    }
+
+    #[test]
+    fn test_plan_unusual_alignment() {
+        let plan = TransferPlan::auto(
+            &TransferRequirement {
+                alignment: 0,
+                ..req(1000)
+            },
+            1024,
+            0,
+        )
+        .unwrap();
+        assert_eq!(plan.payload_transfer_size, 1000);
+        assert_eq!(plan.payload_final_transfer1_size, 0);
+
+        let plan = TransferPlan::fixed(&TransferRequirement {
+            alignment: 96,
+            ..req(200_000)
+        });
+        assert_eq!(plan.payload_transfer_size % 96, 0);
+        assert_eq!(plan.payload_final_transfer1_size % 96, 0);
+        assert_eq!(plan.maximum_leader_size, 96);
+    }
+
+    #[test]
+    fn test_requirement_from_sirm() {
+        let mut ctrl = FakeCtrl::u3v(1);
+        let sirm = Sirm::new(SIRM_ADDRESS);
+        let req = TransferRequirement::from_sirm(&mut ctrl, sirm).unwrap();
+        assert_eq!(req.payload_size, 4096);
+        assert_eq!(req.alignment, 64);
+
+        // The alignment doesn't fit in `u32`.
+        ctrl.write_le(
+            SIRM_ADDRESS + sirm::SI_INFO.0,
+            &(40_u32 << 24).to_le_bytes(),
+        );
+        assert!(matches!(
+            TransferRequirement::from_sirm(&mut ctrl, sirm),
+            Err(ControlError::InvalidDevice(_))
+        ));
+        // The alignment overflows.
+        ctrl.write_le(
+            SIRM_ADDRESS + sirm::SI_INFO.0,
+            &(200_u32 << 24).to_le_bytes(),
+        );
+        assert!(matches!(
+            TransferRequirement::from_sirm(&mut ctrl, sirm),
+            Err(ControlError::InvalidDevice(_))
+        ));
+    }
+
+    #[test]
+    fn test_auto_plan_exceeds_usbfs_memory() {
+        assert!(TransferPlan::auto(&req(32 * 1024 * 1024), 1024 * 1024, 16).is_err());
+    }
+
+    #[test]
+    fn test_usbfs_memory_mb() {
+        let root = std::env::temp_dir().join(format!("cameleon-usbfs-{}", std::process::id()));
+        let dir = root.join("sys/module/usbcore/parameters");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("usbfs_memory_mb"), "1000\n").unwrap();
+
+        assert_eq!(usbfs_memory_mb(&root), Some(1000));
+        assert_eq!(usbfs_memory_mb(&root.join("missing")), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
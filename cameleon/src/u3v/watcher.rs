@@ -0,0 +1,522 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains [`DeviceWatcher`] which notifies arrival and removal of U3V devices.
+//!
+//! The watcher is woken up by libusb hotplug notifications if the host supports them, and
+//! re-enumerates devices to find out what was changed. It falls back to re-enumerating devices
+//! periodically otherwise.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use cameleon::u3v::watcher::{DeviceEvent, DeviceWatcher, WatcherConfig};
+//!
+//! let watcher = DeviceWatcher::new(WatcherConfig::default()).unwrap();
+//! while let Ok(event) = watcher.recv_blocking() {
+//!     match event {
+//!         DeviceEvent::Arrived(info) => println!("arrived: {}", info.port_path()),
+//!         DeviceEvent::Removed(info) => println!("removed: {}", info.port_path()),
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use async_channel::{Receiver, RecvError, Sender, TryRecvError};
+use tracing::{debug, error, warn};
+
+use cameleon_device::u3v;
+
+use super::{CameleonResult, ControlError, DeviceInfo};
+
+/// Enumerates U3V devices connected to the host.
+///
+/// [`DeviceWatcher`] compares results of consecutive calls to find arrived and removed devices.
+pub trait DeviceEnumerator: Send + 'static {
+    /// Returns all devices currently connected to the host.
+    fn enumerate(&mut self) -> CameleonResult<Enumeration>;
+}
+
+/// Devices connected to the host, returned by [`DeviceEnumerator::enumerate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Enumeration {
+    /// Information of devices which are identified.
+    pub devices: Vec<DeviceInfo>,
+
+    /// Port paths of devices which are connected but couldn't be identified, e.g. because the
+    /// device is busy.
+    ///
+    /// A known device at one of these ports is regarded as still connected, so that a transient
+    /// failure doesn't emit [`DeviceEvent::Removed`] followed by [`DeviceEvent::Arrived`].
+    pub unidentified_ports: Vec<String>,
+}
+
+impl From<Vec<DeviceInfo>> for Enumeration {
+    fn from(devices: Vec<DeviceInfo>) -> Self {
+        Self {
+            devices,
+            unidentified_ports: vec![],
+        }
+    }
+}
+
+/// [`DeviceEnumerator`] backed by libusb.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LibUsbEnumerator;
+
+impl DeviceEnumerator for LibUsbEnumerator {
+    fn enumerate(&mut self) -> CameleonResult<Enumeration> {
+        let scan = u3v::scan_devices().map_err(ControlError::from)?;
+        let unidentified_ports = scan
+            .unidentified
+            .iter()
+            .map(|dev| {
+                debug!(
+                    "failed to identify device at {}: {}",
+                    dev.port_path(),
+                    dev.error
+                );
+                dev.port_path()
+            })
+            .collect();
+        Ok(Enumeration {
+            devices: scan
+                .devices
+                .into_iter()
+                .map(|dev| dev.device_info)
+                .collect(),
+            unidentified_ports,
+        })
+    }
+}
+
+/// An event emitted by [`DeviceWatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// A device is connected to the host.
+    Arrived(DeviceInfo),
+
+    /// A device is disconnected from the host.
+    Removed(DeviceInfo),
+}
+
+impl DeviceEvent {
+    /// Returns information of the device.
+    #[must_use]
+    pub fn device_info(&self) -> &DeviceInfo {
+        match self {
+            Self::Arrived(info) | Self::Removed(info) => info,
+        }
+    }
+}
+
+/// Configuration of [`DeviceWatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatcherConfig {
+    /// Interval of re-enumeration.
+    ///
+    /// When hotplug notifications are used, devices are still re-enumerated at this interval in
+    /// case a notification is missed.
+    pub poll_interval: Duration,
+
+    /// Delay between a hotplug notification and the re-enumeration it triggers.
+    ///
+    /// Devices may not respond to requests right after they are attached.
+    pub settle_delay: Duration,
+
+    /// Use libusb hotplug notifications if the host supports them.
+    pub use_hotplug: bool,
+
+    /// Emit [`DeviceEvent::Arrived`] for devices already connected when the watcher starts.
+    pub report_existing: bool,
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1),
+            settle_delay: Duration::from_millis(200),
+            use_hotplug: true,
+            report_existing: false,
+        }
+    }
+}
+
+/// Watches U3V devices connected to the host and emits [`DeviceEvent`].
+///
+/// Events are emitted from a background thread, which stops when the watcher is dropped.
+#[derive(Debug)]
+pub struct DeviceWatcher {
+    events: Receiver<DeviceEvent>,
+    stop_tx: Option<mpsc::Sender<Wakeup>>,
+    handle: Option<thread::JoinHandle<()>>,
+    hotplug: Option<u3v::HotplugWatch>,
+}
+
+impl DeviceWatcher {
+    /// Starts watching devices with [`LibUsbEnumerator`].
+    pub fn new(config: WatcherConfig) -> CameleonResult<Self> {
+        Self::with_enumerator(LibUsbEnumerator, config)
+    }
+
+    /// Starts watching devices with `enumerator`.
+    ///
+    /// The initial enumeration is done before this function returns, so that devices already
+    /// connected are never reported as arrived unless [`WatcherConfig::report_existing`] is set.
+    pub fn with_enumerator<E: DeviceEnumerator>(
+        mut enumerator: E,
+        config: WatcherConfig,
+    ) -> CameleonResult<Self> {
+        let (events_tx, events_rx) = async_channel::unbounded();
+        let (wakeup_tx, wakeup_rx) = mpsc::channel();
+
+        let mut known = HashMap::new();
+        let initial = diff(&mut known, enumerator.enumerate()?);
+        if config.report_existing {
+            for event in initial {
+                events_tx.try_send(event).ok();
+            }
+        }
+
+        let hotplug = if config.use_hotplug {
+            let hotplug_tx = wakeup_tx.clone();
+            u3v::watch_hotplug(move || {
+                hotplug_tx.send(Wakeup::Hotplug).ok();
+            })
+            .map_err(ControlError::from)?
+        } else {
+            None
+        };
+
+        let handle = thread::Builder::new()
+            .name("cameleon-device-watcher".into())
+            .spawn(move || watch(enumerator, known, &config, &wakeup_rx, &events_tx))
+            .map_err(|e| ControlError::from(u3v::Error::from(e)))?;
+
+        Ok(Self {
+            events: events_rx,
+            stop_tx: Some(wakeup_tx),
+            handle: Some(handle),
+            hotplug,
+        })
+    }
+
+    /// Returns `true` if the watcher is woken up by libusb hotplug notifications, `false` if it
+    /// only polls.
+    #[must_use]
+    pub fn uses_hotplug(&self) -> bool {
+        self.hotplug.is_some()
+    }
+
+    /// Receives the next event, blocking the current thread until an event is emitted.
+    pub fn recv_blocking(&self) -> Result<DeviceEvent, RecvError> {
+        self.events.recv_blocking()
+    }
+
+    /// Receives the next event.
+    pub async fn recv(&self) -> Result<DeviceEvent, RecvError> {
+        self.events.recv().await
+    }
+
+    /// Receives the next event if one has already been emitted.
+    pub fn try_recv(&self) -> Result<DeviceEvent, TryRecvError> {
+        self.events.try_recv()
+    }
+
+    /// Returns the receiver of events.
+    ///
+    /// The receiver can be cloned and moved to other threads. It's closed when the watcher is
+    /// dropped.
+    #[must_use]
+    pub fn receiver(&self) -> &Receiver<DeviceEvent> {
+        &self.events
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        // Stop hotplug notifications first so that they don't wake up the stopped thread.
+        self.hotplug.take();
+        if let Some(stop_tx) = self.stop_tx.take() {
+            stop_tx.send(Wakeup::Stop).ok();
+        }
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+enum Wakeup {
+    Hotplug,
+    Stop,
+}
+
+fn watch<E: DeviceEnumerator>(
+    mut enumerator: E,
+    mut known: HashMap<DeviceKey, DeviceInfo>,
+    config: &WatcherConfig,
+    wakeup_rx: &mpsc::Receiver<Wakeup>,
+    events_tx: &Sender<DeviceEvent>,
+) {
+    loop {
+        match wakeup_rx.recv_timeout(config.poll_interval) {
+            Ok(Wakeup::Hotplug) => {
+                thread::sleep(config.settle_delay);
+                // Coalesce notifications which arrived while settling.
+                loop {
+                    match wakeup_rx.try_recv() {
+                        Ok(Wakeup::Hotplug) => {}
+                        Ok(Wakeup::Stop) | Err(mpsc::TryRecvError::Disconnected) => return,
+                        Err(mpsc::TryRecvError::Empty) => break,
+                    }
+                }
+            }
+            Ok(Wakeup::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        let devices = match enumerator.enumerate() {
+            Ok(devices) => devices,
+            Err(e) => {
+                warn!("failed to enumerate devices: {}", e);
+                continue;
+            }
+        };
+
+        for event in diff(&mut known, devices) {
+            if events_tx.try_send(event).is_err() {
+                error!("device event receiver is closed");
+                return;
+            }
+        }
+    }
+}
+
+/// Identifies a device. A device moved to another port is regarded as removed and arrived.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DeviceKey {
+    guid: String,
+    port_path: String,
+}
+
+impl DeviceKey {
+    fn new(info: &DeviceInfo) -> Self {
+        Self {
+            guid: info.guid.clone(),
+            port_path: info.port_path(),
+        }
+    }
+}
+
+/// Updates `known` to `current` and returns events describing the difference. Removals are
+/// ordered before arrivals.
+///
+/// Known devices at unidentified ports are kept as they are.
+fn diff(known: &mut HashMap<DeviceKey, DeviceInfo>, current: Enumeration) -> Vec<DeviceEvent> {
+    let unidentified_ports = current.unidentified_ports;
+    let mut current: HashMap<_, _> = current
+        .devices
+        .into_iter()
+        .map(|info| (DeviceKey::new(&info), info))
+        .collect();
+
+    let mut removed: Vec<_> = known
+        .keys()
+        .filter(|key| !current.contains_key(key) && !unidentified_ports.contains(&key.port_path))
+        .cloned()
+        .collect();
+    removed.sort_by(|a, b| a.port_path.cmp(&b.port_path));
+    let mut events: Vec<_> = removed
+        .into_iter()
+        .filter_map(|key| known.remove(&key))
+        .map(DeviceEvent::Removed)
+        .collect();
+
+    let mut arrived: Vec<_> = current
+        .keys()
+        .filter(|key| !known.contains_key(key))
+        .cloned()
+        .collect();
+    arrived.sort_by(|a, b| a.port_path.cmp(&b.port_path));
+    for key in arrived {
+        if let Some(info) = current.remove(&key) {
+            known.insert(key, info.clone());
+            events.push(DeviceEvent::Arrived(info));
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{collections::VecDeque, time::Instant};
+
+    use cameleon_device::u3v::BusSpeed;
+
+    /// Returns snapshots in order, and repeats the last one once the script is exhausted.
+    struct ScriptedEnumerator {
+        script: VecDeque<CameleonResult<Enumeration>>,
+        last: Enumeration,
+    }
+
+    impl ScriptedEnumerator {
+        fn new(script: Vec<CameleonResult<Enumeration>>) -> Self {
+            Self {
+                script: script.into(),
+                last: Enumeration::default(),
+            }
+        }
+    }
+
+    impl DeviceEnumerator for ScriptedEnumerator {
+        fn enumerate(&mut self) -> CameleonResult<Enumeration> {
+            match self.script.pop_front() {
+                Some(Ok(devices)) => {
+                    self.last = devices.clone();
+                    Ok(devices)
+                }
+                Some(Err(e)) => Err(e),
+                None => Ok(self.last.clone()),
+            }
+        }
+    }
+
+    fn device_info(guid: &str, port: u8) -> DeviceInfo {
+        DeviceInfo {
+            gencp_version: semver::Version::new(1, 0, 0),
+            u3v_version: semver::Version::new(1, 0, 0),
+            guid: guid.into(),
+            vendor_name: "vendor".into(),
+            model_name: "model".into(),
+            family_name: None,
+            device_version: "1.0".into(),
+            manufacturer_info: String::new(),
+            serial_number: guid.into(),
+            user_defined_name: None,
+            supported_speed: BusSpeed::SuperSpeed,
+            connected_speed: Some(BusSpeed::SuperSpeed),
+            bus_number: 1,
+            port_numbers: vec![port],
+        }
+    }
+
+    fn config(report_existing: bool) -> WatcherConfig {
+        WatcherConfig {
+            poll_interval: Duration::from_millis(1),
+            settle_delay: Duration::ZERO,
+            use_hotplug: false,
+            report_existing,
+        }
+    }
+
+    fn recv_n(watcher: &DeviceWatcher, n: usize) -> Vec<DeviceEvent> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut events = vec![];
+        while events.len() < n {
+            assert!(Instant::now() < deadline, "timed out: {:?}", events);
+            match watcher.try_recv() {
+                Ok(event) => events.push(event),
+                Err(_) => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = device_info("A", 1);
+        let b = device_info("B", 2);
+        let mut known = HashMap::new();
+
+        assert_eq!(
+            diff(&mut known, vec![b.clone(), a.clone()].into()),
+            vec![
+                DeviceEvent::Arrived(a.clone()),
+                DeviceEvent::Arrived(b.clone())
+            ]
+        );
+        assert!(diff(&mut known, vec![a.clone(), b.clone()].into()).is_empty());
+
+        // Moving a device to another port is a removal followed by an arrival.
+        let moved = device_info("A", 3);
+        assert_eq!(
+            diff(&mut known, vec![moved.clone(), b].into()),
+            vec![DeviceEvent::Removed(a), DeviceEvent::Arrived(moved)]
+        );
+    }
+
+    #[test]
+    fn test_diff_unidentified() {
+        let a = device_info("A", 1);
+        let b = device_info("B", 2);
+        let mut known = HashMap::new();
+        diff(&mut known, vec![a.clone(), b.clone()].into());
+
+        // `A` failed to be identified, but is still connected.
+        let current = Enumeration {
+            devices: vec![b.clone()],
+            unidentified_ports: vec![a.port_path()],
+        };
+        assert!(diff(&mut known, current).is_empty());
+        assert!(diff(&mut known, vec![a.clone(), b.clone()].into()).is_empty());
+
+        // An unidentified device at an unknown port isn't reported.
+        let current = Enumeration {
+            devices: vec![a, b],
+            unidentified_ports: vec!["1-9".into()],
+        };
+        assert!(diff(&mut known, current).is_empty());
+    }
+
+    #[test]
+    fn test_polling() {
+        let a = device_info("A", 1);
+        let b = device_info("B", 2);
+        let enumerator = ScriptedEnumerator::new(vec![
+            Ok(vec![a.clone()].into()),
+            Ok(vec![a.clone(), b.clone()].into()),
+            Err(ControlError::Timeout.into()),
+            Ok(Enumeration {
+                devices: vec![],
+                unidentified_ports: vec![b.port_path()],
+            }),
+            Ok(vec![b.clone()].into()),
+        ]);
+
+        let watcher = DeviceWatcher::with_enumerator(enumerator, config(false)).unwrap();
+        assert!(!watcher.uses_hotplug());
+        assert_eq!(
+            recv_n(&watcher, 2),
+            vec![DeviceEvent::Arrived(b), DeviceEvent::Removed(a)]
+        );
+        thread::sleep(Duration::from_millis(20));
+        assert!(watcher.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_report_existing() {
+        let a = device_info("A", 1);
+        let enumerator =
+            ScriptedEnumerator::new(vec![Ok(vec![a.clone()].into()), Ok(vec![].into())]);
+
+        let watcher = DeviceWatcher::with_enumerator(enumerator, config(true)).unwrap();
+        assert_eq!(
+            recv_n(&watcher, 2),
+            vec![DeviceEvent::Arrived(a.clone()), DeviceEvent::Removed(a)]
+        );
+
+        let receiver = watcher.receiver().clone();
+        drop(watcher);
+        assert!(receiver.recv_blocking().is_err());
+    }
+}
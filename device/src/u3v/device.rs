@@ -15,7 +15,8 @@ pub struct Device {
 
     ctrl_iface_info: ControlIfaceInfo,
     event_iface_info: Option<ReceiveIfaceInfo>,
-    stream_iface_info: Option<ReceiveIfaceInfo>,
+    /// Stream interfaces ordered by their stream channel index.
+    stream_iface_infos: Vec<ReceiveIfaceInfo>,
 
     pub device_info: DeviceInfo,
 }
@@ -40,8 +41,14 @@ impl Device {
         }
     }
 
+    /// Returns the channel of the first stream interface if exists.
     pub fn stream_channel(&self) -> Result<Option<ReceiveChannel>> {
-        match &self.stream_iface_info {
+        self.stream_channel_at(0)
+    }
+
+    /// Returns the channel of the `index`-th stream interface if exists.
+    pub fn stream_channel_at(&self, index: usize) -> Result<Option<ReceiveChannel>> {
+        match self.stream_iface_infos.get(index) {
             Some(iface_info) => {
                 let device_handle = self.device.open()?;
                 Ok(Some(ReceiveChannel::new(device_handle, iface_info.clone())))
@@ -50,6 +57,23 @@ impl Device {
         }
     }
 
+    /// Returns the channels of all stream interfaces ordered by their stream channel index.
+    pub fn stream_channels(&self) -> Result<Vec<ReceiveChannel>> {
+        self.stream_iface_infos
+            .iter()
+            .map(|iface_info| {
+                let device_handle = self.device.open()?;
+                Ok(ReceiveChannel::new(device_handle, iface_info.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the number of stream interfaces of the device.
+    #[must_use]
+    pub fn num_stream_channels(&self) -> usize {
+        self.stream_iface_infos.len()
+    }
+
     #[must_use]
     pub fn device_info(&self) -> &DeviceInfo {
         &self.device_info
@@ -59,7 +83,7 @@ impl Device {
         device: RusbDevice,
         ctrl_iface_info: ControlIfaceInfo,
         event_iface_info: Option<ReceiveIfaceInfo>,
-        stream_iface_info: Option<ReceiveIfaceInfo>,
+        stream_iface_infos: Vec<ReceiveIfaceInfo>,
         device_info: DeviceInfo,
     ) -> Self {
         let device = get_device(device);
@@ -68,7 +92,7 @@ impl Device {
             device,
             ctrl_iface_info,
             event_iface_info,
-            stream_iface_info,
+            stream_iface_infos,
             device_info,
         };
 
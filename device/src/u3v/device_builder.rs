@@ -10,6 +10,7 @@ use crate::u3v::{BusSpeed, DeviceInfo, Error, Result};
 use super::{
     channel::{ControlIfaceInfo, ReceiveIfaceInfo},
     device::{Device, RusbDevice, RusbDeviceHandle},
+    device_info,
 };
 
 const MISCELLANEOUS_CLASS: u8 = 0xEF;
@@ -23,14 +24,62 @@ const IAD_FUNCTION_PROTOCOL: u8 = 0x00;
 const USB3V_SUBCLASS: u8 = 0x05;
 
 pub fn enumerate_devices() -> Result<Vec<Device>> {
+    Ok(scan_devices()?.devices)
+}
+
+/// Enumerates U3V devices like [`enumerate_devices`], and also returns U3V devices which are
+/// connected but couldn't be opened.
+pub fn scan_devices() -> Result<DeviceScan> {
     let rusb_device_list = rusb::DeviceList::new()?;
     let builders = rusb_device_list
         .iter()
         .filter_map(|dev| DeviceBuilder::new(dev).ok().flatten());
 
-    Ok(builders
-        .filter_map(|builder| builder.build().ok())
-        .collect())
+    let mut scan = DeviceScan {
+        devices: vec![],
+        unidentified: vec![],
+    };
+    for builder in builders {
+        let bus_number = builder.device.bus_number();
+        let port_numbers = builder.device.port_numbers().unwrap_or_default();
+        match builder.build() {
+            Ok(device) => scan.devices.push(device),
+            Err(error) => scan.unidentified.push(UnidentifiedDevice {
+                bus_number,
+                port_numbers,
+                error,
+            }),
+        }
+    }
+    Ok(scan)
+}
+
+/// Result of [`scan_devices`].
+pub struct DeviceScan {
+    /// Devices which are opened and identified successfully.
+    pub devices: Vec<Device>,
+    /// U3V devices which are connected, but whose information couldn't be read, e.g. because
+    /// the device is busy or its descriptors are broken.
+    pub unidentified: Vec<UnidentifiedDevice>,
+}
+
+/// A U3V device which is connected but couldn't be opened.
+#[derive(Debug)]
+pub struct UnidentifiedDevice {
+    /// Bus number that the device is connected to.
+    pub bus_number: u8,
+    /// Port numbers from the root hub to the device.
+    pub port_numbers: Vec<u8>,
+    /// The error which occurred while the device was opened.
+    pub error: Error,
+}
+
+impl UnidentifiedDevice {
+    /// Returns the location of the device in the same format as [`DeviceInfo::port_path`].
+    #[must_use]
+    pub fn port_path(&self) -> String {
+        device_info::port_path(self.bus_number, &self.port_numbers)
+    }
 }
 
 struct DeviceBuilder {
@@ -84,40 +133,37 @@ impl DeviceBuilder {
             .ok_or(Error::InvalidDevice)?;
         let device_info_desc = ctrl_iface_desc.extra();
         let device_info_desc = DeviceInfoDescriptor::from_bytes(device_info_desc)?;
-        let device_info = device_info_desc.interpret(&dev_channel)?;
+        let mut device_info = device_info_desc.interpret(&dev_channel)?;
+        device_info.connected_speed = match self.device.speed() {
+            rusb::Speed::Low => Some(BusSpeed::LowSpeed),
+            rusb::Speed::Full => Some(BusSpeed::FullSpeed),
+            rusb::Speed::High => Some(BusSpeed::HighSpeed),
+            rusb::Speed::Super => Some(BusSpeed::SuperSpeed),
+            rusb::Speed::SuperPlus => Some(BusSpeed::SuperSpeedPlus),
+            _ => None,
+        };
+        device_info.bus_number = self.device.bus_number();
+        device_info.port_numbers = self.device.port_numbers().unwrap_or_default();
 
         // Retrieve event and stream interface information if exists.
-        let receive_ifaces = interfaces.filter_map(|iface| ReceiveIfaceInfo::new(&iface));
-        let mut receive_ifaces: Vec<(ReceiveIfaceInfo, ReceiveIfaceKind)> =
-            receive_ifaces.collect();
-
-        if receive_ifaces.len() > 2 {
-            return Err(Error::InvalidDevice);
+        // A device may have multiple stream interfaces, they are ordered by interface number,
+        // which corresponds to the stream channel index.
+        let mut event_iface = None;
+        let mut stream_ifaces = vec![];
+        for (iface_info, kind) in interfaces.filter_map(|iface| ReceiveIfaceInfo::new(&iface)) {
+            match kind {
+                ReceiveIfaceKind::Event if event_iface.is_none() => event_iface = Some(iface_info),
+                ReceiveIfaceKind::Event => return Err(Error::InvalidDevice),
+                ReceiveIfaceKind::Stream => stream_ifaces.push(iface_info),
+            }
         }
-
-        let (event_iface, stream_iface) = match receive_ifaces.pop() {
-            Some((event_iface, ReceiveIfaceKind::Event)) => match receive_ifaces.pop() {
-                Some((stream_iface, ReceiveIfaceKind::Stream)) => {
-                    (Some(event_iface), Some(stream_iface))
-                }
-                None => (Some(event_iface), None),
-                Some(_) => return Err(Error::InvalidDevice),
-            },
-            Some((stream_iface, ReceiveIfaceKind::Stream)) => match receive_ifaces.pop() {
-                Some((event_iface, ReceiveIfaceKind::Event)) => {
-                    (Some(event_iface), Some(stream_iface))
-                }
-                None => (None, Some(stream_iface)),
-                Some(_) => return Err(Error::InvalidDevice),
-            },
-            None => (None, None),
-        };
+        stream_ifaces.sort_by_key(|iface_info| iface_info.iface_number);
 
         Ok(Device::new(
             self.device,
             ctrl_iface_info,
             event_iface,
-            stream_iface,
+            stream_ifaces,
             device_info,
         ))
     }
@@ -379,6 +425,10 @@ impl DeviceInfoDescriptor {
             serial_number,
             user_defined_name,
             supported_speed,
+            // Filled in by the caller, which has access to the device.
+            connected_speed: None,
+            bus_number: 0,
+            port_numbers: vec![],
         })
     }
 }
@@ -7,7 +7,7 @@ use std::fmt;
 use semver::Version;
 
 /// Device information in class-specific device descriptor.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DeviceInfo {
     /// GenCP version the device provides.
     pub gencp_version: Version,
@@ -45,10 +45,37 @@ pub struct DeviceInfo {
 
     /// Bus speed supported by the device.
     pub supported_speed: BusSpeed,
+
+    /// Bus speed negotiated with the host, `None` if the OS doesn't report it.
+    pub connected_speed: Option<BusSpeed>,
+
+    /// Number of the USB bus that the device is connected to.
+    pub bus_number: u8,
+
+    /// Port numbers from the root hub to the device, e.g. `[1, 3]` if the device is connected to
+    /// the 3rd port of a hub connected to the 1st port of the root hub.
+    pub port_numbers: Vec<u8>,
+}
+
+impl DeviceInfo {
+    /// Returns the physical location of the device as `<bus>-<port>.<port>...`, e.g. `2-1.3`.
+    ///
+    /// This is the same format as device names in `/sys/bus/usb/devices` on Linux, and stays same
+    /// as long as the device is connected to the same port.
+    #[must_use]
+    pub fn port_path(&self) -> String {
+        port_path(self.bus_number, &self.port_numbers)
+    }
+}
+
+/// Formats the bus and port numbers as `<bus>-<port>.<port>...`.
+pub(super) fn port_path(bus_number: u8, port_numbers: &[u8]) -> String {
+    let ports: Vec<String> = port_numbers.iter().map(ToString::to_string).collect();
+    format!("{}-{}", bus_number, ports.join("."))
 }
 
 /// Bus speed supported by each USB device.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum BusSpeed {
     /// USB 1.0/Low-Speed: 1.5 Mbps
     LowSpeed,
@@ -96,7 +123,15 @@ impl fmt::Display for DeviceInfo {
             writeln!(f, "User Defined Name: N/A")
         }?;
 
-        write!(f, "Supported Speed: {:?}", self.supported_speed)?;
+        writeln!(f, "Supported Speed: {:?}", self.supported_speed)?;
+
+        if let Some(connected_speed) = &self.connected_speed {
+            writeln!(f, "Connected Speed: {connected_speed:?}")
+        } else {
+            writeln!(f, "Connected Speed: N/A")
+        }?;
+
+        write!(f, "Port Path: {}", self.port_path())?;
 
         Ok(())
     }
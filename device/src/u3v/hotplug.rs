@@ -0,0 +1,123 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use rusb::{GlobalContext, Hotplug, HotplugBuilder, UsbContext};
+
+use super::Result;
+
+const MISCELLANEOUS_CLASS: u8 = 0xEF;
+const EVENT_HANDLING_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Returns `true` if libusb supports hotplug notifications on the host.
+#[must_use]
+pub fn has_hotplug() -> bool {
+    rusb::has_hotplug()
+}
+
+/// Calls `on_change` on a background thread whenever a USB device which may be a U3V device is
+/// attached to or detached from the host.
+///
+/// libusb doesn't allow synchronous device access inside hotplug callbacks, so `on_change`
+/// doesn't carry the device. Enumerate devices with [`super::enumerate_devices`] outside of the
+/// callback to know what was changed.
+///
+/// Returns `Ok(None)` if libusb doesn't support hotplug notifications on the host.
+/// Notifications stop when the returned [`HotplugWatch`] is dropped.
+pub fn watch_hotplug<F>(on_change: F) -> Result<Option<HotplugWatch>>
+where
+    F: FnMut() + Send + 'static,
+{
+    if !has_hotplug() {
+        return Ok(None);
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let (result_tx, result_rx) = mpsc::sync_channel(1);
+    let thread_stop = stop.clone();
+    let handle = thread::Builder::new()
+        .name("cameleon-hotplug".into())
+        .spawn(move || {
+            let context = GlobalContext::default();
+            let callback: Box<dyn Hotplug<GlobalContext>> = Box::new(Notifier(on_change));
+            let registration = match HotplugBuilder::new()
+                .class(MISCELLANEOUS_CLASS)
+                .enumerate(false)
+                .register(context, callback)
+            {
+                Ok(registration) => {
+                    result_tx.send(Ok(())).ok();
+                    registration
+                }
+                Err(err) => {
+                    result_tx.send(Err(err)).ok();
+                    return;
+                }
+            };
+
+            while !thread_stop.load(Ordering::Relaxed) {
+                if let Err(err) = context.handle_events(Some(EVENT_HANDLING_TIMEOUT)) {
+                    log::warn!("failed to handle libusb events: {}", err);
+                    thread::sleep(EVENT_HANDLING_TIMEOUT);
+                }
+            }
+            drop(registration);
+        })?;
+
+    match result_rx.recv() {
+        Ok(Ok(())) => Ok(Some(HotplugWatch {
+            stop,
+            handle: Some(handle),
+        })),
+        Ok(Err(err)) => {
+            handle.join().ok();
+            Err(err.into())
+        }
+        Err(_) => {
+            handle.join().ok();
+            Err(rusb::Error::Other.into())
+        }
+    }
+}
+
+/// A guard of hotplug notifications registered by [`watch_hotplug`].
+///
+/// Notifications stop when the guard is dropped.
+#[derive(Debug)]
+pub struct HotplugWatch {
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for HotplugWatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+struct Notifier<F>(F);
+
+impl<F> Hotplug<GlobalContext> for Notifier<F>
+where
+    F: FnMut() + Send,
+{
+    fn device_arrived(&mut self, _: rusb::Device<GlobalContext>) {
+        (self.0)();
+    }
+
+    fn device_left(&mut self, _: rusb::Device<GlobalContext>) {
+        (self.0)();
+    }
+}
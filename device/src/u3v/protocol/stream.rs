@@ -60,7 +60,8 @@ pub struct Leader<'a> {
 }
 
 impl<'a> Leader<'a> {
-    const LEADER_MAGIC: u32 = 0x4C56_3355;
+    /// The magic number at the start of a leader.
+    pub const LEADER_MAGIC: u32 = 0x4C56_3355;
 
     /// Parse bytes as Leader.
     pub fn parse(buf: &'a (impl AsRef<[u8]> + ?Sized)) -> Result<Self> {
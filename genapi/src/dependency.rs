@@ -0,0 +1,555 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains references between nodes, e.g. `pValue` or `pIsAvailable`.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    elem_type::{AddressKind, ImmOrPNode, NamedValue, ValueKind},
+    node_base::NodeElementBase,
+    register_base::RegisterBase,
+    store::{NodeData, NodeId, NodeStore},
+};
+
+/// The element through which a node refers to another node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DependencyKind {
+    /// `pValue`, `pValueIndexed`, `pValueDefault` or `pValue` of `Boolean`, `Command`,
+    /// `Enumeration` and `String`.
+    Value,
+    /// `pValueCopy`.
+    ValueCopy,
+    /// `pIndex` of a value or an address.
+    Index,
+    /// `pMin`.
+    Min,
+    /// `pMax`.
+    Max,
+    /// `pInc`.
+    Inc,
+    /// `pVariable` of a formula.
+    Variable,
+    /// `pAddress`, `IntSwissKnife` in an address, or `pOffset` of `pIndex` in an address.
+    Address,
+    /// `pLength`.
+    Length,
+    /// `pPort`.
+    Port,
+    /// `pChunkID`.
+    ChunkId,
+    /// `pCommandValue`.
+    CommandValue,
+    /// `pIsImplemented`.
+    IsImplemented,
+    /// `pIsAvailable`.
+    IsAvailable,
+    /// `pIsLocked`.
+    IsLocked,
+    /// `pBlockPolling`.
+    BlockPolling,
+    /// `pError`.
+    Error,
+    /// `pAlias`.
+    Alias,
+    /// `pCastAlias`.
+    CastAlias,
+    /// `pInvalidator`.
+    Invalidator,
+    /// `pSelected`, the referred node is selected by the referring node.
+    Selected,
+    /// `pFeature` of a category.
+    Feature,
+    /// `EnumEntry` of an enumeration.
+    Entry,
+}
+
+impl DependencyKind {
+    /// Returns `true` if the referring node reads the referred node to evaluate its value or
+    /// state.
+    ///
+    /// `pValueCopy`, `pAlias`, `pCastAlias`, `pInvalidator`, `pSelected` and `pFeature` are not
+    /// reads.
+    #[must_use]
+    pub fn is_read(self) -> bool {
+        !matches!(
+            self,
+            Self::ValueCopy
+                | Self::Alias
+                | Self::CastAlias
+                | Self::Invalidator
+                | Self::Selected
+                | Self::Feature
+        )
+    }
+}
+
+/// A reference from a node to another node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dependency {
+    /// The element through which the node is referred.
+    pub kind: DependencyKind,
+    /// The referred node.
+    pub node: NodeId,
+}
+
+impl NodeData {
+    /// Returns references from the node to other nodes in the order they appear in the node.
+    #[must_use]
+    pub fn dependencies(&self) -> Vec<Dependency> {
+        let mut c = Collector::default();
+        match self {
+            Self::Node(n) => c.elem_base(&n.elem_base),
+            Self::Category(n) => {
+                c.elem_base(&n.elem_base);
+                c.all(DependencyKind::Feature, &n.p_features);
+            }
+            Self::Integer(n) => {
+                c.elem_base(&n.elem_base);
+                c.value_kind(&n.value_kind);
+                c.imm_or_pnode(DependencyKind::Min, &n.min);
+                c.imm_or_pnode(DependencyKind::Max, &n.max);
+                c.imm_or_pnode(DependencyKind::Inc, &n.inc);
+                c.all(DependencyKind::Selected, &n.p_selected);
+            }
+            Self::IntReg(n) => {
+                c.register_base(&n.register_base);
+                c.all(DependencyKind::Selected, &n.p_selected);
+            }
+            Self::MaskedIntReg(n) => {
+                c.register_base(&n.register_base);
+                c.all(DependencyKind::Selected, &n.p_selected);
+            }
+            Self::Boolean(n) => {
+                c.elem_base(&n.elem_base);
+                c.imm_or_pnode(DependencyKind::Value, &n.value);
+                c.all(DependencyKind::Selected, &n.p_selected);
+            }
+            Self::Command(n) => {
+                c.elem_base(&n.elem_base);
+                c.imm_or_pnode(DependencyKind::Value, &n.value);
+                c.imm_or_pnode(DependencyKind::CommandValue, &n.command_value);
+            }
+            Self::Enumeration(n) => {
+                c.elem_base(&n.elem_base);
+                c.all(DependencyKind::Entry, &n.entries);
+                c.imm_or_pnode(DependencyKind::Value, &n.value);
+                c.all(DependencyKind::Selected, &n.p_selected);
+            }
+            Self::EnumEntry(n) => c.elem_base(&n.elem_base),
+            Self::Float(n) => {
+                c.elem_base(&n.elem_base);
+                c.value_kind(&n.value_kind);
+                c.imm_or_pnode(DependencyKind::Min, &n.min);
+                c.imm_or_pnode(DependencyKind::Max, &n.max);
+                if let Some(inc) = &n.inc {
+                    c.imm_or_pnode(DependencyKind::Inc, inc);
+                }
+            }
+            Self::FloatReg(n) => c.register_base(&n.register_base),
+            Self::String(n) => {
+                c.elem_base(&n.elem_base);
+                c.imm_or_pnode(DependencyKind::Value, &n.value);
+            }
+            Self::StringReg(n) => c.register_base(&n.register_base),
+            Self::Register(n) => c.register_base(&n.register_base),
+            Self::Converter(n) => {
+                c.elem_base(&n.elem_base);
+                c.variables(&n.p_variables);
+                c.push(DependencyKind::Value, n.p_value);
+            }
+            Self::IntConverter(n) => {
+                c.elem_base(&n.elem_base);
+                c.variables(&n.p_variables);
+                c.push(DependencyKind::Value, n.p_value);
+            }
+            Self::SwissKnife(n) => {
+                c.elem_base(&n.elem_base);
+                c.variables(&n.p_variables);
+            }
+            Self::IntSwissKnife(n) => {
+                c.elem_base(&n.elem_base);
+                c.variables(&n.p_variables);
+            }
+            Self::Port(n) => {
+                c.elem_base(&n.elem_base);
+                if let Some(chunk_id) = &n.chunk_id {
+                    c.imm_or_pnode(DependencyKind::ChunkId, chunk_id);
+                }
+            }
+            Self::ConfRom(_)
+            | Self::TextDesc(_)
+            | Self::IntKey(_)
+            | Self::AdvFeatureLock(_)
+            | Self::SmartFeature(_) => {}
+        }
+        c.0
+    }
+}
+
+/// References between all nodes in a [`NodeStore`], indexed in both directions.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    dependencies: HashMap<NodeId, Vec<Dependency>>,
+    dependents: HashMap<NodeId, Vec<Dependency>>,
+}
+
+impl DependencyGraph {
+    /// Collects references between the nodes in `store`.
+    #[must_use]
+    pub fn new(store: &impl NodeStore) -> Self {
+        let mut graph = Self::default();
+        store.visit_nodes(|data| {
+            let nid = match data {
+                NodeData::ConfRom(_)
+                | NodeData::TextDesc(_)
+                | NodeData::IntKey(_)
+                | NodeData::AdvFeatureLock(_)
+                | NodeData::SmartFeature(_) => return,
+                _ => data.node_base().id(),
+            };
+            let dependencies = data.dependencies();
+            for dep in &dependencies {
+                graph
+                    .dependents
+                    .entry(dep.node)
+                    .or_default()
+                    .push(Dependency {
+                        kind: dep.kind,
+                        node: nid,
+                    });
+            }
+            graph.dependencies.insert(nid, dependencies);
+        });
+        graph
+    }
+
+    /// Returns references from the node to other nodes.
+    #[must_use]
+    pub fn dependencies(&self, nid: NodeId) -> &[Dependency] {
+        self.dependencies.get(&nid).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns references to the node from other nodes. `node` of each [`Dependency`] is the
+    /// referring node.
+    #[must_use]
+    pub fn dependents(&self, nid: NodeId) -> &[Dependency] {
+        self.dependents.get(&nid).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns nodes which the node reads to evaluate its value or state, e.g. `pValue` or
+    /// `pIsAvailable`.
+    pub fn reads(&self, nid: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.dependencies(nid)
+            .iter()
+            .filter(|dep| dep.kind.is_read())
+            .map(|dep| dep.node)
+    }
+
+    /// Returns nodes which read the node to evaluate their value or state.
+    pub fn readers(&self, nid: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.dependents(nid)
+            .iter()
+            .filter(|dep| dep.kind.is_read())
+            .map(|dep| dep.node)
+    }
+
+    /// Returns nodes whose change invalidates the cache of the node, i.e. `pInvalidator` of the
+    /// node.
+    pub fn invalidators(&self, nid: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.dependencies(nid)
+            .iter()
+            .filter(|dep| dep.kind == DependencyKind::Invalidator)
+            .map(|dep| dep.node)
+    }
+
+    /// Returns nodes whose cache is invalidated by a change of the node, i.e. nodes which refer
+    /// to the node by `pInvalidator`.
+    pub fn invalidated_nodes(&self, nid: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.dependents(nid)
+            .iter()
+            .filter(|dep| dep.kind == DependencyKind::Invalidator)
+            .map(|dep| dep.node)
+    }
+
+    /// Returns nodes whose value or state may change when the node changes, i.e. nodes which
+    /// read or are invalidated by the node directly or indirectly. The node itself is not
+    /// included.
+    #[must_use]
+    pub fn affected_nodes(&self, nid: NodeId) -> Vec<NodeId> {
+        let mut visited = HashSet::new();
+        visited.insert(nid);
+        let mut affected = vec![];
+        let mut stack = vec![nid];
+        while let Some(node) = stack.pop() {
+            for next in self.readers(node).chain(self.invalidated_nodes(node)) {
+                if visited.insert(next) {
+                    affected.push(next);
+                    stack.push(next);
+                }
+            }
+        }
+        affected
+    }
+}
+
+#[derive(Default)]
+struct Collector(Vec<Dependency>);
+
+impl Collector {
+    fn push(&mut self, kind: DependencyKind, node: NodeId) {
+        self.0.push(Dependency { kind, node });
+    }
+
+    fn opt(&mut self, kind: DependencyKind, node: Option<NodeId>) {
+        if let Some(node) = node {
+            self.push(kind, node);
+        }
+    }
+
+    fn all(&mut self, kind: DependencyKind, nodes: &[NodeId]) {
+        for &node in nodes {
+            self.push(kind, node);
+        }
+    }
+
+    fn imm_or_pnode<T>(&mut self, kind: DependencyKind, value: &ImmOrPNode<T>) {
+        if let ImmOrPNode::PNode(node) = value {
+            self.push(kind, *node);
+        }
+    }
+
+    fn value_kind<T>(&mut self, value_kind: &ValueKind<T>) {
+        match value_kind {
+            ValueKind::Value(_) => {}
+            ValueKind::PValue(p_value) => {
+                self.push(DependencyKind::Value, p_value.p_value);
+                self.all(DependencyKind::ValueCopy, &p_value.p_value_copies);
+            }
+            ValueKind::PIndex(p_index) => {
+                self.push(DependencyKind::Index, p_index.p_index);
+                for indexed in &p_index.value_indexed {
+                    self.imm_or_pnode(DependencyKind::Value, &indexed.indexed);
+                }
+                self.imm_or_pnode(DependencyKind::Value, &p_index.value_default);
+            }
+        }
+    }
+
+    fn variables(&mut self, variables: &[NamedValue<NodeId>]) {
+        for variable in variables {
+            self.push(DependencyKind::Variable, variable.value);
+        }
+    }
+
+    fn elem_base(&mut self, base: &NodeElementBase) {
+        self.opt(DependencyKind::IsImplemented, base.p_is_implemented);
+        self.opt(DependencyKind::IsAvailable, base.p_is_available);
+        self.opt(DependencyKind::IsLocked, base.p_is_locked);
+        self.opt(DependencyKind::BlockPolling, base.p_block_polling);
+        self.all(DependencyKind::Error, &base.p_errors);
+        self.opt(DependencyKind::Alias, base.p_alias);
+        self.opt(DependencyKind::CastAlias, base.p_cast_alias);
+        self.all(DependencyKind::Invalidator, &base.p_invalidators);
+    }
+
+    fn register_base(&mut self, base: &RegisterBase) {
+        self.elem_base(&base.elem_base);
+        for address in &base.address_kinds {
+            match address {
+                AddressKind::Address(address) => {
+                    self.imm_or_pnode(DependencyKind::Address, address);
+                }
+                AddressKind::IntSwissKnife(node) => self.push(DependencyKind::Address, *node),
+                AddressKind::PIndex(p_index) => {
+                    self.push(DependencyKind::Index, p_index.p_index);
+                    if let Some(offset) = &p_index.offset {
+                        self.imm_or_pnode(DependencyKind::Address, offset);
+                    }
+                }
+            }
+        }
+        self.imm_or_pnode(DependencyKind::Length, &base.length);
+        self.push(DependencyKind::Port, base.p_port);
+        self.all(DependencyKind::Invalidator, &base.p_invalidators);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::GenApiBuilder,
+        store::{DefaultNodeStore, NodeStore},
+    };
+
+    fn graph(xml_nodes: &str) -> (DefaultNodeStore, DependencyGraph) {
+        let xml = format!(
+            r#"<RegisterDescription
+            ModelName="CameleonModel"
+            VendorName="CameleonVendor"
+            StandardNameSpace="None"
+            SchemaMajorVersion="1"
+            SchemaMinorVersion="1"
+            SchemaSubMinorVersion="0"
+            MajorVersion="1"
+            MinorVersion="0"
+            SubMinorVersion="0"
+            ProductGuid="01234567-0123-0123-0123-0123456789ab"
+            VersionGuid="76543210-3210-3210-3210-ba9876543210"
+            xmlns="http://www.genicam.org/GenApi/Version_1_0">
+            {}
+            </RegisterDescription>"#,
+            xml_nodes
+        );
+        let (_, store, _) = GenApiBuilder::<DefaultNodeStore>::default()
+            .build(&xml)
+            .unwrap();
+        let graph = DependencyGraph::new(&store);
+        (store, graph)
+    }
+
+    fn dependencies(xml_nodes: &str, name: &str) -> Vec<(DependencyKind, String)> {
+        let (store, graph) = graph(xml_nodes);
+        let nid = store.id_by_name(name).unwrap();
+        graph
+            .dependencies(nid)
+            .iter()
+            .map(|dep| (dep.kind, store.name_by_id(dep.node).unwrap().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_dependency_graph() {
+        let (store, graph) = graph(
+            r#"
+            <Integer Name="Width">
+                <pValue>WidthReg</pValue>
+                <pMax>WidthMax</pMax>
+            </Integer>
+            <IntReg Name="WidthReg">
+                <Address>0x100</Address>
+                <Length>4</Length>
+                <AccessMode>RW</AccessMode>
+                <pPort>Device</pPort>
+                <pInvalidator>Binning</pInvalidator>
+                <Sign>Unsigned</Sign>
+                <Endianess>LittleEndian</Endianess>
+            </IntReg>
+            <IntSwissKnife Name="WidthMax">
+                <pVariable Name="SENSOR">SensorWidth</pVariable>
+                <pVariable Name="BINNING">Binning</pVariable>
+                <Formula>SENSOR / BINNING</Formula>
+            </IntSwissKnife>
+            <Integer Name="SensorWidth">
+                <Value>1024</Value>
+            </Integer>
+            <Integer Name="Binning">
+                <Value>1</Value>
+            </Integer>
+            <Port Name="Device"/>
+            "#,
+        );
+        let id = |name| store.id_by_name(name).unwrap();
+        let names = |nodes: Vec<NodeId>| {
+            let mut names: Vec<_> = nodes
+                .into_iter()
+                .map(|nid| store.name_by_id(nid).unwrap())
+                .collect();
+            names.sort_unstable();
+            names
+        };
+
+        assert_eq!(
+            names(graph.reads(id("Width")).collect()),
+            ["WidthMax", "WidthReg"]
+        );
+        assert_eq!(names(graph.readers(id("WidthReg")).collect()), ["Width"]);
+        assert_eq!(
+            names(graph.invalidators(id("WidthReg")).collect()),
+            ["Binning"]
+        );
+        assert_eq!(
+            names(graph.invalidated_nodes(id("Binning")).collect()),
+            ["WidthReg"]
+        );
+        assert_eq!(
+            names(graph.affected_nodes(id("Binning"))),
+            ["Width", "WidthMax", "WidthReg"]
+        );
+        assert!(graph.affected_nodes(id("Width")).is_empty());
+    }
+
+    #[test]
+    fn test_integer_dependencies() {
+        let deps = dependencies(
+            r#"
+            <Integer Name="Width">
+                <pIsAvailable>WidthAvailable</pIsAvailable>
+                <pValue>WidthReg</pValue>
+                <Min>1</Min>
+                <pMax>WidthMax</pMax>
+                <pSelected>OffsetX</pSelected>
+            </Integer>
+            "#,
+            "Width",
+        );
+        assert_eq!(
+            deps,
+            [
+                (DependencyKind::IsAvailable, "WidthAvailable".into()),
+                (DependencyKind::Value, "WidthReg".into()),
+                (DependencyKind::Max, "WidthMax".into()),
+                (DependencyKind::Selected, "OffsetX".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_register_dependencies() {
+        let deps = dependencies(
+            r#"
+            <IntReg Name="LUTValueReg">
+                <pIsLocked>Locked</pIsLocked>
+                <Address>0x100</Address>
+                <pIndex Offset="4">LUTIndex</pIndex>
+                <Length>4</Length>
+                <AccessMode>RW</AccessMode>
+                <pPort>Device</pPort>
+                <pInvalidator>LUTEnable</pInvalidator>
+                <Sign>Unsigned</Sign>
+                <Endianess>LittleEndian</Endianess>
+            </IntReg>
+            "#,
+            "LUTValueReg",
+        );
+        assert_eq!(
+            deps,
+            [
+                (DependencyKind::IsLocked, "Locked".into()),
+                (DependencyKind::Index, "LUTIndex".into()),
+                (DependencyKind::Port, "Device".into()),
+                (DependencyKind::Invalidator, "LUTEnable".into()),
+            ]
+        );
+
+        let deps = dependencies(
+            r#"
+            <IntSwissKnife Name="WidthMax">
+                <pVariable Name="SENSOR">SensorWidth</pVariable>
+                <pVariable Name="OFFSET">OffsetX</pVariable>
+                <Formula>SENSOR - OFFSET</Formula>
+            </IntSwissKnife>
+            "#,
+            "WidthMax",
+        );
+        assert_eq!(
+            deps,
+            [
+                (DependencyKind::Variable, "SensorWidth".into()),
+                (DependencyKind::Variable, "OffsetX".into()),
+            ]
+        );
+    }
+}
@@ -18,7 +18,7 @@ pub enum NameSpace {
     Custom,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub enum Visibility {
     #[default]
     Beginner,
@@ -13,6 +13,7 @@
 )]
 
 pub mod builder;
+pub mod dependency;
 pub mod elem_type;
 pub mod formula;
 pub mod interface;
@@ -66,7 +67,7 @@ pub use string::StringNode;
 pub use string_reg::StringRegNode;
 pub use swiss_knife::SwissKnifeNode;
 
-use std::borrow::Cow;
+use std::{borrow::Cow, cell::RefCell, collections::HashSet};
 
 use auto_impl::auto_impl;
 use tracing::error;
@@ -213,6 +214,11 @@ impl<T, U> ValueCtxt<T, U> {
     where
         U: store::CacheStore,
     {
+        WRITTEN_NODES.with(|written_nodes| {
+            if let Some(written_nodes) = written_nodes.borrow_mut().as_mut() {
+                written_nodes.insert(nid);
+            }
+        });
         self.cache_store.invalidate_by(nid)
     }
 
@@ -230,3 +236,38 @@ impl<T, U> ValueCtxt<T, U> {
         self.cache_store.clear()
     }
 }
+
+thread_local! {
+    /// Nodes written on this thread in [`record_written_nodes`], or `None` outside of it.
+    static WRITTEN_NODES: RefCell<Option<HashSet<store::NodeId>>> = const { RefCell::new(None) };
+}
+
+/// Calls `f` and returns its result with the nodes written while it runs.
+///
+/// A node is recorded when its write invalidates the caches which depend on it, so nodes written
+/// indirectly, e.g. a register behind `pValue`, are also included. Writes are recorded per
+/// thread, and nodes recorded by a nested call are also returned by the outer call.
+pub fn record_written_nodes<R>(f: impl FnOnce() -> R) -> (R, HashSet<store::NodeId>) {
+    /// Merges the nodes into the recording of the caller, even if `f` panics.
+    struct Restore(Option<HashSet<store::NodeId>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            WRITTEN_NODES.with(|written_nodes| {
+                let mut written_nodes = written_nodes.borrow_mut();
+                let inner = written_nodes.take().unwrap_or_default();
+                *written_nodes = self.0.take().map(|mut outer| {
+                    outer.extend(inner);
+                    outer
+                });
+            });
+        }
+    }
+
+    let outer = WRITTEN_NODES.with(|written_nodes| written_nodes.replace(Some(HashSet::new())));
+    let _restore = Restore(outer);
+    let result = f();
+    let written =
+        WRITTEN_NODES.with(|written_nodes| written_nodes.borrow().clone().unwrap_or_default());
+    (result, written)
+}
@@ -93,6 +93,28 @@ impl<'a> NodeBase<'a> {
         self.elem.event_id
     }
 
+    /// Returns `true` if the node is implemented, i.e. `pIsImplemented` of the node is not set
+    /// or evaluates to `true`.
+    pub fn is_implemented<T: ValueStore, U: CacheStore>(
+        &self,
+        device: &mut impl Device,
+        store: &impl NodeStore,
+        cx: &mut ValueCtxt<T, U>,
+    ) -> GenApiResult<bool> {
+        self.elem.is_implemented(device, store, cx)
+    }
+
+    /// Returns `true` if the node is available, i.e. `pIsAvailable` of the node is not set or
+    /// evaluates to `true`.
+    pub fn is_available<T: ValueStore, U: CacheStore>(
+        &self,
+        device: &mut impl Device,
+        store: &impl NodeStore,
+        cx: &mut ValueCtxt<T, U>,
+    ) -> GenApiResult<bool> {
+        self.elem.is_available(device, store, cx)
+    }
+
     optional_string_elem_getter! {description}
     optional_string_elem_getter! {tooltip}
     optional_string_elem_getter! {docu_url}
@@ -27,11 +27,12 @@ impl From<MemoryError> for GenTlError {
 impl From<ControlError> for GenTlError {
     fn from(err: ControlError) -> Self {
         use GenTlError::{
-            BufferTooSmall, InvalidValue, Io, NotInitialized, ResourceInUse, Timeout,
+            AccessDenied, BufferTooSmall, InvalidValue, Io, NotInitialized, ResourceInUse, Timeout,
         };
 
         match err {
             ControlError::Busy => ResourceInUse,
+            ControlError::ReadOnly => AccessDenied,
             ControlError::Disconnected | ControlError::Io(..) | ControlError::InvalidDevice(..) => {
                 Io(err.into())
             }